@@ -0,0 +1,377 @@
+use crate::tuple::{Tuple, point, vector};
+use crate::color::Color;
+use crate::matrix::Matrix;
+use crate::transform::{translation, scaling, rotation_x, rotation_y, rotation_z, view_transform};
+use crate::world::World;
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::tonemap::Output;
+use std::f64::consts::PI;
+use std::io::Result;
+
+// how a track gets from one key to the next
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Easing {
+    Linear,
+    // smoothstep - eases out of the key and into the next one
+    Smooth,
+    // holds the value until the next key
+    Step,
+}
+
+// anything a track can blend between
+pub trait Lerp {
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Tuple {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        *self + (*other - *self) * t
+    }
+}
+
+impl Lerp for Color {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        *self * (1.0 - t) + *other * t
+    }
+}
+
+// a shape's transform split into parts that can be blended - blending
+// whole matrices squashes rotations
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Pose {
+    pub translation: Tuple,
+    pub rotation: Tuple,    // radians about x, y then z
+    pub scale: Tuple,
+}
+
+impl Pose {
+    pub fn matrix(&self) -> Matrix {
+        let t = self.translation;
+        let r = self.rotation;
+        let s = self.scale;
+        translation(t.x, t.y, t.z)
+            .mult(&rotation_z(r.z))
+            .mult(&rotation_y(r.y))
+            .mult(&rotation_x(r.x))
+            .mult(&scaling(s.x, s.y, s.z))
+    }
+}
+
+impl Lerp for Pose {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Pose {
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: self.rotation.lerp(&other.rotation, t),
+            scale: self.scale.lerp(&other.scale, t),
+        }
+    }
+}
+
+pub fn pose(translation: Tuple, rotation: Tuple, scale: Tuple) -> Pose {
+    Pose { translation, rotation, scale }
+}
+
+// where a shape sits with nothing done to it
+pub fn rest_pose() -> Pose {
+    pose(vector(0.0, 0.0, 0.0), vector(0.0, 0.0, 0.0), vector(1.0, 1.0, 1.0))
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f64,          // seconds
+    pub value: T,
+    pub easing: Easing,     // on the way to the next key
+}
+
+// keyframed value over time - before the first key it holds the first
+// value, after the last it holds the last
+#[derive(Debug,Clone,PartialEq)]
+pub struct Track<T> {
+    pub keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Lerp + Clone> Track<T> {
+    pub fn add_key(&mut self, time: f64, value: T, easing: Easing) {
+        assert!(!time.is_nan(), "key time can't be NaN");
+        self.keys.push(Keyframe { time, value, easing });
+        self.keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    // None only for a track with no keys
+    pub fn value_at(&self, time: f64) -> Option<T> {
+        let first = self.keys.first()?;
+        if time <= first.time {
+            return Some(first.value.clone());
+        }
+        for pair in self.keys.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if time < b.time {
+                let t = (time - a.time) / (b.time - a.time);
+                let t = match a.easing {
+                    Easing::Linear => t,
+                    Easing::Smooth => t * t * (3.0 - 2.0 * t),
+                    Easing::Step   => 0.0,
+                };
+                return Some(a.value.lerp(&b.value, t));
+            }
+        }
+        self.keys.last().map(|k| k.value.clone())
+    }
+}
+
+pub fn track<T>() -> Track<T> {
+    Track { keys: vec!() }
+}
+
+// a track with a key at each of `times`, all eased the same way
+pub fn track_from<T: Lerp + Clone>(times: &[f64], values: &[T], easing: Easing) -> Track<T> {
+    let mut t = track();
+    for (time, value) in times.iter().zip(values) {
+        t.add_key(*time, value.clone(), easing);
+    }
+    t
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum CameraPath {
+    // keyed from / to / up, as for view_transform
+    Keys { from: Track<Tuple>, to: Track<Tuple>, up: Track<Tuple> },
+    // circles `target` at a fixed radius and height, starting on the -z
+    // side and heading round towards -x
+    Orbit { target: Tuple, radius: f64, height: f64, turns: f64, duration: f64 },
+}
+
+impl CameraPath {
+    pub fn transform_at(&self, time: f64) -> Option<Matrix> {
+        match self {
+            CameraPath::Keys { from, to, up } => {
+                Some(view_transform(from.value_at(time)?, to.value_at(time)?, up.value_at(time)?))
+            },
+            CameraPath::Orbit { target, radius, height, turns, duration } => {
+                let angle = 2.0 * PI * turns * (time / duration).clamp(0.0, 1.0);
+                let from = point(target.x - radius * angle.sin(),
+                                 target.y + height,
+                                 target.z - radius * angle.cos());
+                Some(view_transform(from, *target, vector(0.0, 1.0, 0.0)))
+            },
+        }
+    }
+}
+
+// product spin - once round the target over `duration` seconds
+pub fn turntable(target: Tuple, radius: f64, height: f64, duration: f64) -> CameraPath {
+    CameraPath::Orbit { target, radius, height, turns: 1.0, duration }
+}
+
+// everything that changes over time in a scene. Anything without a track
+// is left the way the world and camera were set up.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Animation {
+    pub fps: f64,
+    pub poses: Vec<(usize, Track<Pose>)>,       // by object index
+    pub colors: Vec<(usize, Track<Color>)>,     // material color, by object index
+    pub light_position: Option<Track<Tuple>>,
+    pub light_intensity: Option<Track<Color>>,
+    pub camera: Option<CameraPath>,
+}
+
+impl Animation {
+    pub fn time_of(&self, frame: usize) -> f64 {
+        frame as f64 / self.fps
+    }
+
+    // puts the world and camera where they should be at `time`
+    pub fn apply(&self, w: &mut World, c: &mut Camera, time: f64) {
+        for (index, t) in &self.poses {
+            if let Some(p) = t.value_at(time) {
+                w.get_object_mut(*index).set_transform(p.matrix());
+            }
+        }
+        for (index, t) in &self.colors {
+            if let Some(col) = t.value_at(time) {
+                let mut m = w.get_object(*index).get_material().clone();
+                m.color = col;
+                w.get_object_mut(*index).set_material(m);
+            }
+        }
+        if let Some(light) = w.light.as_mut() {
+            if let Some(p) = self.light_position.as_ref().and_then(|t| t.value_at(time)) {
+                light.position = p;
+            }
+            if let Some(i) = self.light_intensity.as_ref().and_then(|t| t.value_at(time)) {
+                light.intensity = i;
+            }
+        }
+        if let Some(m) = self.camera.as_ref().and_then(|path| path.transform_at(time)) {
+            c.transform = m;
+        }
+    }
+
+    pub fn render_frame(&self, w: &mut World, c: &mut Camera, frame: usize) -> Canvas {
+        self.apply(w, c, self.time_of(frame));
+        c.render(&*w)
+    }
+
+    // frames first..=last kept in memory, e.g. for gif or y4m
+    pub fn render_frames(&self, w: &mut World, c: &mut Camera, first: usize, last: usize) -> Vec<Canvas> {
+        (first..=last).map(|frame| self.render_frame(w, c, frame)).collect()
+    }
+
+    // renders frames first..=last to <prefix>0001.ppm and so on, numbered
+    // by frame, and hands back the file names
+    pub fn render_sequence(&self, w: &mut World, c: &mut Camera, first: usize, last: usize,
+                           prefix: &str, out: &Output) -> Result<Vec<String>> {
+        let mut names = vec!();
+        for frame in first..=last {
+            let name = frame_name(prefix, frame);
+            self.render_frame(w, c, frame).to_ppm_with(&name, out)?;
+            names.push(name);
+        }
+        Ok(names)
+    }
+}
+
+pub fn animation(fps: f64) -> Animation {
+    assert!(fps > 0.0 && fps.is_finite(), "fps has to be positive");
+    Animation {
+        fps,
+        poses: vec!(),
+        colors: vec!(),
+        light_position: None,
+        light_intensity: None,
+        camera: None,
+    }
+}
+
+pub fn frame_name(prefix: &str, frame: usize) -> String {
+    format!("{}{:04}.ppm", prefix, frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::animation::{Easing, Lerp, animation, track, track_from, pose, rest_pose,
+                           turntable, frame_name, CameraPath};
+    use crate::world::default_world;
+    use crate::camera::camera;
+    use crate::color::color;
+    use crate::tuple::{point, vector};
+    use crate::transform::{translation, rotation_y, view_transform};
+    use crate::lights::point_light;
+    use crate::backgrounds::solid_background;
+    use crate::tonemap::linear_output;
+    use crate::equals::equals;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn linear_and_smooth_tracks(){
+        let mut t = track();
+        t.add_key(2.0, 10.0, Easing::Linear);
+        t.add_key(0.0, 0.0, Easing::Smooth);
+        t.add_key(4.0, 20.0, Easing::Step);
+
+        assert!(equals(t.value_at(-1.0).unwrap(), 0.0));
+        assert!(equals(t.value_at(1.0).unwrap(), 5.0));     // smooth is symmetric
+        assert!(t.value_at(0.5).unwrap() < 2.5);             // but slow to start
+        assert!(equals(t.value_at(3.0).unwrap(), 15.0));
+        assert!(equals(t.value_at(9.0).unwrap(), 20.0));
+        assert!(track::<f64>().value_at(1.0).is_none());
+    }
+
+    #[test]
+    fn step_holds_until_the_next_key(){
+        let t = track_from(&[0.0, 1.0], &[color(1.0, 0.0, 0.0), color(0.0, 0.0, 1.0)], Easing::Step);
+        assert!(t.value_at(0.99).unwrap().equals(color(1.0, 0.0, 0.0)));
+        assert!(t.value_at(1.0).unwrap().equals(color(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn poses_blend_part_by_part(){
+        let a = rest_pose();
+        let b = pose(vector(2.0, 0.0, 0.0), vector(0.0, PI, 0.0), vector(1.0, 1.0, 1.0));
+        let half = a.lerp(&b, 0.5);
+        assert!(half.matrix().equals(translation(1.0, 0.0, 0.0).mult(&rotation_y(PI / 2.0))));
+    }
+
+    #[test]
+    fn applying_an_animation_moves_things(){
+        let mut w = default_world();
+        let mut c = camera(10, 10, PI / 2.0);
+        let mut a = animation(24.0);
+
+        let mut moves = track();
+        moves.add_key(0.0, rest_pose(), Easing::Linear);
+        moves.add_key(1.0, pose(vector(0.0, 3.0, 0.0), vector(0.0, 0.0, 0.0), vector(1.0, 1.0, 1.0)), Easing::Linear);
+        a.poses.push((0, moves));
+        a.colors.push((1, track_from(&[0.0], &[color(1.0, 0.0, 0.0)], Easing::Linear)));
+        a.light_intensity = Some(track_from(&[0.0, 2.0], &[color(0.0, 0.0, 0.0), color(1.0, 1.0, 1.0)], Easing::Linear));
+        a.camera = Some(CameraPath::Keys {
+            from: track_from(&[0.0], &[point(0.0, 0.0, -5.0)], Easing::Linear),
+            to: track_from(&[0.0], &[point(0.0, 0.0, 0.0)], Easing::Linear),
+            up: track_from(&[0.0], &[vector(0.0, 1.0, 0.0)], Easing::Linear),
+        });
+
+        a.apply(&mut w, &mut c, a.time_of(12));
+        assert!(w.get_object(0).get_transform().equals(translation(0.0, 1.5, 0.0)));
+        assert!(w.get_object(1).get_material().color.equals(color(1.0, 0.0, 0.0)));
+        assert!(w.light.as_ref().unwrap().intensity.equals(color(0.25, 0.25, 0.25)));
+        assert!(c.transform.equals(view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0))));
+    }
+
+    #[test]
+    fn turntable_goes_all_the_way_round(){
+        let path = turntable(point(0.0, 1.0, 0.0), 5.0, 2.0, 4.0);
+        let start = path.transform_at(0.0).unwrap();
+        assert!(start.equals(view_transform(point(0.0, 3.0, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0))));
+        let quarter = path.transform_at(1.0).unwrap();
+        assert!(quarter.equals(view_transform(point(-5.0, 3.0, 0.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0))));
+        let end = path.transform_at(4.0).unwrap();
+        assert!(end.equals(start));
+    }
+
+    #[test]
+    fn rendering_a_numbered_sequence(){
+        let mut w = default_world();
+        w.light = Some(point_light(point(-10.0, 10.0, -10.0), color(1.0, 1.0, 1.0)));
+        let mut c = camera(4, 3, PI / 2.0);
+        let mut a = animation(10.0);
+        a.camera = Some(turntable(point(0.0, 0.0, 0.0), 5.0, 0.0, 1.0));
+
+        let names = a.render_sequence(&mut w, &mut c, 1, 3, "anim_test_", &linear_output()).unwrap();
+        assert_eq!(names, vec!["anim_test_0001.ppm", "anim_test_0002.ppm", "anim_test_0003.ppm"]);
+        for n in &names {
+            assert!(std::path::Path::new(n).exists());
+            let _ = std::fs::remove_file(n);
+        }
+        assert_eq!(frame_name("frame_", 12), "frame_0012.ppm");
+    }
+
+    #[test]
+    fn frames_are_rendered_edge_to_edge(){
+        let mut w = default_world();
+        w.background = Some(solid_background(color(0.0, 0.0, 1.0)));
+        let mut c = camera(4, 3, PI / 2.0);
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        let a = animation(10.0);
+        let frame = a.render_frame(&mut w, &mut c, 0);
+        assert!(frame.pixel_at(3, 2).equals(color(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn nan_key_times_are_rejected(){
+        track().add_key(f64::NAN, 1.0, Easing::Linear);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_fps_is_rejected(){
+        animation(0.0);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::shapes::{Base, Shape, shape, xz_area_scale};
+use crate::shape_index::ShapeIndex;
+use crate::intersections::{Intersection, intersection, intersections};
+use crate::tuple::{Tuple, point, vector};
+use crate::rays::Ray;
+use crate::materials::Material;
+use crate::matrix::Matrix;
+use crate::equals::EPSILON;
+use std::f64::consts::PI;
+
+// flat ring in the xz plane, centered on the origin - a disk of
+// radius outer with a hole of radius inner cut out of the middle
+pub struct Annulus {
+    supe: Base,
+    pub inner: f64,
+    pub outer: f64,
+}
+
+impl Shape for Annulus {
+    fn get_transform(&self) -> &Matrix {
+        self.supe.get_transform()
+    }
+
+    fn set_transform(&mut self, t: Matrix){
+        self.supe.set_transform( t );
+    }
+
+    fn get_material(&self) -> &Material {
+        self.supe.get_material()
+    }
+
+    fn set_material(&mut self, m: Material){
+        self.supe.set_material( m );
+    }
+
+    fn local_normal_at(&self, _object_point: Tuple) -> Tuple {
+        vector(0.0, 1.0, 0.0)
+    }
+
+    fn intersect(&self, r: Ray) -> Vec<Intersection> {
+        let r2 = self.saved_ray(r);
+        if r2.direction.y.abs() < EPSILON {
+            return vec!();
+        }
+
+        let t = -r2.origin.y / r2.direction.y;
+        let p = r2.position(t);
+        let dist = p.x.powf(2.0) + p.z.powf(2.0);
+        if dist > self.outer.powf(2.0) || dist < self.inner.powf(2.0) {
+            return vec!();
+        }
+
+        intersections(&[intersection(t, self.get_index())])
+    }
+
+    fn get_index(&self) -> usize {
+        self.supe.get_index()
+    }
+
+    fn set_index(&mut self, index: usize){
+        self.supe.set_index(index);
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.supe.get_parent()
+    }
+
+    fn set_parent(&mut self, parent_index: usize){
+        self.supe.set_parent(parent_index);
+    }
+
+    fn get_reference(&self) -> ShapeIndex {
+        self.supe.get_reference()
+    }
+
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Annulus"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        vec![self.inner, self.outer]
+    }
+
+    // u runs around the ring, v from the inner edge to the outer
+    fn local_uv_at(&self, object_point: Tuple) -> Option<(f64, f64)> {
+        let theta = object_point.z.atan2(object_point.x);
+        let u = (theta / (2.0 * PI)).rem_euclid(1.0);
+        let r = (object_point.x.powf(2.0) + object_point.z.powf(2.0)).sqrt();
+        let v = (r - self.inner) / (self.outer - self.inner);
+        Some((u, v))
+    }
+
+    fn local_sample(&self, u: f64, v: f64) -> Option<Tuple> {
+        let r2 = self.inner.powf(2.0) + v * (self.outer.powf(2.0) - self.inner.powf(2.0));
+        let r = r2.sqrt();
+        let theta = 2.0 * PI * u;
+        Some(point(r * theta.cos(), 0.0, r * theta.sin()))
+    }
+
+    fn area(&self) -> f64 {
+        PI * (self.outer.powf(2.0) - self.inner.powf(2.0)) *
+            xz_area_scale(self.get_transform())
+    }
+
+    fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
+        0
+    }
+
+    fn get_object(&self, _index: usize) -> Option<&Box<dyn Shape>> {
+        None
+    }
+
+    fn get_size(&self) -> usize {
+        0
+    }
+}
+
+pub fn annulus() -> Annulus {
+    Annulus {
+        supe: shape(),
+        inner: 0.5,
+        outer: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::annuli::annulus;
+    use crate::shapes::Shape;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::transform::scaling;
+    use crate::equals::equals;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn default_annulus_radii(){
+        let a = annulus();
+        assert_eq!(a.inner, 0.5);
+        assert_eq!(a.outer, 1.0);
+    }
+
+    #[test]
+    fn normal_of_annulus_is_constant(){
+        let a = annulus();
+        assert_eq!(a.local_normal_at(point(0.75, 0.0, 0.0)), vector(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn ray_hits_the_ring(){
+        let a = annulus();
+        let xs = a.intersect(ray(point(0.0, -1.0, 0.75), vector(0.0, 1.0, 0.0), 0));
+
+        assert_eq!(xs.len(), 1);
+        assert!(equals(xs[0].t, 1.0));
+    }
+
+    #[test]
+    fn ray_through_the_hole_misses(){
+        let a = annulus();
+        let xs = a.intersect(ray(point(0.2, 1.0, 0.2), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn ray_outside_the_ring_misses(){
+        let a = annulus();
+        let xs = a.intersect(ray(point(0.0, 1.0, 1.1), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn ray_parallel_to_annulus(){
+        let a = annulus();
+        let xs = a.intersect(ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn uv_mapping_on_an_annulus(){
+        let a = annulus();
+        let (u, v) = a.local_uv_at(point(0.5, 0.0, 0.0)).unwrap();
+        assert!(equals(u, 0.0) && equals(v, 0.0));
+
+        let (u, v) = a.local_uv_at(point(-0.75, 0.0, 0.0)).unwrap();
+        assert!(equals(u, 0.5) && equals(v, 0.5));
+    }
+
+    #[test]
+    fn samples_lie_on_the_ring(){
+        let a = annulus();
+        for i in 0..10 {
+            for j in 0..10 {
+                let p = a.local_sample(i as f64 / 10.0, j as f64 / 10.0).unwrap();
+                let r = (p.x.powf(2.0) + p.z.powf(2.0)).sqrt();
+                assert!(r >= 0.5 - 1e-9 && r <= 1.0 + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn area_of_an_annulus(){
+        let mut a = annulus();
+        assert!(equals(a.area(), 0.75 * PI));
+
+        a.set_transform(scaling(2.0, 2.0, 2.0));
+        assert!(equals(a.area(), 3.0 * PI));
+    }
+}
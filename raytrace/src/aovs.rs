@@ -0,0 +1,233 @@
+use crate::canvas::{Canvas, canvas};
+use crate::color::{Color, color};
+use crate::rays::Ray;
+use crate::world::World;
+use crate::intersections::{Computations, prepare_computations};
+use crate::materials::surface_color;
+use crate::media::{medium_at_origin, march_segment};
+use std::io::Result;
+
+// auxiliary output variables - the extra images a render can spit out
+// alongside the finished picture, for compositing and debugging. Every
+// canvas is the same size as the render.
+pub struct Passes {
+    pub beauty: Canvas,
+    pub depth: Canvas,      // distance to the first hit, infinite on a miss
+    pub normal: Canvas,     // world space normal, x y z in r g b
+    pub object: Canvas,     // a made up color per object
+    pub albedo: Canvas,     // surface color before any lighting
+    pub direct: Canvas,     // the point light's Phong contribution
+    pub indirect: Canvas,   // everything else - reflection, refraction, fog
+    pub shadow: Canvas,     // 1 where the point light is blocked
+    pub object_ids: Vec<Vec<Option<usize>>>,    // [y][x], None on a miss
+}
+
+impl Passes {
+    pub fn object_at(&self, x: usize, y: usize) -> Option<usize> {
+        self.object_ids[y][x]
+    }
+
+    // prefix_depth.pfm, prefix_normal.pfm and prefix_<pass>.ppm for the
+    // rest. Depth and normals go out as floats since they aren't colors.
+    pub fn write(&self, prefix: &str) -> Result<()> {
+        self.depth.to_pfm(&format!("{}_depth.pfm", prefix))?;
+        self.normal.to_pfm(&format!("{}_normal.pfm", prefix))?;
+        self.beauty.to_ppm(&format!("{}_beauty.ppm", prefix))?;
+        self.object.to_ppm(&format!("{}_object.ppm", prefix))?;
+        self.albedo.to_ppm(&format!("{}_albedo.ppm", prefix))?;
+        self.direct.to_ppm(&format!("{}_direct.ppm", prefix))?;
+        self.indirect.to_ppm(&format!("{}_indirect.ppm", prefix))?;
+        self.shadow.to_ppm(&format!("{}_shadow.ppm", prefix))?;
+        Ok(())
+    }
+}
+
+pub fn passes(width: usize, height: usize) -> Passes {
+    let mut depth = canvas(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            depth.write_pixel(x, y, color(f64::INFINITY, f64::INFINITY, f64::INFINITY));
+        }
+    }
+
+    Passes {
+        beauty: canvas(width, height),
+        depth,
+        normal: canvas(width, height),
+        object: canvas(width, height),
+        albedo: canvas(width, height),
+        direct: canvas(width, height),
+        indirect: canvas(width, height),
+        shadow: canvas(width, height),
+        object_ids: vec![vec![None; width]; height],
+    }
+}
+
+// fills in every pass for the pixel at (x, y) looking along r. Shades
+// the hit once, the same way color_at does, and splits the result up.
+pub fn record(p: &mut Passes, w: &World, r: Ray, x: usize, y: usize) {
+    let black = color(0.0, 0.0, 0.0);
+    let xs = w.intersect(r);
+    let (direct, rest, distance) = match xs.iter().find(|i| i.t >= 0.0) {
+        Some(hit) => {
+            let comps = prepare_computations(*hit, r, w, &xs);
+            record_surface(p, w, &comps, x, y);
+
+            let (direct, rest, shadowed) = w.shade_hit_parts(comps);
+            if shadowed {
+                p.shadow.write_pixel(x, y, color(1.0, 1.0, 1.0));
+            }
+            (direct, rest, hit.t)
+        },
+        // all background, which counts as indirect
+        None => (black, w.background_color(r), f64::INFINITY),
+    };
+
+    // direct light is what's left of it after any fog, and the light the
+    // fog scatters in counts as indirect - so neither goes negative
+    let (scattered, transmittance) = match medium_at_origin(w, &xs) {
+        Some((m, container)) => march_segment(w, r, distance, m, container),
+        None                 => (black, color(1.0, 1.0, 1.0)),
+    };
+    let direct = direct * transmittance;
+    let indirect = scattered + rest * transmittance;
+
+    p.beauty.write_pixel(x, y, direct + indirect);
+    p.direct.write_pixel(x, y, direct);
+    p.indirect.write_pixel(x, y, indirect);
+}
+
+// only the passes that need no shading - depth, normal, object and
+// albedo. Much cheaper than record, and all the denoiser looks at.
+pub fn record_guides(p: &mut Passes, w: &World, r: Ray, x: usize, y: usize) {
+    let xs = w.intersect(r);
+    if let Some(hit) = xs.iter().find(|i| i.t >= 0.0) {
+        let comps = prepare_computations(*hit, r, w, &xs);
+        record_surface(p, w, &comps, x, y);
+    }
+}
+
+fn record_surface(p: &mut Passes, w: &World, comps: &Computations, x: usize, y: usize) {
+    let o = w.get_object(comps.object);
+    let n = comps.normalv;
+    p.depth.write_pixel(x, y, color(comps.t, comps.t, comps.t));
+    p.normal.write_pixel(x, y, color(n.x, n.y, n.z));
+    p.object.write_pixel(x, y, id_color(comps.object));
+    p.albedo.write_pixel(x, y, surface_color(o.get_material(), o.as_ref(), comps.point, w));
+    p.object_ids[y][x] = Some(comps.object);
+}
+
+// bright, well spread colors so neighbouring ids are easy to tell apart
+pub fn id_color(id: usize) -> Color {
+    let mut h = (id as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    h ^= h >> 31;
+    let channel = |shift: u32| 0.25 + ((h >> shift) & 0xFF) as f64 / 255.0 * 0.75;
+    color(channel(0), channel(8), channel(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aovs::{passes, record, record_guides, id_color};
+    use crate::world::default_world;
+    use crate::camera::camera;
+    use crate::color::color;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::transform::view_transform;
+    use crate::equals::equals;
+    use crate::media::fog;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn a_hit_fills_every_pass(){
+        let w = default_world();
+        let mut p = passes(1, 1);
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        record(&mut p, &w, r, 0, 0);
+
+        assert!(equals(p.depth.pixel_at(0, 0).r, 4.0));
+        assert!(p.normal.pixel_at(0, 0).equals(color(0.0, 0.0, -1.0)));
+        assert_eq!(p.object_at(0, 0), Some(0));
+        assert!(p.object.pixel_at(0, 0).equals(id_color(0)));
+        assert!(p.albedo.pixel_at(0, 0).equals(color(0.8, 1.0, 0.6)));
+
+        let sum = p.direct.pixel_at(0, 0) + p.indirect.pixel_at(0, 0);
+        assert!(sum.equals(p.beauty.pixel_at(0, 0)));
+        assert!(p.shadow.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn a_miss_leaves_infinite_depth(){
+        let w = default_world();
+        let mut p = passes(1, 1);
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 1.0, 0.0), 0);
+        record(&mut p, &w, r, 0, 0);
+
+        assert!(p.depth.pixel_at(0, 0).r.is_infinite());
+        assert_eq!(p.object_at(0, 0), None);
+        assert!(p.albedo.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn shadow_mask_marks_blocked_points(){
+        // the inner sphere seen from inside the outer one faces away from
+        // the light, and the outer sphere blocks it
+        let w = default_world();
+        let mut p = passes(1, 1);
+        let r = ray(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0), 0);
+        record(&mut p, &w, r, 0, 0);
+        assert!(p.shadow.pixel_at(0, 0).equals(color(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn passes_match_color_at_in_fog(){
+        let mut w = default_world();
+        w.fog = Some(fog(0.2));
+        let mut p = passes(1, 2);
+        let hit = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let miss = ray(point(0.0, 0.0, -5.0), vector(0.0, 1.0, 0.0), 0);
+        record(&mut p, &w, hit, 0, 0);
+        record(&mut p, &w, miss, 0, 1);
+
+        for (y, r) in [(0, hit), (1, miss)] {
+            assert!(p.beauty.pixel_at(0, y).equals(w.color_at(r)));
+            let i = p.indirect.pixel_at(0, y);
+            assert!(i.r >= 0.0 && i.g >= 0.0 && i.b >= 0.0);
+            assert!((p.direct.pixel_at(0, y) + i).equals(p.beauty.pixel_at(0, y)));
+        }
+    }
+
+    #[test]
+    fn guides_match_the_full_record(){
+        let w = default_world();
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let mut full = passes(1, 1);
+        let mut guides = passes(1, 1);
+        record(&mut full, &w, r, 0, 0);
+        record_guides(&mut guides, &w, r, 0, 0);
+
+        assert!(guides.depth.equals(full.depth));
+        assert!(guides.normal.equals(full.normal));
+        assert!(guides.albedo.equals(full.albedo));
+        assert_eq!(guides.object_at(0, 0), Some(0));
+        // nothing shaded
+        assert!(guides.beauty.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn ids_get_different_colors(){
+        assert!(!id_color(0).equals(id_color(1)));
+        assert!(!id_color(1).equals(id_color(2)));
+    }
+
+    #[test]
+    fn rendering_passes_with_a_camera(){
+        let w = default_world();
+        let mut c = camera(11, 11, PI / 2.0);
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        let p = c.render_passes(&w);
+        assert_eq!(p.object_at(5, 5), Some(0));
+        assert_eq!(p.object_at(0, 0), None);
+        assert!(p.beauty.pixel_at(5, 5).equals(color(0.38066, 0.47583, 0.2855)));
+    }
+}
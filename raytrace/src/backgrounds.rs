@@ -0,0 +1,168 @@
+use crate::color::{Color, color};
+use crate::canvas::Canvas;
+use crate::tuple::{Tuple, vector};
+use std::f64::consts::PI;
+
+// what a ray sees when it misses everything, looked up by direction
+#[derive(Debug)]
+pub enum Background {
+    Solid(Color),
+    // blends from bottom (straight down) to top (straight up)
+    Gradient { bottom: Color, top: Color },
+    // equirectangular (latitude/longitude) image, -z in the middle and
+    // straight up along the top row
+    Image(Canvas),
+    Sky(Sky),
+}
+
+impl Background {
+    pub fn color_at(&self, direction: Tuple) -> Color {
+        let d = direction.normal();
+        match self {
+            Background::Solid(c) => *c,
+            Background::Gradient { bottom, top } => {
+                let t = (d.y + 1.0) / 2.0;
+                *bottom * (1.0 - t) + *top * t
+            },
+            Background::Image(c) => {
+                let (u, v) = equirect_uv(d);
+                let x = ((u * c.width as f64) as usize).min(c.width - 1);
+                let y = ((v * c.height as f64) as usize).min(c.height - 1);
+                c.pixel_at(x, y)
+            },
+            Background::Sky(s) => s.color_at(d),
+        }
+    }
+}
+
+// simple analytic daylight - a zenith-to-horizon blend above the horizon,
+// flat ground below it, and a sun disk with a soft glow around it. Not a
+// physical model, but cheap and it reads as sky.
+#[derive(Debug,Clone)]
+pub struct Sky {
+    pub sun: Tuple,             // direction towards the sun
+    pub sun_color: Color,
+    pub sun_size: f64,          // angular radius in radians
+    pub zenith: Color,
+    pub horizon: Color,
+    pub ground: Color,
+}
+
+impl Sky {
+    pub fn color_at(&self, d: Tuple) -> Color {
+        if d.y < 0.0 {
+            return self.ground;
+        }
+
+        // squashed towards the horizon like the real thing
+        let t = d.y.sqrt();
+        let base = self.horizon * (1.0 - t) + self.zenith * t;
+
+        let cos_sun = d.dot(&self.sun.normal());
+        if cos_sun >= self.sun_size.cos() {
+            return self.sun_color;
+        }
+        let glow = cos_sun.max(0.0).powf(64.0) * 0.25;
+        base + self.sun_color * glow
+    }
+}
+
+// (u, v) in [0, 1) for a unit direction, u around the horizon and v from
+// straight up (0) to straight down (1)
+pub fn equirect_uv(d: Tuple) -> (f64, f64) {
+    let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+    let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+    (u.rem_euclid(1.0), v)
+}
+
+pub fn solid_background(c: Color) -> Background {
+    Background::Solid(c)
+}
+
+pub fn gradient_background(bottom: Color, top: Color) -> Background {
+    Background::Gradient { bottom, top }
+}
+
+pub fn environment_map(c: Canvas) -> Background {
+    Background::Image(c)
+}
+
+pub fn sky(sun: Tuple) -> Background {
+    Background::Sky(Sky {
+        sun,
+        sun_color: color(20.0, 18.0, 15.0),
+        sun_size: 0.00935,
+        zenith: color(0.15, 0.35, 0.85),
+        horizon: color(0.75, 0.85, 1.0),
+        ground: color(0.3, 0.28, 0.25),
+    })
+}
+
+// a sun straight above for the common case
+pub fn default_sky() -> Background {
+    sky(vector(0.0, 1.0, 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backgrounds::{solid_background, gradient_background, environment_map,
+                             sky, equirect_uv};
+    use crate::canvas::canvas;
+    use crate::color::color;
+    use crate::tuple::vector;
+    use crate::equals::equals;
+
+    #[test]
+    fn solid_is_the_same_everywhere(){
+        let b = solid_background(color(0.2, 0.3, 0.4));
+        assert!(b.color_at(vector(0.0, 1.0, 0.0)).equals(color(0.2, 0.3, 0.4)));
+        assert!(b.color_at(vector(1.0, -1.0, 3.0)).equals(color(0.2, 0.3, 0.4)));
+    }
+
+    #[test]
+    fn gradient_runs_bottom_to_top(){
+        let b = gradient_background(color(0.0, 0.0, 0.0), color(1.0, 0.5, 0.0));
+        assert!(b.color_at(vector(0.0, -1.0, 0.0)).equals(color(0.0, 0.0, 0.0)));
+        assert!(b.color_at(vector(0.0, 2.0, 0.0)).equals(color(1.0, 0.5, 0.0)));
+        assert!(b.color_at(vector(1.0, 0.0, 0.0)).equals(color(0.5, 0.25, 0.0)));
+    }
+
+    #[test]
+    fn equirect_mapping(){
+        let (u, v) = equirect_uv(vector(0.0, 0.0, -1.0));
+        assert!(equals(u, 0.5) && equals(v, 0.5));
+        let (u, _) = equirect_uv(vector(1.0, 0.0, 0.0));
+        assert!(equals(u, 0.75));
+        let (_, v) = equirect_uv(vector(0.0, 1.0, 0.0));
+        assert!(equals(v, 0.0));
+        let (_, v) = equirect_uv(vector(0.0, -1.0, 0.0));
+        assert!(equals(v, 1.0));
+    }
+
+    #[test]
+    fn environment_map_lookup(){
+        let mut c = canvas(4, 2);
+        c.write_pixel(2, 0, color(1.0, 0.0, 0.0));   // upper, just past -z
+        c.write_pixel(3, 1, color(0.0, 0.0, 1.0));   // lower, towards +x
+        let b = environment_map(c);
+
+        assert!(b.color_at(vector(0.1, 0.5, -1.0)).equals(color(1.0, 0.0, 0.0)));
+        assert!(b.color_at(vector(1.0, -0.5, 0.0)).equals(color(0.0, 0.0, 1.0)));
+        assert!(b.color_at(vector(-1.0, 0.5, 0.0)).equals(color(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn sky_has_sun_sky_and_ground(){
+        let b = sky(vector(1.0, 1.0, 0.0));
+        let sun = b.color_at(vector(1.0, 1.0, 0.0));
+        assert!(sun.equals(color(20.0, 18.0, 15.0)));
+
+        let ground = b.color_at(vector(0.0, -1.0, 0.0));
+        assert!(ground.equals(color(0.3, 0.28, 0.25)));
+
+        // zenith is bluer than the horizon
+        let up = b.color_at(vector(-1.0, 10.0, 0.0));
+        let flat = b.color_at(vector(-1.0, 0.01, 0.0));
+        assert!(up.b - up.r > flat.b - flat.r);
+    }
+}
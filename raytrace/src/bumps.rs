@@ -0,0 +1,249 @@
+use crate::shapes::Shape;
+use crate::world::World;
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::tuple::{Tuple, vector};
+use crate::noise::perlin;
+use crate::sampling::orthonormal_basis;
+
+// step used for the finite differences below
+const DELTA: f64 = 0.0001;
+
+// ways to fake surface detail by bending the shading normal. The real
+// geometry (and so intersections, shadows and the over/under points) is
+// left alone.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Bump {
+    // Perlin noise in object space - scale is the size of a bump
+    Noise { scale: f64, amplitude: f64 },
+    // brightness of one of the world's patterns used as a height
+    Pattern { pattern: usize, amplitude: f64 },
+    // tangent space normal map from one of the world's textures, laid
+    // out with the shape's UVs
+    NormalMap { texture: usize, strength: f64 },
+}
+
+pub fn noise_bump(scale: f64, amplitude: f64) -> Bump {
+    Bump::Noise { scale, amplitude }
+}
+
+pub fn pattern_bump(pattern: usize, amplitude: f64) -> Bump {
+    Bump::Pattern { pattern, amplitude }
+}
+
+pub fn normal_map(texture: usize) -> Bump {
+    Bump::NormalMap { texture, strength: 1.0 }
+}
+
+// n is the world normal at world point p, already flipped towards the eye.
+// Like normal_at this only goes through the shape's own transform - hits
+// are looked up among the World's top level shapes, so a bumped shape
+// inside a group never gets here until groups can convert through their
+// parents (world_to_object is still a stub).
+pub fn perturb_normal(b: &Bump, o: &dyn Shape, p: Tuple, n: Tuple, w: &World) -> Tuple {
+    match b {
+        Bump::Noise { scale, amplitude } => {
+            let inverse = o.get_transform().inverse();
+            from_height(|q| perlin(inverse.multup(&q) * (1.0 / scale)) * amplitude, p, n)
+        },
+        Bump::Pattern { pattern, amplitude } => {
+            let pat = w.get_pattern(*pattern);
+            from_height(|q| luminance(pat.pattern_at_shape(o, q)) * amplitude, p, n)
+        },
+        Bump::NormalMap { texture, strength } => {
+            let object_point = o.get_transform().inverse().multup(&p);
+            let (u, v) = match o.local_uv_at(object_point) {
+                Some(uv) => uv,
+                None     => return n,
+            };
+            let (t, b) = match tangent_frame(o, p, n) {
+                Some(frame) => frame,
+                None        => return n,
+            };
+
+            let c = texture_at(w.get_texture(*texture), u, v);
+            let x = (c.r * 2.0 - 1.0) * strength;
+            let y = (c.g * 2.0 - 1.0) * strength;
+            let z = c.b * 2.0 - 1.0;
+            (t * x + b * y + n * z).normal()
+        },
+    }
+}
+
+// tilts n away from uphill on the height field h
+fn from_height<F: Fn(Tuple) -> f64>(h: F, p: Tuple, n: Tuple) -> Tuple {
+    let dx = vector(DELTA, 0.0, 0.0);
+    let dy = vector(0.0, DELTA, 0.0);
+    let dz = vector(0.0, 0.0, DELTA);
+    let gradient = vector(h(p + dx) - h(p - dx),
+                          h(p + dy) - h(p - dy),
+                          h(p + dz) - h(p - dz)) * (1.0 / (2.0 * DELTA));
+
+    // only the part along the surface matters
+    let along = gradient - n * gradient.dot(&n);
+    (n - along).normal()
+}
+
+// world space tangent (direction of increasing u) and bitangent
+// (increasing v) at p, both perpendicular to n. Worked out numerically
+// from local_uv_at, so any shape with UVs gets one.
+pub fn tangent_frame(o: &dyn Shape, p: Tuple, n: Tuple) -> Option<(Tuple, Tuple)> {
+    let q = o.get_transform().inverse().multup(&p);
+    let (u0, v0) = o.local_uv_at(q)?;
+    let (a, b) = orthonormal_basis(o.local_normal_at(q).normal());
+    let (ua, va) = o.local_uv_at(q + a * DELTA)?;
+    let (ub, vb) = o.local_uv_at(q + b * DELTA)?;
+
+    // how u and v change stepping along a and b, inverted to get how the
+    // point changes stepping along u and v
+    let du_a = wrap(ua - u0);
+    let dv_a = wrap(va - v0);
+    let du_b = wrap(ub - u0);
+    let dv_b = wrap(vb - v0);
+    let det = du_a * dv_b - du_b * dv_a;
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let dp_du = (a * dv_b - b * dv_a) * (1.0 / det);
+    let dp_dv = (b * du_a - a * du_b) * (1.0 / det);
+
+    let mut t = o.get_transform().multup(&dp_du);
+    let mut bt = o.get_transform().multup(&dp_dv);
+    t.w = 0.0;
+    bt.w = 0.0;
+
+    let t = (t - n * t.dot(&n)).normal();
+    let mut b = n.cross(&t);
+    if b.dot(&bt) < 0.0 {
+        b = -b;
+    }
+    Some((t, b))
+}
+
+// uv seams - a step across one shows up as a jump of nearly 1
+fn wrap(d: f64) -> f64 {
+    if d > 0.5 {
+        d - 1.0
+    } else if d < -0.5 {
+        d + 1.0
+    } else {
+        d
+    }
+}
+
+fn luminance(c: Color) -> f64 {
+    0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b
+}
+
+// nearest pixel, with v = 1 along the top row
+fn texture_at(c: &Canvas, u: f64, v: f64) -> Color {
+    let x = ((u.rem_euclid(1.0) * c.width as f64) as usize).min(c.width - 1);
+    let y = (((1.0 - v).clamp(0.0, 1.0) * c.height as f64) as usize).min(c.height - 1);
+    c.pixel_at(x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bumps::{noise_bump, pattern_bump, normal_map, perturb_normal, tangent_frame};
+    use crate::world::world;
+    use crate::planes::plane;
+    use crate::spheres::sphere;
+    use crate::shapes::Shape;
+    use crate::canvas::canvas;
+    use crate::color::color;
+    use crate::gradients::gradient_pattern;
+    use crate::tuple::{point, vector};
+    use crate::transform::rotation_x;
+    use crate::equals::equals;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn tangent_frame_of_a_plane(){
+        let o = plane();
+        let n = vector(0.0, 1.0, 0.0);
+        let (t, b) = tangent_frame(&o, point(0.3, 0.0, 0.2), n).unwrap();
+        assert!(t.equals(vector(1.0, 0.0, 0.0)));
+        assert!(b.equals(vector(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn tangent_frame_follows_the_transform(){
+        let mut p = plane();
+        p.set_transform(rotation_x(PI / 2.0));
+        let o: Box<dyn Shape> = Box::new(p);
+        let n = o.normal_at(point(0.3, 0.2, 0.0));
+        let (t, b) = tangent_frame(o.as_ref(), point(0.3, 0.2, 0.0), n).unwrap();
+        assert!(t.equals(vector(1.0, 0.0, 0.0)));
+        assert!(b.equals(vector(0.0, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn tangent_frame_of_a_sphere_runs_around_the_equator(){
+        let o: Box<dyn Shape> = Box::new(sphere());
+        let p = point(0.0, 0.0, -1.0);
+        let (t, b) = tangent_frame(o.as_ref(), p, o.normal_at(p)).unwrap();
+        assert!(t.equals(vector(1.0, 0.0, 0.0)));
+        assert!(b.equals(vector(0.0, 1.0, 0.0)));
+
+        // and across the seam at the back
+        let p = point(0.0, 0.0, 1.0);
+        let (t, _) = tangent_frame(o.as_ref(), p, o.normal_at(p)).unwrap();
+        assert!(t.equals(vector(-1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn flat_normal_map_changes_nothing(){
+        let mut w = world();
+        let mut c = canvas(2, 2);
+        for y in 0..2 {
+            for x in 0..2 {
+                c.write_pixel(x, y, color(0.5, 0.5, 1.0));
+            }
+        }
+        let tex = w.add_texture(c);
+        let o = plane();
+        let n = vector(0.0, 1.0, 0.0);
+        let bent = perturb_normal(&normal_map(tex), &o, point(0.2, 0.0, 0.7), n, &w);
+        assert!(bent.equals(n));
+    }
+
+    #[test]
+    fn normal_map_tilts_along_the_tangent(){
+        let mut w = world();
+        let mut c = canvas(1, 1);
+        c.write_pixel(0, 0, color(1.0, 0.5, 1.0));
+        let tex = w.add_texture(c);
+        let o = plane();
+        let bent = perturb_normal(&normal_map(tex), &o, point(0.2, 0.0, 0.7),
+                                  vector(0.0, 1.0, 0.0), &w);
+        assert!(bent.equals(vector(1.0, 1.0, 0.0).normal()));
+    }
+
+    #[test]
+    fn gradient_pattern_bump_leans_downhill(){
+        // height rises along x, so the normal leans back towards -x
+        let mut w = world();
+        let pat = w.add_pattern(Box::new(gradient_pattern(color(0.0, 0.0, 0.0), color(1.0, 1.0, 1.0))));
+        let o = plane();
+        let bent = perturb_normal(&pattern_bump(pat, 1.0), &o, point(0.5, 0.0, 0.0),
+                                  vector(0.0, 1.0, 0.0), &w);
+        assert!(bent.equals(vector(-1.0, 1.0, 0.0).normal()));
+    }
+
+    #[test]
+    fn noise_bump_varies_but_stays_facing_out(){
+        let w = world();
+        let o = plane();
+        let n = vector(0.0, 1.0, 0.0);
+        let b = noise_bump(0.5, 0.05);
+        let mut moved = false;
+        for i in 0..20 {
+            let p = point(i as f64 * 0.31, 0.0, i as f64 * 0.17);
+            let bent = perturb_normal(&b, &o, p, n, &w);
+            assert!(equals(bent.mag(), 1.0));
+            assert!(bent.dot(&n) > 0.5);
+            moved |= !bent.equals(n);
+        }
+        assert!(moved);
+    }
+}
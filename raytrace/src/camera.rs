@@ -1,8 +1,31 @@
 use crate::matrix::{Matrix, identity};
 use crate::rays::{Ray, ray};
-use crate::tuple::{point, origin};
+use crate::tuple::{Tuple, point, vector, origin};
 use crate::world::World;
 use crate::canvas::{Canvas, canvas};
+use crate::color::color;
+use crate::intersections::prepare_computations;
+use crate::occlusion::{Occlusion, occlusion_at};
+use crate::sampling::{rng, pixel_seed};
+use crate::aovs::{Passes, passes, record, record_guides};
+use crate::stats::{self, Stats, Progress};
+use std::time::Instant;
+use std::borrow::Borrow;
+use std::f64::consts::PI;
+
+// how camera space directions are laid out across the canvas
+#[derive(Debug,Clone,PartialEq)]
+pub enum Projection {
+    // pinhole, field_of_view across the longer side
+    Perspective,
+    // parallel rays, `width` world units across the canvas
+    Orthographic { width: f64 },
+    // equidistant fisheye - angle from the center grows linearly out to
+    // field_of_view / 2 at the edge of a circle touching the shorter side
+    Fisheye,
+    // 360 x 180 degree equirectangular, looking forward at the center
+    Panorama,
+}
 
 #[derive(Debug)]
 pub struct Camera {
@@ -11,38 +34,217 @@ pub struct Camera {
     pub field_of_view: f64,
     pub transform: Matrix,
     pub pixel_size: f64,
+    pub projection: Projection,
+    // interocular distance - when set the image is two views side by
+    // side, left eye on the left, each hsize wide
+    pub stereo: Option<f64>,
     half_width: f64,
     half_height: f64,
 }
 
 impl Camera {
+    // pixels outside a fisheye's circle have no ray - this falls back
+    // to one straight ahead, renders leave them black instead
     pub fn ray_for_pixel(&self, px: i32, py: i32) -> Ray {
-        let xoffset = (px as f64 + 0.5) * self.pixel_size;
-        let yoffset = (py as f64 + 0.5) * self.pixel_size;
+        let x = px as f64 + 0.5;
+        let y = py as f64 + 0.5;
+        self.ray_for_point(x, y).unwrap_or_else(|| self.to_world(origin(), vector(0.0, 0.0, -1.0)))
+    }
 
-        let world_x = self.half_width - xoffset;
-        let world_y = self.half_height - yoffset;
+    // same as ray_for_pixel, but aimed anywhere on the canvas rather
+    // than at pixel centers - (0, 0) is the top left corner of the
+    // first pixel. Used for jittered sampling.
+    pub fn ray_for_point(&self, x: f64, y: f64) -> Option<Ray> {
+        let (x, eye) = match self.stereo {
+            Some(iod) if x >= self.hsize as f64 => (x - self.hsize as f64, -iod / 2.0),
+            Some(iod)                           => (x, iod / 2.0),
+            None                                => (x, 0.0),
+        };
 
-        let pixel = self.transform.inverse().multup( &point(world_x, world_y, -1.0) );
-        let origin = self.transform.inverse().multup( &origin() );
-        let direction = (pixel - origin).normal();
+        // camera space looks down -z with +x to the left
+        let (from, direction) = match &self.projection {
+            Projection::Perspective => {
+                let world_x = self.half_width - x * self.pixel_size;
+                let world_y = self.half_height - y * self.pixel_size;
+                (point(eye, 0.0, 0.0), vector(world_x, world_y, -1.0))
+            },
+            Projection::Orthographic { width } => {
+                let scale = width / self.hsize as f64;
+                let world_x = (self.hsize as f64 / 2.0 - x) * scale;
+                let world_y = (self.vsize as f64 / 2.0 - y) * scale;
+                (point(world_x + eye, world_y, 0.0), vector(0.0, 0.0, -1.0))
+            },
+            Projection::Fisheye => {
+                let radius = self.hsize.min(self.vsize) as f64 / 2.0;
+                let nx = (self.hsize as f64 / 2.0 - x) / radius;
+                let ny = (self.vsize as f64 / 2.0 - y) / radius;
+                let r = (nx * nx + ny * ny).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * self.field_of_view / 2.0;
+                let (sx, sy) = if r > 0.0 { (nx / r, ny / r) } else { (0.0, 0.0) };
+                (point(eye, 0.0, 0.0), vector(theta.sin() * sx, theta.sin() * sy, -theta.cos()))
+            },
+            Projection::Panorama => {
+                let longitude = (x / self.hsize as f64 - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - y / self.vsize as f64) * PI;
+                // eyes sit on a circle so every direction gets stereo
+                let left = vector(longitude.cos(), 0.0, -longitude.sin());
+                (origin() + left * eye,
+                 vector(-longitude.sin() * latitude.cos(), latitude.sin(), -longitude.cos() * latitude.cos()))
+            },
+        };
 
-        ray( origin, direction, 0 )
+        Some(self.to_world(from, direction))
+    }
+
+    // full canvas width, both eyes included
+    pub fn image_width(&self) -> i32 {
+        if self.stereo.is_some() { self.hsize * 2 } else { self.hsize }
+    }
+
+    fn to_world(&self, from: Tuple, direction: Tuple) -> Ray {
+        let inverse = self.transform.inverse();
+        let origin = inverse.multup( &from );
+        let mut direction = inverse.multup( &direction );
+        direction.w = 0.0;
+
+        ray( origin, direction.normal(), 0 )
+    }
+
+    // takes the world or a reference to it
+    pub fn render<W: Borrow<World>>(&self, w: W) -> Canvas {
+        let w = w.borrow();
+        let (width, height) = self.size();
+        let mut image = canvas(width, height);
+        self.for_each_pixel(|x, y, ray| image.write_pixel(x, y, w.color_at(ray)));
+        image
     }
 
-    pub fn render(&self, w: World) -> Canvas {
-        let mut image = canvas(self.hsize.try_into().unwrap(), self.vsize.try_into().unwrap());
+    // render, but collecting stats and calling `observer` after every row
+    // so a long render can show how it's getting on
+    pub fn render_observed(&self, w: &World, observer: &mut dyn FnMut(&Progress)) -> (Canvas, Stats) {
+        let (width, height) = self.size();
+        let mut image = canvas(width, height);
+        let started = Instant::now();
+        stats::start();
 
-        for y in 0..(self.vsize - 1){
-            for x in 0..(self.hsize - 1){
-                let ray = self.ray_for_pixel(x, y);
-                let color = w.color_at(ray);
-                image.write_pixel(x.try_into().unwrap(), y.try_into().unwrap(), color);
+        self.for_each_sample(&[(0.5, 0.5)],
+            |x, y, ray| image.write_pixel(x, y, w.color_at(ray)),
+            |y| observer(&Progress {
+                done: (y + 1) * width,
+                total: width * height,
+                elapsed: started.elapsed(),
+                rays: stats::current().rays(),
+            }));
+
+        (image, stats::finish())
+    }
+
+    // "clay" render - every pixel is just how open the surface is, white
+    // where nothing is nearby and darker in corners. Misses are white.
+    pub fn render_occlusion(&self, w: &World, ao: &Occlusion) -> Canvas {
+        let (width, height) = self.size();
+        let mut image = canvas(width, height);
+
+        self.for_each_pixel(|x, y, ray| {
+            let xs = w.intersect(ray);
+            let value = match xs.iter().find(|i| i.t >= 0.0) {
+                Some(hit) => {
+                    let comps = prepare_computations(*hit, ray, w, &xs);
+                    let mut r = rng(pixel_seed(ao.seed, x, y));
+                    occlusion_at(w, comps.over_point, comps.normalv, ao, &mut r)
+                },
+                None => 1.0,
+            };
+            image.write_pixel(x, y, color(value, value, value));
+        });
+
+        image
+    }
+
+    // render with a transparent background for compositing. Each pixel
+    // is split into samples x samples subpixels, and alpha is the share of
+    // them that hit something - so edges come out antialiased, and the
+    // colors are premultiplied (misses add nothing).
+    pub fn render_alpha(&self, w: &World, samples: usize) -> Canvas {
+        let (width, height) = self.size();
+        let n = samples.max(1);
+        let mut image = canvas(width, height);
+        let mut hits = vec![vec![0; width]; height];
+
+        let mut offsets = vec!();
+        for j in 0..n {
+            for i in 0..n {
+                offsets.push(((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64));
+            }
+        }
+
+        self.for_each_sample(&offsets, |x, y, ray| {
+            let xs = w.intersect(ray);
+            if xs.iter().any(|i| i.t >= 0.0) {
+                image.write_pixel(x, y, image.pixel_at(x, y) + w.color_for_hits(ray, &xs));
+                hits[y][x] += 1;
+            }
+        }, |_| ());
+
+        let total = (n * n) as f64;
+        for (y, row) in hits.iter().enumerate() {
+            for (x, h) in row.iter().enumerate() {
+                image.write_pixel(x, y, image.pixel_at(x, y) * (1.0 / total));
+                image.write_alpha(x, y, *h as f64 / total);
             }
         }
 
         image
     }
+
+    // the normal render plus all the auxiliary passes in one go
+    pub fn render_passes(&self, w: &World) -> Passes {
+        let (width, height) = self.size();
+        let mut p = passes(width, height);
+        self.for_each_pixel(|x, y, ray| record(&mut p, w, ray, x, y));
+        p
+    }
+
+    // depth, normal, object and albedo only, no shading
+    pub fn render_guides(&self, w: &World) -> Passes {
+        let (width, height) = self.size();
+        let mut p = passes(width, height);
+        self.for_each_pixel(|x, y, ray| record_guides(&mut p, w, ray, x, y));
+        p
+    }
+
+    // canvas size, both eyes included
+    fn size(&self) -> (usize, usize) {
+        (self.image_width().try_into().unwrap(), self.vsize.try_into().unwrap())
+    }
+
+    // f gets the ray through the center of every pixel, row by row.
+    // Pixels with no ray (outside a fisheye's circle) are skipped.
+    fn for_each_pixel(&self, f: impl FnMut(usize, usize, Ray)) {
+        self.for_each_sample(&[(0.5, 0.5)], f, |_| ());
+    }
+
+    // same, but with a ray through each of `offsets` (0 to 1 across the
+    // pixel) and end_of_row called after each row is done
+    fn for_each_sample(&self, offsets: &[(f64, f64)],
+                       mut f: impl FnMut(usize, usize, Ray),
+                       mut end_of_row: impl FnMut(usize)) {
+        let (width, height) = self.size();
+        for y in 0..height {
+            for x in 0..width {
+                for (dx, dy) in offsets {
+                    if let Some(ray) = self.ray_for_point(x as f64 + dx, y as f64 + dy) {
+                        stats::record(|s| s.primary_rays += 1);
+                        f(x, y, ray);
+                    }
+                }
+            }
+            end_of_row(y);
+        }
+    }
 }
 
 pub fn camera(hsize: i32, vsize: i32, field_of_view: f64) -> Camera {
@@ -61,6 +263,8 @@ pub fn camera(hsize: i32, vsize: i32, field_of_view: f64) -> Camera {
             field_of_view,
             transform: identity(),
             pixel_size: (half_width * 2.0) / hsize as f64,
+            projection: Projection::Perspective,
+            stereo: None,
             half_width,
             half_height,
     }
@@ -69,13 +273,18 @@ pub fn camera(hsize: i32, vsize: i32, field_of_view: f64) -> Camera {
 #[cfg(test)]
 mod tests {
     use std::f64::consts::{PI, SQRT_2};
-    use crate::camera::camera;
+    use crate::camera::{camera, Projection};
+    use crate::backgrounds::solid_background;
     use crate::matrix::identity;
     use crate::equals::equals;
     use crate::tuple::{point, vector};
     use crate::transform::{rotation_y, translation, view_transform};
-    use crate::world::default_world;
+    use crate::world::{world, default_world};
     use crate::color::color;
+    use crate::occlusion::occlusion;
+    use crate::planes::plane;
+    use crate::spheres::sphere;
+    use crate::shapes::Shape;
 
     #[test]
     fn constructing_a_camera(){
@@ -141,4 +350,140 @@ mod tests {
         let image = c.render(w);
         assert!( image.pixel_at(5, 5).equals( color(0.38066, 0.47583, 0.2855) ));
     }
+
+    #[test]
+    fn occlusion_render_of_a_sphere_on_a_floor(){
+        let mut w = world();
+        w.add_object(Box::new(plane()));
+        let mut s = sphere();
+        s.set_transform(translation(0.0, 1.0, 0.0));
+        w.add_object(Box::new(s));
+
+        let mut c = camera(11, 11, PI / 2.0);
+        c.transform = view_transform(point(0.0, 3.0, -3.0),
+                                     point(0.0, 0.0, 0.0),
+                                     vector(0.0, 1.0, 0.0));
+        let ao = occlusion();
+        let image = c.render_occlusion(&w, &ao);
+        assert_eq!(image.width, 11);
+        assert_eq!(image.height, 11);
+
+        // sky is white, and it's grey where the sphere meets the floor
+        assert!( image.pixel_at(0, 0).equals(color(1.0, 1.0, 1.0)) );
+        let darkest = image.pixels.iter().flatten().map(|p| p.r).fold(1.0, f64::min);
+        assert!( darkest < 0.7 );
+
+        let again = c.render_occlusion(&w, &ao);
+        assert!( image.pixels == again.pixels );
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel(){
+        let mut c = camera(200, 100, PI / 2.0);
+        c.projection = Projection::Orthographic { width: 4.0 };
+
+        let r = c.ray_for_pixel(100, 50);
+        assert!( r.origin.equals(point(-0.01, -0.01, 0.0)) );
+        assert!( r.direction.equals(vector(0.0, 0.0, -1.0)) );
+
+        // top left corner is 2 units left and 1 up - camera space +x is left
+        let r = c.ray_for_point(0.0, 0.0).unwrap();
+        assert!( r.origin.equals(point(2.0, 1.0, 0.0)) );
+        assert!( r.direction.equals(vector(0.0, 0.0, -1.0)) );
+    }
+
+    #[test]
+    fn fisheye_angle_grows_with_distance_from_center(){
+        let mut c = camera(100, 100, PI);
+        c.projection = Projection::Fisheye;
+
+        let r = c.ray_for_point(50.0, 50.0).unwrap();
+        assert!( r.direction.equals(vector(0.0, 0.0, -1.0)) );
+
+        // edge of the circle is 90 degrees off axis, half way is 45
+        let r = c.ray_for_point(50.0, 0.0).unwrap();
+        assert!( r.direction.equals(vector(0.0, 1.0, 0.0)) );
+        let r = c.ray_for_point(25.0, 50.0).unwrap();
+        assert!( r.direction.equals(vector(SQRT_2 / 2.0, 0.0, -SQRT_2 / 2.0)) );
+
+        // corners are outside the image circle
+        assert!( c.ray_for_point(1.0, 1.0).is_none() );
+    }
+
+    #[test]
+    fn panorama_covers_every_direction(){
+        let mut c = camera(360, 180, PI / 2.0);
+        c.projection = Projection::Panorama;
+
+        let r = c.ray_for_point(180.0, 90.0).unwrap();
+        assert!( r.direction.equals(vector(0.0, 0.0, -1.0)) );
+        let r = c.ray_for_point(270.0, 90.0).unwrap();
+        assert!( r.direction.equals(vector(-1.0, 0.0, 0.0)) );
+        let r = c.ray_for_point(0.0, 90.0).unwrap();
+        assert!( r.direction.equals(vector(0.0, 0.0, 1.0)) );
+        let r = c.ray_for_point(180.0, 0.0).unwrap();
+        assert!( r.direction.equals(vector(0.0, 1.0, 0.0)) );
+    }
+
+    #[test]
+    fn stereo_puts_the_eyes_side_by_side(){
+        let mut c = camera(100, 50, PI / 2.0);
+        c.stereo = Some(0.064);
+        assert_eq!(c.image_width(), 200);
+
+        let left = c.ray_for_point(50.0, 25.0).unwrap();
+        let right = c.ray_for_point(150.0, 25.0).unwrap();
+        assert!( left.origin.equals(point(0.032, 0.0, 0.0)) );
+        assert!( right.origin.equals(point(-0.032, 0.0, 0.0)) );
+        assert!( left.direction.equals(right.direction) );
+
+        let mut small = camera(10, 5, PI / 2.0);
+        small.stereo = Some(0.064);
+        let image = small.render(default_world());
+        assert_eq!(image.width, 20);
+        assert_eq!(image.height, 5);
+    }
+
+    #[test]
+    fn rendering_fills_the_last_row_and_column(){
+        let mut w = world();
+        w.background = Some(solid_background(color(1.0, 1.0, 1.0)));
+        let mut c = camera(10, 5, PI / 2.0);
+        c.stereo = Some(0.064);
+
+        // bottom right of the right eye
+        let image = c.render(&w);
+        assert!( image.pixel_at(19, 4).equals(color(1.0, 1.0, 1.0)) );
+        assert!( image.pixel_at(9, 4).equals(color(1.0, 1.0, 1.0)) );
+    }
+
+    #[test]
+    fn fisheye_render_leaves_corners_black(){
+        let mut w = default_world();
+        w.background = Some(solid_background(color(1.0, 1.0, 1.0)));
+        let mut c = camera(11, 11, PI);
+        c.projection = Projection::Fisheye;
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+
+        let image = c.render(w);
+        assert!( image.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)) );
+        assert!( image.pixel_at(5, 5).equals(color(0.38066, 0.47583, 0.2855)) );
+        assert!( image.pixel_at(5, 0).equals(color(1.0, 1.0, 1.0)) );
+    }
+
+    #[test]
+    fn rendering_with_alpha(){
+        let w = default_world();
+        let mut c = camera(11, 11, PI / 2.0);
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        let image = c.render_alpha(&w, 3);
+
+        assert!( equals(image.alpha_at(5, 5), 1.0) );
+        assert!( equals(image.alpha_at(0, 0), 0.0) );
+        assert!( image.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)) );
+
+        // somewhere along the rim only part of the pixel is covered
+        let partial = (0..11).any(|x| image.alpha_at(x, 5) > 0.0 && image.alpha_at(x, 5) < 1.0);
+        assert!( partial );
+    }
 }
@@ -1,19 +1,28 @@
-use std::fs::File;
-use std::io::{Write, Result};
+use std::fs::{File, read};
+use std::io::{Write, Result, Error, ErrorKind};
 use crate::color::{Color, color};
+use crate::tonemap::Output;
+use crate::imagediff::{Difference, difference, heatmap};
+use crate::png::png_bytes;
+use crate::equals::equals;
 
 #[derive(Debug)]
 pub struct Canvas {
     pub height: usize,
     pub width: usize,
     pub pixels: Vec<Vec<Color>>,
+    // coverage, 0 transparent to 1 opaque. None means opaque all over.
+    // Where it's set, pixels are premultiplied - already scaled by alpha.
+    pub alpha: Option<Vec<Vec<f64>>>,
 }
 
 impl Canvas {
+    // no alpha counts the same as fully opaque
     pub fn equals(&self, c: Canvas) -> bool {
         ( self.width == c.width ) &&
         ( self.height == c.height ) &&
-        c_equals(&self.pixels, &c.pixels)
+        c_equals(&self.pixels, &c.pixels) &&
+        (0..self.height).all(|y| (0..self.width).all(|x| equals(self.alpha_at(x, y), c.alpha_at(x, y))))
     }
 
     pub fn write_pixel(&mut self, x: usize, y: usize, c: Color) -> () {
@@ -24,6 +33,27 @@ impl Canvas {
         self.pixels[y][x]
     }
 
+    pub fn write_alpha(&mut self, x: usize, y: usize, a: f64) {
+        let (width, height) = (self.width, self.height);
+        self.alpha.get_or_insert_with(|| vec![vec![1.0; width]; height])[y][x] = a;
+    }
+
+    pub fn alpha_at(&self, x: usize, y: usize) -> f64 {
+        match &self.alpha {
+            Some(a) => a[y][x],
+            None    => 1.0,
+        }
+    }
+
+    // graded version of equals - see imagediff. None if the sizes differ
+    pub fn difference(&self, other: &Canvas) -> Option<Difference> {
+        difference(self, other)
+    }
+
+    pub fn heatmap(&self, other: &Canvas) -> Option<Canvas> {
+        heatmap(self, other)
+    }
+
     pub fn to_ppm(&self, name: &str) -> Result<File> {
         let mut f = File::create(name)?;
         let _ = write!(f, "P3\n");
@@ -36,6 +66,90 @@ impl Canvas {
         Ok(f)
     }
 
+    // to_ppm through an exposure / tone mapping / sRGB / dither pipeline
+    pub fn to_ppm_with(&self, name: &str, out: &Output) -> Result<File> {
+        let mut f = File::create(name)?;
+        write!(f, "P3\n{} {}\n255\n", self.width, self.height)?;
+        for (y, row) in self.pixels.iter().enumerate() {
+            let strings: Vec<String> = row.iter().enumerate().map(|(x, p)| {
+                let [r, g, b] = out.encode(*p, x, y);
+                format!("{} {} {}", r, g, b)
+            }).collect();
+            writeln!(f, "{}", Self::wrap_row(&strings))?;
+        }
+        Ok(f)
+    }
+
+    // packed 8-bit RGB, top row first, for the binary formats
+    pub fn to_bytes(&self, out: &Output) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.width * self.height * 3);
+        for (y, row) in self.pixels.iter().enumerate() {
+            for (x, p) in row.iter().enumerate() {
+                bytes.extend_from_slice(&out.encode(*p, x, y));
+            }
+        }
+        bytes
+    }
+
+    // display ready copy - values in 0..1, without the dithering
+    pub fn tone_mapped(&self, out: &Output) -> Canvas {
+        let mut c = canvas(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                c.write_pixel(x, y, out.apply(self.pixel_at(x, y)));
+            }
+        }
+        c.alpha = self.alpha.clone();
+        c
+    }
+
+    // 8-bit RGBA with straight (not premultiplied) alpha, which is what
+    // PNG and PAM expect
+    pub fn to_rgba_bytes(&self, out: &Output) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.width * self.height * 4);
+        for (y, row) in self.pixels.iter().enumerate() {
+            for (x, p) in row.iter().enumerate() {
+                let a = self.alpha_at(x, y).clamp(0.0, 1.0);
+                let straight = if a > 0.0 { *p * (1.0 / a) } else { color(0.0, 0.0, 0.0) };
+                bytes.extend_from_slice(&out.encode(straight, x, y));
+                bytes.push((a * 255.0).round() as u8);
+            }
+        }
+        bytes
+    }
+
+    pub fn to_png(&self, name: &str, out: &Output) -> Result<File> {
+        let mut f = File::create(name)?;
+        f.write_all(&png_bytes(self.width, self.height, &self.to_rgba_bytes(out)))?;
+        Ok(f)
+    }
+
+    // netpbm's PAM - the PPM family's answer to alpha
+    pub fn to_pam(&self, name: &str, out: &Output) -> Result<File> {
+        let mut f = File::create(name)?;
+        write!(f, "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+               self.width, self.height)?;
+        f.write_all(&self.to_rgba_bytes(out))?;
+        Ok(f)
+    }
+
+    // float version of to_ppm - nothing gets clamped, so depth passes and
+    // HDR renders survive. Little endian, rows bottom to top.
+    pub fn to_pfm(&self, name: &str) -> Result<File> {
+        let mut f = File::create(name)?;
+        write!(f, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let mut bytes = Vec::with_capacity(self.width * self.height * 12);
+        for row in self.pixels.iter().rev() {
+            for p in row {
+                for v in [p.r, p.g, p.b] {
+                    bytes.extend_from_slice(&(v as f32).to_le_bytes());
+                }
+            }
+        }
+        f.write_all(&bytes)?;
+        Ok(f)
+    }
+
     // test generates a cargo warning unless this is marked
     // public - probably should be an internal-only fn though
     pub fn pix_255(value: f64) -> i32 {
@@ -54,12 +168,16 @@ impl Canvas {
     }
 
     pub fn pixel_row_to_string(row: &Vec<Color>) -> String {
+        let strings: Vec<String> = row.iter().map(|p| Self::pixel_to_string(*p)).collect();
+        Self::wrap_row(&strings)
+    }
+
+    fn wrap_row(pixels: &[String]) -> String {
         let mut s = String::new();
         let mut index = 0;
         let mut line_length = 0;
 
-        for pixel in row {
-            let new_pix = &Self::pixel_to_string(*pixel);
+        for new_pix in pixels {
             line_length += new_pix.len() + 1;
 
             s += new_pix;
@@ -67,7 +185,7 @@ impl Canvas {
 
             // PPM line length should not exceed 70 chars
             // maximum pixel string is 12 chars, so 70-12=58
-            if index < row.len()-1 && line_length > 58 {
+            if index < pixels.len()-1 && line_length > 58 {
                 s += "\n";
                 line_length = 0;
             }
@@ -83,7 +201,128 @@ pub fn canvas(width: usize, height:usize) -> Canvas {
 
     Canvas { width,
              height,
-             pixels: v }
+             pixels: v,
+             alpha: None }
+}
+
+// reads a PPM (plain P3 or binary P6) back into a canvas, with
+// channels scaled to 0-1
+pub fn canvas_from_ppm(name: &str) -> Result<Canvas> {
+    ppm_to_canvas(&read(name)?)
+}
+
+// reads a PFM (the float version of PPM, "PF" color or "Pf" grey) - no
+// clamping, so this is the one to use for HDR environment maps
+pub fn canvas_from_pfm(name: &str) -> Result<Canvas> {
+    pfm_to_canvas(&read(name)?)
+}
+
+pub fn ppm_to_canvas(bytes: &[u8]) -> Result<Canvas> {
+    let (header, start) = header_tokens(bytes, 4)?;
+    let width = parse_number(&header[1])? as usize;
+    let height = parse_number(&header[2])? as usize;
+    let maxval = parse_number(&header[3])?;
+    if maxval <= 0.0 {
+        return Err(bad_image("PPM maxval must be positive"));
+    }
+
+    let values: Vec<f64> = match header[0].as_str() {
+        "P3" => {
+            let text = String::from_utf8_lossy(&bytes[start..]);
+            text.split_whitespace().map(parse_number).collect::<Result<Vec<f64>>>()?
+        },
+        "P6" if maxval < 256.0 => bytes[start..].iter().map(|b| *b as f64).collect(),
+        "P6" => bytes[start..].chunks_exact(2)
+                              .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64)
+                              .collect(),
+        _ => return Err(bad_image("not a P3 or P6 file")),
+    };
+    if values.len() < width * height * 3 {
+        return Err(bad_image("PPM is missing pixel data"));
+    }
+
+    let mut c = canvas(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) * 3;
+            c.write_pixel(x, y, color(values[i]     / maxval,
+                                      values[i + 1] / maxval,
+                                      values[i + 2] / maxval));
+        }
+    }
+    Ok(c)
+}
+
+pub fn pfm_to_canvas(bytes: &[u8]) -> Result<Canvas> {
+    let (header, start) = header_tokens(bytes, 4)?;
+    let channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _    => return Err(bad_image("not a PF or Pf file")),
+    };
+    let width = parse_number(&header[1])? as usize;
+    let height = parse_number(&header[2])? as usize;
+    // the sign of the scale gives the byte order
+    let little_endian = parse_number(&header[3])? < 0.0;
+
+    let floats: Vec<f64> = bytes[start..].chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if little_endian { f32::from_le_bytes(b) as f64 } else { f32::from_be_bytes(b) as f64 }
+    }).collect();
+    if floats.len() < width * height * channels {
+        return Err(bad_image("PFM is missing pixel data"));
+    }
+
+    // rows are stored bottom to top
+    let mut c = canvas(width, height);
+    for row in 0..height {
+        for x in 0..width {
+            let i = (row * width + x) * channels;
+            let pixel = if channels == 3 {
+                color(floats[i], floats[i + 1], floats[i + 2])
+            } else {
+                color(floats[i], floats[i], floats[i])
+            };
+            c.write_pixel(x, height - 1 - row, pixel);
+        }
+    }
+    Ok(c)
+}
+
+// the first n whitespace separated words of a PPM/PFM header (skipping
+// # comments) and where the pixel data starts - right after the single
+// whitespace character following the last word
+fn header_tokens(bytes: &[u8], n: usize) -> Result<(Vec<String>, usize)> {
+    let mut tokens = vec!();
+    let mut i = 0;
+    while tokens.len() < n {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i < bytes.len() && bytes[i] == b'#' {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+            continue;
+        }
+        let begin = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if begin == i {
+            return Err(bad_image("header is cut short"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[begin..i]).to_string());
+    }
+    Ok((tokens, (i + 1).min(bytes.len())))
+}
+
+fn parse_number(s: &str) -> Result<f64> {
+    s.parse::<f64>().map_err(|_| bad_image("expected a number"))
+}
+
+fn bad_image(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
 }
 
 // uncertain whether this should go into the equals module in
@@ -106,25 +345,26 @@ pub fn c_equals(a: &Vec<Vec<Color>>, b: &Vec<Vec<Color>>) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use crate::canvas::{Canvas, canvas};
+    use crate::canvas::{Canvas, canvas, canvas_from_ppm, canvas_from_pfm, ppm_to_canvas, pfm_to_canvas};
     use crate::color::color;
+    use crate::tonemap::linear_output;
     use std::fs::read_to_string;
 
     #[test]
     fn canvas_has_width_and_height(){
-        let c = Canvas { width: 10, height: 20, pixels: vec![vec![color( 0.0, 0.0, 0.0 )]] };
+        let c = Canvas { width: 10, height: 20, pixels: vec![vec![color( 0.0, 0.0, 0.0 )]], alpha: None };
         assert!( (c.width == 10) && (c.height == 20) );
     }
 
     #[test]
     fn canvas_1_1_has_1_pixel(){
-        let c = Canvas { width: 1, height: 1, pixels: vec![vec![color( 0.0, 0.0, 0.0 )]] };
+        let c = Canvas { width: 1, height: 1, pixels: vec![vec![color( 0.0, 0.0, 0.0 )]], alpha: None };
         assert!( (c.pixels.len() == 1) && (c.pixels[0].len() == 1) );
     }
 
     #[test]
     fn canvas_default_color_eq_0_0_0(){
-        let c = Canvas { width: 1, height: 1, pixels: vec![vec![color( 0.0, 0.0, 0.0 )]] };
+        let c = Canvas { width: 1, height: 1, pixels: vec![vec![color( 0.0, 0.0, 0.0 )]], alpha: None };
         assert!( c.pixels[0][0].equals( color( 0.0, 0.0, 0.0 ) ));
     }
 
@@ -133,7 +373,7 @@ mod tests {
         let c = canvas(1,1);
         assert!( c.equals( Canvas { width: 1, 
                                     height: 1, 
-                                    pixels: vec![vec![color( 0.0, 0.0, 0.0 )]] }));
+                                    pixels: vec![vec![color( 0.0, 0.0, 0.0 )]], alpha: None }));
     }
 
     #[test]
@@ -295,6 +535,130 @@ mod tests {
         assert_eq!('\n', last_byte as char);  
     }
 
+    #[test]
+    fn reading_a_plain_ppm(){
+        let text = "P3\n# a comment\n2 1\n255\n255 0 0  0 51 255\n";
+        let c = ppm_to_canvas(text.as_bytes()).unwrap();
+        assert_eq!(c.width, 2);
+        assert_eq!(c.height, 1);
+        assert!( c.pixel_at(0, 0).equals(color(1.0, 0.0, 0.0)) );
+        assert!( c.pixel_at(1, 0).equals(color(0.0, 0.2, 1.0)) );
+    }
+
+    #[test]
+    fn reading_a_binary_ppm(){
+        let mut bytes = b"P6 1 2 255\n".to_vec();
+        bytes.extend_from_slice(&[255, 0, 0, 0, 0, 255]);
+        let c = ppm_to_canvas(&bytes).unwrap();
+        assert!( c.pixel_at(0, 0).equals(color(1.0, 0.0, 0.0)) );
+        assert!( c.pixel_at(0, 1).equals(color(0.0, 0.0, 1.0)) );
+    }
+
+    #[test]
+    fn ppm_survives_a_round_trip(){
+        let mut c = canvas(3, 2);
+        c.write_pixel(2, 1, color(1.0, 1.0, 0.0));
+        let _ = c.to_ppm("round_trip.ppm");
+        let back = canvas_from_ppm("round_trip.ppm").unwrap();
+        let _ = std::fs::remove_file("round_trip.ppm");
+        assert_eq!(back.width, 3);
+        assert!( back.pixel_at(2, 1).equals(color(1.0, 1.0, 0.0)) );
+        assert!( back.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)) );
+    }
+
+    #[test]
+    fn reading_a_pfm(){
+        // little endian, bottom row first
+        let mut bytes = b"PF\n1 2\n-1.0\n".to_vec();
+        for v in [4.0f32, 0.0, 0.0, 0.0, 0.5, 0.25] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let c = pfm_to_canvas(&bytes).unwrap();
+        assert!( c.pixel_at(0, 1).equals(color(4.0, 0.0, 0.0)) );
+        assert!( c.pixel_at(0, 0).equals(color(0.0, 0.5, 0.25)) );
+
+        let mut bytes = b"Pf\n1 1\n1.0\n".to_vec();
+        bytes.extend_from_slice(&2.0f32.to_be_bytes());
+        let c = pfm_to_canvas(&bytes).unwrap();
+        assert!( c.pixel_at(0, 0).equals(color(2.0, 2.0, 2.0)) );
+    }
+
+    #[test]
+    fn pfm_survives_a_round_trip(){
+        let mut c = canvas(3, 2);
+        c.write_pixel(0, 0, color(12.5, -1.0, 0.25));
+        c.write_pixel(2, 1, color(0.0, 1e6, 3.0));
+        let _ = c.to_pfm("round_trip.pfm");
+        let back = canvas_from_pfm("round_trip.pfm").unwrap();
+        let _ = std::fs::remove_file("round_trip.pfm");
+        assert!( back.equals(c) );
+    }
+
+    #[test]
+    fn alpha_is_opaque_until_written(){
+        let mut c = canvas(2, 1);
+        assert!( c.alpha.is_none() );
+        assert!( c.alpha_at(1, 0) == 1.0 );
+        c.write_alpha(1, 0, 0.25);
+        assert!( c.alpha_at(0, 0) == 1.0 && c.alpha_at(1, 0) == 0.25 );
+    }
+
+    #[test]
+    fn canvases_differing_only_in_alpha_are_not_equal(){
+        let a = canvas(2, 1);
+        let mut opaque = canvas(2, 1);
+        opaque.write_alpha(1, 0, 1.0);
+        assert!( a.equals(opaque) );
+
+        let mut b = canvas(2, 1);
+        b.write_alpha(1, 0, 0.5);
+        assert!( !a.equals(b) );
+    }
+
+    #[test]
+    fn rgba_output_is_straight_alpha(){
+        let mut c = canvas(3, 1);
+        c.write_pixel(0, 0, color(0.25, 0.0, 0.0));
+        c.write_alpha(0, 0, 0.5);
+        c.write_alpha(1, 0, 0.0);
+        let bytes = c.to_rgba_bytes(&linear_output());
+        assert_eq!(bytes, vec![128, 0, 0, 128,   0, 0, 0, 0,   0, 0, 0, 255]);
+
+        let _ = c.to_pam("alpha.pam", &linear_output());
+        let pam = std::fs::read("alpha.pam").unwrap();
+        let _ = std::fs::remove_file("alpha.pam");
+        assert!( pam.starts_with(b"P7\nWIDTH 3\nHEIGHT 1\nDEPTH 4\n") );
+        assert!( pam.ends_with(&bytes) );
+
+        let _ = c.to_png("alpha.png", &linear_output());
+        let png = std::fs::read("alpha.png").unwrap();
+        let _ = std::fs::remove_file("alpha.png");
+        assert_eq!( &png[1..4], b"PNG" );
+    }
+
+    #[test]
+    fn ppm_through_an_output_pipeline(){
+        let mut c = canvas(2, 1);
+        c.write_pixel(0, 0, color(0.5, 0.0, 4.0));
+        let mut out = linear_output();
+        out.srgb = true;
+        let _ = c.to_ppm_with("srgb.ppm", &out);
+        let lines = read_lines("srgb.ppm");
+        let _ = std::fs::remove_file("srgb.ppm");
+        assert_eq!("188 0 255 0 0 0 ", lines[3]);
+
+        let bytes = c.to_bytes(&out);
+        assert_eq!(bytes, vec![188, 0, 255, 0, 0, 0]);
+        assert!(c.tone_mapped(&out).pixel_at(0, 0).equals(color(0.735357, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn bad_images_are_errors(){
+        assert!( ppm_to_canvas(b"P3\n2 2\n255\n1 2 3\n").is_err() );
+        assert!( ppm_to_canvas(b"P5\n1 1\n255\n0").is_err() );
+        assert!( pfm_to_canvas(b"PF\n1").is_err() );
+    }
+
     // leaving this as test helper function for now
     // will probably have utility elsewhere and be moved later
     fn read_lines(filename: &str) -> Vec<String> {
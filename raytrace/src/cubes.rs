@@ -83,6 +83,14 @@ impl Shape for Cube {
         self.supe.get_reference()
     }
     
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Cube"
+    }
+
     fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
         0
     }
@@ -110,6 +110,18 @@ impl Shape for Cylinder {
         self.supe.get_reference()
     }
     
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Cylinder"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        vec![self.minimum, self.maximum, if self.closed { 1.0 } else { 0.0 }]
+    }
+
     fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
         0
     }
@@ -0,0 +1,257 @@
+use crate::canvas::{Canvas, canvas};
+use crate::color::{Color, color};
+use crate::aovs::Passes;
+use crate::camera::Camera;
+use crate::world::World;
+use crate::pathtracer::PathTracer;
+
+// joint bilateral filter for cleaning up low-sample path traced renders.
+// Each pixel becomes a weighted average of its neighbours, where the
+// weights come mostly from the noise-free auxiliary passes - neighbours
+// with a different normal, depth or albedo (another object, the other
+// side of an edge) count for little, so edges stay sharp while flat
+// areas get smoothed.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Denoiser {
+    pub radius: usize,          // neighbourhood is (2 * radius + 1) square
+    pub sigma_spatial: f64,     // in pixels
+    pub sigma_color: f64,       // difference in the noisy image itself
+    pub sigma_normal: f64,      // 1 - cos of the angle between normals
+    pub sigma_depth: f64,       // relative difference in depth
+    pub sigma_albedo: f64,
+    // filter the lighting with the surface color divided out, then put it
+    // back, so textures aren't blurred along with the noise
+    pub demodulate: bool,
+}
+
+impl Denoiser {
+    // guides has to be the same size as noisy, e.g. from render_guides
+    // with the same camera - None if it isn't
+    pub fn denoise(&self, noisy: &Canvas, guides: &Passes) -> Option<Canvas> {
+        let (width, height) = (noisy.width, noisy.height);
+        for g in [&guides.normal, &guides.depth, &guides.albedo] {
+            if g.width != width || g.height != height {
+                return None;
+            }
+        }
+        let r = self.radius as i64;
+        // a zero sigma would make even the centre weight 0 / 0
+        let sigma = |s: f64| s.max(1e-6);
+        let (sigma_spatial, sigma_color) = (sigma(self.sigma_spatial), sigma(self.sigma_color));
+        let (sigma_normal, sigma_depth, sigma_albedo) =
+            (sigma(self.sigma_normal), sigma(self.sigma_depth), sigma(self.sigma_albedo));
+        let mut out = canvas(width, height);
+        out.alpha = noisy.alpha.clone();
+
+        let signal = |x: usize, y: usize| {
+            let c = noisy.pixel_at(x, y);
+            if self.demodulate { demodulate(c, guides.albedo.pixel_at(x, y)) } else { c }
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let centre = signal(x, y);
+                let n0 = guides.normal.pixel_at(x, y);
+                let d0 = guides.depth.pixel_at(x, y).r;
+                let a0 = guides.albedo.pixel_at(x, y);
+
+                let mut sum = color(0.0, 0.0, 0.0);
+                let mut total = 0.0;
+                for j in -r..=r {
+                    for i in -r..=r {
+                        let (sx, sy) = (x as i64 + i, y as i64 + j);
+                        if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+                            continue;
+                        }
+                        let (sx, sy) = (sx as usize, sy as usize);
+                        let c = signal(sx, sy);
+                        // a NaN or infinite firefly would spread to the
+                        // whole neighbourhood
+                        if !finite(c) {
+                            continue;
+                        }
+
+                        let spatial = ((i * i + j * j) as f64) / (2.0 * sigma_spatial.powi(2));
+                        let shade = if finite(centre) { distance2(c, centre) / (2.0 * sigma_color.powi(2)) } else { 0.0 };
+                        let normal = normal_difference(n0, guides.normal.pixel_at(sx, sy)) / sigma_normal;
+                        let depth = depth_difference(d0, guides.depth.pixel_at(sx, sy).r) / sigma_depth;
+                        let albedo = distance2(a0, guides.albedo.pixel_at(sx, sy)) / (2.0 * sigma_albedo.powi(2));
+
+                        let w = (-(spatial + shade + normal * normal + depth * depth + albedo)).exp();
+                        sum = sum + c * w;
+                        total += w;
+                    }
+                }
+
+                // the centre pixel has weight 1 unless it's the firefly
+                // itself, and then it's left alone if nothing else is near
+                if total <= 0.0 {
+                    out.write_pixel(x, y, noisy.pixel_at(x, y));
+                    continue;
+                }
+                let filtered = sum * (1.0 / total);
+                let result = if self.demodulate { remodulate(filtered, a0) } else { filtered };
+                out.write_pixel(x, y, result);
+            }
+        }
+        Some(out)
+    }
+
+    // path trace at whatever sample count pt says, then clean it up using
+    // guide passes from the same camera
+    pub fn render(&self, pt: &PathTracer, c: &Camera, w: &World) -> Canvas {
+        let noisy = pt.render(c, w);
+        // same camera, so the sizes always match
+        self.denoise(&noisy, &c.render_guides(w)).unwrap()
+    }
+}
+
+pub fn denoiser() -> Denoiser {
+    Denoiser {
+        radius: 5,
+        sigma_spatial: 3.0,
+        sigma_color: 0.5,
+        sigma_normal: 0.1,
+        sigma_depth: 0.05,
+        sigma_albedo: 0.1,
+        demodulate: true,
+    }
+}
+
+// misses have infinite depth, which only matches another miss
+fn depth_difference(a: f64, b: f64) -> f64 {
+    if a.is_infinite() || b.is_infinite() {
+        return if a == b { 0.0 } else { f64::INFINITY };
+    }
+    (a - b).abs() / a.abs().max(b.abs()).max(1e-6)
+}
+
+fn demodulate(c: Color, albedo: Color) -> Color {
+    color(c.r / albedo.r.max(0.01), c.g / albedo.g.max(0.01), c.b / albedo.b.max(0.01))
+}
+
+fn remodulate(c: Color, albedo: Color) -> Color {
+    color(c.r * albedo.r.max(0.01), c.g * albedo.g.max(0.01), c.b * albedo.b.max(0.01))
+}
+
+fn finite(c: Color) -> bool {
+    c.r.is_finite() && c.g.is_finite() && c.b.is_finite()
+}
+
+fn distance2(a: Color, b: Color) -> f64 {
+    (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2)
+}
+
+// normals are stored as colors in the normal pass, and are all zero
+// where the ray missed
+fn normal_difference(a: Color, b: Color) -> f64 {
+    let zero = color(0.0, 0.0, 0.0);
+    if a == zero || b == zero {
+        return if a == b { 0.0 } else { 1.0 };
+    }
+    (1.0 - (a.r * b.r + a.g * b.g + a.b * b.b)).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::denoise::denoiser;
+    use crate::aovs::{Passes, passes};
+    use crate::canvas::{Canvas, canvas};
+    use crate::color::color;
+    use crate::imagediff::difference;
+    use crate::sampling::rng;
+
+    // two flat surfaces side by side, at different depths and facing
+    // different ways, with noise sprinkled over a clean image
+    fn scene() -> (Canvas, Canvas, Passes) {
+        let (w, h) = (16, 8);
+        let mut clean = canvas(w, h);
+        let mut noisy = canvas(w, h);
+        let mut g = passes(w, h);
+        let mut r = rng(7);
+        for y in 0..h {
+            for x in 0..w {
+                let left = x < w / 2;
+                let c = if left { color(0.2, 0.2, 0.2) } else { color(0.8, 0.6, 0.4) };
+                let n = if left { color(0.0, 0.0, -1.0) } else { color(0.0, 1.0, 0.0) };
+                let d = if left { 3.0 } else { 6.0 };
+                clean.write_pixel(x, y, c);
+                noisy.write_pixel(x, y, c * (0.5 + r.next_f64()));
+                g.normal.write_pixel(x, y, n);
+                g.depth.write_pixel(x, y, color(d, d, d));
+                g.albedo.write_pixel(x, y, color(1.0, 1.0, 1.0));
+            }
+        }
+        (clean, noisy, g)
+    }
+
+    #[test]
+    fn denoising_gets_closer_to_the_clean_image(){
+        let (clean, noisy, g) = scene();
+        let before = difference(&noisy, &clean).unwrap().rmse;
+        let after = difference(&denoiser().denoise(&noisy, &g).unwrap(), &clean).unwrap().rmse;
+        assert!(after < before * 0.5);
+    }
+
+    #[test]
+    fn edges_in_the_guides_stay_sharp(){
+        let (clean, noisy, g) = scene();
+        let out = denoiser().denoise(&noisy, &g).unwrap();
+        // right beside the edge, nothing bleeds across from the other side
+        assert!((out.pixel_at(7, 4).r - clean.pixel_at(7, 4).r).abs() < 0.1);
+        assert!((out.pixel_at(8, 4).b - clean.pixel_at(8, 4).b).abs() < 0.15);
+    }
+
+    #[test]
+    fn textures_survive_demodulation(){
+        // a checker texture with no noise at all comes through untouched
+        let (w, h) = (8, 8);
+        let mut img = canvas(w, h);
+        let mut g = passes(w, h);
+        for y in 0..h {
+            for x in 0..w {
+                let a = if (x + y) % 2 == 0 { color(0.9, 0.9, 0.9) } else { color(0.1, 0.1, 0.1) };
+                img.write_pixel(x, y, a * 0.5);
+                g.albedo.write_pixel(x, y, a);
+                g.normal.write_pixel(x, y, color(0.0, 1.0, 0.0));
+                g.depth.write_pixel(x, y, color(2.0, 2.0, 2.0));
+            }
+        }
+        let out = denoiser().denoise(&img, &g).unwrap();
+        assert!(out.equals(img));
+    }
+
+    #[test]
+    fn zero_sigmas_leave_the_image_alone(){
+        // nothing matches anything but itself, rather than NaN everywhere
+        let (_, noisy, g) = scene();
+        let mut d = denoiser();
+        d.sigma_spatial = 0.0;
+        d.sigma_color = 0.0;
+        d.sigma_normal = 0.0;
+        d.sigma_depth = 0.0;
+        d.sigma_albedo = 0.0;
+        assert!(d.denoise(&noisy, &g).unwrap().equals(noisy));
+    }
+
+    #[test]
+    fn mismatched_guides_are_rejected(){
+        let (_, noisy, _) = scene();
+        assert!(denoiser().denoise(&noisy, &passes(4, 4)).is_none());
+    }
+
+    #[test]
+    fn fireflies_dont_spread(){
+        let (_, mut noisy, g) = scene();
+        noisy.write_pixel(3, 3, color(f64::NAN, 0.0, 0.0));
+        noisy.write_pixel(12, 4, color(f64::INFINITY, 1.0, 1.0));
+        let out = denoiser().denoise(&noisy, &g).unwrap();
+
+        // the bad pixels themselves get filled in from their neighbours
+        for row in &out.pixels {
+            for p in row {
+                assert!(p.r.is_finite() && p.g.is_finite() && p.b.is_finite());
+            }
+        }
+    }
+}
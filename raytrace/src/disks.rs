@@ -0,0 +1,206 @@
+use crate::shapes::{Base, Shape, shape, xz_area_scale};
+use crate::shape_index::ShapeIndex;
+use crate::intersections::{Intersection, intersection, intersections};
+use crate::tuple::{Tuple, point, vector};
+use crate::rays::Ray;
+use crate::materials::Material;
+use crate::matrix::Matrix;
+use crate::equals::EPSILON;
+use std::f64::consts::PI;
+
+// flat circle in the xz plane, centered on the origin
+pub struct Disk {
+    supe: Base,
+    pub radius: f64,
+}
+
+impl Shape for Disk {
+    fn get_transform(&self) -> &Matrix {
+        self.supe.get_transform()
+    }
+
+    fn set_transform(&mut self, t: Matrix){
+        self.supe.set_transform( t );
+    }
+
+    fn get_material(&self) -> &Material {
+        self.supe.get_material()
+    }
+
+    fn set_material(&mut self, m: Material){
+        self.supe.set_material( m );
+    }
+
+    fn local_normal_at(&self, _object_point: Tuple) -> Tuple {
+        vector(0.0, 1.0, 0.0)
+    }
+
+    fn intersect(&self, r: Ray) -> Vec<Intersection> {
+        let r2 = self.saved_ray(r);
+        if r2.direction.y.abs() < EPSILON {
+            return vec!();
+        }
+
+        let t = -r2.origin.y / r2.direction.y;
+        let p = r2.position(t);
+        if p.x.powf(2.0) + p.z.powf(2.0) > self.radius.powf(2.0) {
+            return vec!();
+        }
+
+        intersections(&[intersection(t, self.get_index())])
+    }
+
+    fn get_index(&self) -> usize {
+        self.supe.get_index()
+    }
+
+    fn set_index(&mut self, index: usize){
+        self.supe.set_index(index);
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.supe.get_parent()
+    }
+
+    fn set_parent(&mut self, parent_index: usize){
+        self.supe.set_parent(parent_index);
+    }
+
+    fn get_reference(&self) -> ShapeIndex {
+        self.supe.get_reference()
+    }
+
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Disk"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        vec![self.radius]
+    }
+
+    // u runs around the rim, v out from the center
+    fn local_uv_at(&self, object_point: Tuple) -> Option<(f64, f64)> {
+        let theta = object_point.z.atan2(object_point.x);
+        let u = (theta / (2.0 * PI)).rem_euclid(1.0);
+        let v = (object_point.x.powf(2.0) + object_point.z.powf(2.0)).sqrt() / self.radius;
+        Some((u, v))
+    }
+
+    fn local_sample(&self, u: f64, v: f64) -> Option<Tuple> {
+        // sqrt keeps the samples from bunching up at the center
+        let r = self.radius * v.sqrt();
+        let theta = 2.0 * PI * u;
+        Some(point(r * theta.cos(), 0.0, r * theta.sin()))
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius.powf(2.0) * xz_area_scale(self.get_transform())
+    }
+
+    fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
+        0
+    }
+
+    fn get_object(&self, _index: usize) -> Option<&Box<dyn Shape>> {
+        None
+    }
+
+    fn get_size(&self) -> usize {
+        0
+    }
+}
+
+pub fn disk() -> Disk {
+    Disk {
+        supe: shape(),
+        radius: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disks::disk;
+    use crate::shapes::Shape;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::transform::scaling;
+    use crate::equals::equals;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn normal_of_disk_is_constant(){
+        let d = disk();
+        assert_eq!(d.local_normal_at(point(0.0, 0.0, 0.0)), vector(0.0, 1.0, 0.0));
+        assert_eq!(d.local_normal_at(point(0.5, 0.0, -0.5)), vector(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn ray_hits_a_disk(){
+        let d = disk();
+        let r = ray(point(0.5, 1.0, 0.5), vector(0.0, -1.0, 0.0), 0);
+        let xs = d.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        assert!(equals(xs[0].t, 1.0));
+    }
+
+    #[test]
+    fn ray_misses_outside_radius(){
+        let d = disk();
+        let r = ray(point(0.8, 1.0, 0.8), vector(0.0, -1.0, 0.0), 0);
+        assert_eq!(d.intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn ray_parallel_to_disk(){
+        let d = disk();
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        assert_eq!(d.intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn larger_radius_catches_more_rays(){
+        let mut d = disk();
+        d.radius = 2.0;
+        let r = ray(point(0.8, -1.0, 0.8), vector(0.0, 1.0, 0.0), 0);
+        assert_eq!(d.intersect(r).len(), 1);
+    }
+
+    #[test]
+    fn uv_mapping_on_a_disk(){
+        let d = disk();
+        let (u, v) = d.local_uv_at(point(0.5, 0.0, 0.0)).unwrap();
+        assert!(equals(u, 0.0) && equals(v, 0.5));
+
+        let (u, v) = d.local_uv_at(point(0.0, 0.0, 1.0)).unwrap();
+        assert!(equals(u, 0.25) && equals(v, 1.0));
+
+        let (u, _) = d.local_uv_at(point(0.0, 0.0, -1.0)).unwrap();
+        assert!(equals(u, 0.75));
+    }
+
+    #[test]
+    fn samples_lie_on_the_disk(){
+        let d = disk();
+        for i in 0..10 {
+            for j in 0..10 {
+                let p = d.local_sample(i as f64 / 10.0, j as f64 / 10.0).unwrap();
+                assert!(equals(p.y, 0.0));
+                assert!(p.x.powf(2.0) + p.z.powf(2.0) <= 1.0 + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn area_of_a_scaled_disk(){
+        let mut d = disk();
+        assert!(equals(d.area(), PI));
+
+        d.set_transform(scaling(2.0, 1.0, 3.0));
+        assert!(equals(d.area(), 6.0 * PI));
+    }
+}
@@ -0,0 +1,508 @@
+use crate::canvas::Canvas;
+use crate::tonemap::{Output, output};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Write, Result, Error, ErrorKind};
+
+const MAX_CODE: u16 = 4095;
+
+// animated GIF settings. Every frame shares one palette, picked by median
+// cut from all of them, so colors don't flicker from frame to frame.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Gif {
+    pub delay: u16,             // between frames, in hundredths of a second
+    pub loops: Option<u16>,     // None plays once, Some(0) loops forever
+    pub colors: usize,          // palette size, 2 to 256
+    pub out: Output,            // how linear colors become 8 bits
+}
+
+impl Gif {
+    pub fn bytes(&self, frames: &[Canvas]) -> Result<Vec<u8>> {
+        let first = frames.first().ok_or_else(|| bad_frames("no frames"))?;
+        let (width, height) = (first.width, first.height);
+        if frames.iter().any(|f| f.width != width || f.height != height) {
+            return Err(bad_frames("frames must all be the same size"));
+        }
+        if width > 65535 || height > 65535 {
+            return Err(bad_frames("too big for a GIF"));
+        }
+
+        let rgb: Vec<Vec<u8>> = frames.iter().map(|f| f.to_bytes(&self.out)).collect();
+        let all: Vec<[u8; 3]> = rgb.iter()
+                                   .flat_map(|f| f.chunks_exact(3).map(|p| [p[0], p[1], p[2]]))
+                                   .collect();
+        let palette = median_cut(&all, self.colors.clamp(2, 256));
+
+        // table size is a power of two, 2^bits entries
+        let bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(1);
+        let mut out = b"GIF89a".to_vec();
+        out.extend_from_slice(&(width as u16).to_le_bytes());
+        out.extend_from_slice(&(height as u16).to_le_bytes());
+        out.push(0x80 | ((bits as u8 - 1) << 4) | (bits as u8 - 1));
+        out.extend_from_slice(&[0, 0]);
+        for i in 0..(1 << bits) {
+            out.extend_from_slice(palette.get(i).unwrap_or(&[0, 0, 0]));
+        }
+
+        if let Some(n) = self.loops {
+            out.extend_from_slice(&[0x21, 0xFF, 11]);
+            out.extend_from_slice(b"NETSCAPE2.0");
+            out.extend_from_slice(&[3, 1]);
+            out.extend_from_slice(&n.to_le_bytes());
+            out.push(0);
+        }
+
+        let mut lookup = HashMap::new();
+        for frame in &rgb {
+            // graphic control extension, for the delay
+            out.extend_from_slice(&[0x21, 0xF9, 4, 0]);
+            out.extend_from_slice(&self.delay.to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+
+            out.push(0x2C);
+            out.extend_from_slice(&[0, 0, 0, 0]);
+            out.extend_from_slice(&(width as u16).to_le_bytes());
+            out.extend_from_slice(&(height as u16).to_le_bytes());
+            out.push(0);
+
+            let indices: Vec<u8> = frame.chunks_exact(3)
+                .map(|p| *lookup.entry([p[0], p[1], p[2]])
+                                .or_insert_with(|| nearest(&palette, [p[0], p[1], p[2]])))
+                .collect();
+            let min_code_size = bits.max(2) as u8;
+            out.push(min_code_size);
+            for block in lzw_encode(&indices, min_code_size).chunks(255) {
+                out.push(block.len() as u8);
+                out.extend_from_slice(block);
+            }
+            out.push(0);
+        }
+
+        out.push(0x3B);
+        Ok(out)
+    }
+
+    pub fn write(&self, name: &str, frames: &[Canvas]) -> Result<File> {
+        let bytes = self.bytes(frames)?;
+        let mut f = File::create(name)?;
+        f.write_all(&bytes)?;
+        Ok(f)
+    }
+}
+
+// 25 frames a second, looping forever, full palette
+pub fn gif() -> Gif {
+    Gif { delay: 4, loops: Some(0), colors: 256, out: output() }
+}
+
+// YUV4MPEG2 - raw frames that ffmpeg and most encoders read directly
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Y4m {
+    pub fps: (u32, u32),    // numerator, denominator
+    pub subsample: bool,    // 4:2:0 chroma instead of full resolution 4:4:4
+    pub out: Output,
+}
+
+impl Y4m {
+    pub fn bytes(&self, frames: &[Canvas]) -> Result<Vec<u8>> {
+        let first = frames.first().ok_or_else(|| bad_frames("no frames"))?;
+        let (width, height) = (first.width, first.height);
+        if frames.iter().any(|f| f.width != width || f.height != height) {
+            return Err(bad_frames("frames must all be the same size"));
+        }
+
+        let chroma = if self.subsample { "420jpeg" } else { "444" };
+        let mut out = format!("YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C{}\n",
+                              width, height, self.fps.0, self.fps.1, chroma).into_bytes();
+
+        for frame in frames {
+            out.extend_from_slice(b"FRAME\n");
+            let rgb = frame.to_bytes(&self.out);
+            let yuv: Vec<(f64, f64, f64)> = rgb.chunks_exact(3).map(|p| to_yuv(p[0], p[1], p[2])).collect();
+
+            out.extend(yuv.iter().map(|p| p.0.round() as u8));
+            if self.subsample {
+                // average each 2x2 block, with odd edges using what's there
+                let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+                for plane in [1, 2] {
+                    for cy in 0..ch {
+                        for cx in 0..cw {
+                            let mut sum = 0.0;
+                            let mut n = 0.0;
+                            for y in (cy * 2)..(cy * 2 + 2).min(height) {
+                                for x in (cx * 2)..(cx * 2 + 2).min(width) {
+                                    let p = yuv[y * width + x];
+                                    sum += if plane == 1 { p.1 } else { p.2 };
+                                    n += 1.0;
+                                }
+                            }
+                            out.push((sum / n).round() as u8);
+                        }
+                    }
+                }
+            } else {
+                out.extend(yuv.iter().map(|p| p.1.round() as u8));
+                out.extend(yuv.iter().map(|p| p.2.round() as u8));
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn write(&self, name: &str, frames: &[Canvas]) -> Result<File> {
+        let bytes = self.bytes(frames)?;
+        let mut f = File::create(name)?;
+        f.write_all(&bytes)?;
+        Ok(f)
+    }
+}
+
+pub fn y4m(fps: u32) -> Y4m {
+    Y4m { fps: (fps, 1), subsample: false, out: output() }
+}
+
+// BT.601, limited range (16-235 luma), which is what players assume for
+// Y4M unless told otherwise
+pub fn to_yuv(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let u = (b - y) / 1.772;
+    let v = (r - y) / 1.402;
+    (16.0 + 219.0 * y, 128.0 + 224.0 * u, 128.0 + 224.0 * v)
+}
+
+// Heckbert's median cut - keep splitting the box with the widest spread
+// of colors at its median until there are n boxes, then average each one
+pub fn median_cut(pixels: &[[u8; 3]], n: usize) -> Vec<[u8; 3]> {
+    // count repeats first, which makes the boxes a lot smaller for renders
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    for p in pixels {
+        *counts.entry(*p).or_insert(0) += 1;
+    }
+    let mut unique: Vec<([u8; 3], usize)> = counts.into_iter().collect();
+    unique.sort();
+    if unique.is_empty() {
+        return vec![[0, 0, 0]];
+    }
+
+    let mut boxes = vec![unique];
+    while boxes.len() < n {
+        // the box whose widest channel is widest, if any can still split
+        let pick = boxes.iter().enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest_channel(b)))
+            .max_by_key(|(_, (_, range))| *range);
+        let (i, (channel, _)) = match pick {
+            Some(p) => p,
+            None    => break,
+        };
+
+        let mut b = boxes.swap_remove(i);
+        b.sort_by_key(|(c, _)| c[channel]);
+        let total: usize = b.iter().map(|(_, n)| n).sum();
+        let mut seen = 0;
+        let mut split = 1;
+        for (k, (_, n)) in b.iter().enumerate() {
+            seen += n;
+            if seen * 2 >= total {
+                split = (k + 1).clamp(1, b.len() - 1);
+                break;
+            }
+        }
+        let rest = b.split_off(split);
+        boxes.push(b);
+        boxes.push(rest);
+    }
+
+    boxes.iter().map(|b| {
+        let total: usize = b.iter().map(|(_, n)| n).sum();
+        let mut avg = [0usize; 3];
+        for (c, n) in b {
+            for k in 0..3 {
+                avg[k] += c[k] as usize * n;
+            }
+        }
+        [((avg[0] + total / 2) / total) as u8,
+         ((avg[1] + total / 2) / total) as u8,
+         ((avg[2] + total / 2) / total) as u8]
+    }).collect()
+}
+
+fn widest_channel(b: &[([u8; 3], usize)]) -> (usize, u8) {
+    let mut best = (0, 0);
+    for k in 0..3 {
+        let lo = b.iter().map(|(c, _)| c[k]).min().unwrap_or(0);
+        let hi = b.iter().map(|(c, _)| c[k]).max().unwrap_or(0);
+        if hi - lo > best.1 {
+            best = (k, hi - lo);
+        }
+    }
+    best
+}
+
+pub fn nearest(palette: &[[u8; 3]], p: [u8; 3]) -> u8 {
+    let distance = |c: &[u8; 3]| {
+        (0..3).map(|k| (c[k] as i32 - p[k] as i32).pow(2)).sum::<i32>()
+    };
+    palette.iter().enumerate()
+           .min_by_key(|(_, c)| distance(c))
+           .map_or(0, |(i, _)| i as u8)
+}
+
+// GIF flavoured LZW - codes are packed least significant bit first and
+// grow a bit wider each time the table fills a power of two
+pub fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut out = BitWriter { bytes: vec!(), buffer: 0, count: 0 };
+    let mut width = min_code_size as u32 + 1;
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+
+    out.write(clear, width);
+    let mut current: Option<u16> = None;
+    for &k in indices {
+        let prefix = match current {
+            Some(c) => c,
+            None    => { current = Some(k as u16); continue; },
+        };
+        if let Some(&code) = table.get(&(prefix, k)) {
+            current = Some(code);
+            continue;
+        }
+
+        out.write(prefix, width);
+        if next <= MAX_CODE {
+            table.insert((prefix, k), next);
+            next += 1;
+            // the decoder widens one code later than it could, so match it
+            if next > (1 << width) && width < 12 {
+                width += 1;
+            }
+        } else {
+            out.write(clear, width);
+            table.clear();
+            next = end + 1;
+            width = min_code_size as u32 + 1;
+        }
+        current = Some(k as u16);
+    }
+
+    if let Some(c) = current {
+        out.write(c, width);
+    }
+    out.write(end, width);
+    out.finish()
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += width;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn bad_frames(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gif::{gif, y4m, median_cut, nearest, lzw_encode, to_yuv};
+    use crate::canvas::{Canvas, canvas};
+    use crate::color::color;
+    use crate::tonemap::linear_output;
+    use crate::animation::{animation, turntable};
+    use crate::world::default_world;
+    use crate::camera::camera;
+    use crate::tuple::point;
+    use std::f64::consts::PI;
+
+    // straightforward decoder to check the encoder against
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let mut table: Vec<Vec<u8>> = vec!();
+        let mut width = min_code_size as usize + 1;
+        let mut out = vec!();
+        let mut prev: Option<Vec<u8>> = None;
+        let (mut buffer, mut count, mut pos) = (0u32, 0, 0);
+        loop {
+            while count < width {
+                buffer |= (data[pos] as u32) << count;
+                pos += 1;
+                count += 8;
+            }
+            let code = (buffer & ((1 << width) - 1)) as usize;
+            buffer >>= width;
+            count -= width;
+
+            if code == clear {
+                table = (0..clear).map(|i| vec![i as u8]).collect();
+                table.push(vec!());
+                table.push(vec!());
+                width = min_code_size as usize + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = if code < table.len() {
+                table[code].clone()
+            } else {
+                let p = prev.clone().unwrap();
+                let mut e = p.clone();
+                e.push(p[0]);
+                e
+            };
+            out.extend_from_slice(&entry);
+            if let Some(p) = prev {
+                let mut e = p.clone();
+                e.push(entry[0]);
+                table.push(e);
+                if table.len() == 1 << width && width < 12 {
+                    width += 1;
+                }
+            }
+            prev = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trips(){
+        let mut data = vec!();
+        for i in 0..20000u32 {
+            data.push(((i * 7 + i / 13) % 5) as u8);
+        }
+        for (input, size) in [(vec![0u8], 2u8), (vec![1, 1, 1, 1, 1, 1, 1], 2), (data.clone(), 3)] {
+            assert_eq!(lzw_decode(&lzw_encode(&input, size), size), input);
+        }
+
+        // enough variety to fill the table and force a clear
+        let noisy: Vec<u8> = (0..60000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&noisy, 8), 8), noisy);
+    }
+
+    #[test]
+    fn median_cut_finds_the_clusters(){
+        let mut pixels = vec!();
+        for i in 0..10u8 {
+            pixels.push([250 - i, 0, 0]);
+            pixels.push([0, 0, 200 + i]);
+        }
+        let mut palette = median_cut(&pixels, 2);
+        palette.sort();
+        assert_eq!(palette, vec![[0, 0, 205], [246, 0, 0]]);
+        assert_eq!(nearest(&palette, [0, 10, 180]), 0);
+
+        // never more entries than there are colors
+        assert_eq!(median_cut(&[[1, 2, 3], [1, 2, 3]], 16), vec![[1, 2, 3]]);
+    }
+
+    fn frames() -> Vec<Canvas> {
+        (0..3).map(|i| {
+            let mut c = canvas(5, 3);
+            c.write_pixel(i, 1, color(1.0, 0.5, 0.0));
+            c
+        }).collect()
+    }
+
+    #[test]
+    fn gif_layout(){
+        let mut g = gif();
+        g.out = linear_output();
+        g.delay = 10;
+        g.loops = Some(3);
+        let bytes = g.bytes(&frames()).unwrap();
+
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(&bytes[6..10], &[5, 0, 3, 0]);
+        // two colors, so a two entry global table
+        assert_eq!(bytes[10], 0x80);
+        let ext = bytes.windows(11).position(|w| w == b"NETSCAPE2.0").unwrap();
+        assert_eq!(&bytes[ext + 13..ext + 15], &[3, 0]);
+        assert_eq!(bytes.windows(4).filter(|w| *w == [0x21, 0xF9, 4, 0]).count(), 3);
+        assert_eq!(*bytes.last().unwrap(), 0x3B);
+
+        g.loops = None;
+        let once = g.bytes(&frames()).unwrap();
+        assert!(!once.windows(11).any(|w| w == b"NETSCAPE2.0"));
+    }
+
+    #[test]
+    fn frames_must_match(){
+        assert!(gif().bytes(&[]).is_err());
+        assert!(gif().bytes(&[canvas(2, 2), canvas(3, 2)]).is_err());
+        assert!(y4m(24).bytes(&[canvas(2, 2), canvas(2, 3)]).is_err());
+    }
+
+    #[test]
+    fn y4m_layout(){
+        let mut v = y4m(30);
+        v.out = linear_output();
+        let bytes = v.bytes(&frames()).unwrap();
+        let header = b"YUV4MPEG2 W5 H3 F30:1 Ip A1:1 C444\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(bytes.len(), header.len() + 3 * (6 + 5 * 3 * 3));
+
+        v.subsample = true;
+        let bytes = v.bytes(&frames()).unwrap();
+        let header = b"YUV4MPEG2 W5 H3 F30:1 Ip A1:1 C420jpeg\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(bytes.len(), header.len() + 3 * (6 + 15 + 2 * 3 * 2));
+    }
+
+    #[test]
+    fn an_animation_to_a_gif(){
+        let mut w = default_world();
+        let mut c = camera(8, 6, PI / 2.0);
+        let mut a = animation(10.0);
+        a.camera = Some(turntable(point(0.0, 0.0, 0.0), 5.0, 1.0, 0.4));
+        let frames = a.render_frames(&mut w, &mut c, 0, 3);
+        assert_eq!(frames.len(), 4);
+
+        let bytes = gif().bytes(&frames).unwrap();
+        let gce = bytes.windows(4).position(|w| w == [0x21, 0xF9, 4, 0]).unwrap();
+        assert_eq!(bytes[gce + 8], 0x2C);
+
+        // the first frame's data sub-blocks decode to one index per pixel
+        let mut pos = gce + 18;
+        let min_code_size = bytes[pos];
+        pos += 1;
+        let mut data = vec!();
+        while bytes[pos] != 0 {
+            let n = bytes[pos] as usize;
+            data.extend_from_slice(&bytes[pos + 1..pos + 1 + n]);
+            pos += n + 1;
+        }
+        assert_eq!(lzw_decode(&data, min_code_size).len(), 8 * 6);
+    }
+
+    #[test]
+    fn yuv_conversion(){
+        let (y, u, v) = to_yuv(0, 0, 0);
+        assert_eq!((y.round(), u.round(), v.round()), (16.0, 128.0, 128.0));
+        let (y, u, v) = to_yuv(255, 255, 255);
+        assert_eq!((y.round(), u.round(), v.round()), (235.0, 128.0, 128.0));
+        let (_, _, v) = to_yuv(255, 0, 0);
+        assert_eq!(v.round(), 240.0);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::world::{World, world, default_world};
+use crate::camera::{Camera, camera};
+use crate::canvas::{Canvas, canvas, canvas_from_pfm};
+use crate::color::color;
+use crate::tuple::{point, vector};
+use crate::transform::{view_transform, translation, scaling, rotation_x};
+use crate::lights::point_light;
+use crate::spheres::sphere;
+use crate::planes::plane;
+use crate::cubes::cube;
+use crate::shapes::Shape;
+use crate::materials::material;
+use crate::checkers::checker_pattern;
+use crate::microfacet::gold;
+use crate::bumps::noise_bump;
+use crate::backgrounds::{solid_background, gradient_background};
+use crate::media::fog;
+use crate::imagediff::difference;
+use std::f64::consts::PI;
+
+// whole image regression tests. Each scene is rendered small and checked
+// against a stored PFM in golden/ - run the tests with BLESS=1 to write
+// new references after an intended change.
+pub struct Scene {
+    pub name: &'static str,
+    pub build: fn() -> (World, Camera),
+}
+
+pub struct Comparison {
+    pub rmse: f64,
+    pub psnr: f64,          // in dB, infinite for identical images
+    pub bad_pixels: usize,  // how many have a channel off by more than the tolerance
+    pub diff: Canvas,       // white where a pixel failed, otherwise the difference
+}
+
+impl Comparison {
+    pub fn passed(&self) -> bool {
+        self.bad_pixels == 0
+    }
+}
+
+pub fn scenes() -> Vec<Scene> {
+    vec![
+        Scene { name: "default_world", build: default_scene },
+        Scene { name: "mirror_and_glass", build: glass_scene },
+        Scene { name: "patterns", build: pattern_scene },
+        Scene { name: "microfacet_and_bumps", build: microfacet_scene },
+        Scene { name: "fog", build: fog_scene },
+    ]
+}
+
+pub fn render_scene(s: &Scene) -> Canvas {
+    let (w, c) = (s.build)();
+    c.render(w)
+}
+
+pub fn compare(actual: &Canvas, reference: &Canvas, tolerance: f64) -> Comparison {
+    let overall = match difference(actual, reference) {
+        Some(d) => d,
+        None    => return Comparison { rmse: f64::INFINITY, psnr: 0.0,
+                                       bad_pixels: actual.width * actual.height,
+                                       diff: canvas(actual.width, actual.height) },
+    };
+
+    let mut diff = canvas(actual.width, actual.height);
+    let mut bad_pixels = 0;
+    for y in 0..actual.height {
+        for x in 0..actual.width {
+            let a = actual.pixel_at(x, y);
+            let b = reference.pixel_at(x, y);
+            let d = color((a.r - b.r).abs(), (a.g - b.g).abs(), (a.b - b.b).abs());
+
+            // NaN never compares as within tolerance
+            if !(d.r <= tolerance && d.g <= tolerance && d.b <= tolerance) {
+                bad_pixels += 1;
+                diff.write_pixel(x, y, color(1.0, 1.0, 1.0));
+            } else {
+                diff.write_pixel(x, y, d);
+            }
+        }
+    }
+
+    Comparison { rmse: overall.rmse, psnr: overall.psnr, bad_pixels, diff }
+}
+
+// renders one scene and checks it against dir/<name>.pfm, or overwrites
+// the reference when blessing. Failures leave dir/<name>_diff.ppm behind.
+pub fn check(s: &Scene, dir: &str, tolerance: f64, bless: bool) -> Result<Comparison, String> {
+    let image = render_scene(s);
+    let path = format!("{}/{}.pfm", dir, s.name);
+    if bless {
+        image.to_pfm(&path).map_err(|e| format!("{}: {}", path, e))?;
+        return Ok(compare(&image, &image, tolerance));
+    }
+
+    let reference = canvas_from_pfm(&path)
+        .map_err(|e| format!("{}: {} (run with BLESS=1 to create it)", path, e))?;
+    let result = compare(&image, &reference, tolerance);
+    if !result.passed() {
+        let diff_path = format!("{}/{}_diff.ppm", dir, s.name);
+        let saved = match result.diff.to_ppm(&diff_path) {
+            Ok(_)  => format!("diff in {}", diff_path),
+            Err(e) => format!("couldn't write {}: {}", diff_path, e),
+        };
+        return Err(format!("{}: {} pixels differ, RMSE {:.6}, PSNR {:.2} dB, {}",
+                           s.name, result.bad_pixels, result.rmse, result.psnr, saved));
+    }
+    Ok(result)
+}
+
+fn small_camera(from: (f64, f64, f64), to: (f64, f64, f64)) -> Camera {
+    let mut c = camera(32, 24, PI / 3.0);
+    c.transform = view_transform(point(from.0, from.1, from.2),
+                                 point(to.0, to.1, to.2),
+                                 vector(0.0, 1.0, 0.0));
+    c
+}
+
+fn floor(w: &mut World) {
+    let mut f = plane();
+    let mut m = material();
+    m.color = color(0.8, 0.8, 0.8);
+    m.specular = 0.0;
+    f.set_material(m);
+    f.set_transform(translation(0.0, -1.0, 0.0));
+    w.add_object(Box::new(f));
+}
+
+// the spheres don't reach the edges, so give the misses a color - an
+// all black border would hide a renderer that skips the last row or column
+fn default_scene() -> (World, Camera) {
+    let mut w = default_world();
+    w.background = Some(solid_background(color(0.2, 0.2, 0.2)));
+    (w, small_camera((0.0, 0.0, -5.0), (0.0, 0.0, 0.0)))
+}
+
+fn glass_scene() -> (World, Camera) {
+    let mut w = world();
+    w.light = Some(point_light(point(-10.0, 10.0, -10.0), color(1.0, 1.0, 1.0)));
+    w.background = Some(gradient_background(color(0.1, 0.1, 0.2), color(0.6, 0.7, 1.0)));
+    floor(&mut w);
+
+    let mut mirror = sphere();
+    let mut m = material();
+    m.color = color(0.1, 0.1, 0.1);
+    m.reflective = 0.9;
+    mirror.set_material(m);
+    mirror.set_transform(translation(-1.2, 0.0, 0.0));
+    w.add_object(Box::new(mirror));
+
+    let mut glass = sphere();
+    let mut m = material();
+    m.color = color(0.0, 0.0, 0.0);
+    m.diffuse = 0.1;
+    m.reflective = 0.9;
+    m.transparency = 0.9;
+    m.refractive_index = 1.5;
+    glass.set_material(m);
+    glass.set_transform(translation(1.2, 0.0, 0.0));
+    w.add_object(Box::new(glass));
+
+    (w, small_camera((0.0, 1.0, -5.0), (0.0, 0.0, 0.0)))
+}
+
+fn pattern_scene() -> (World, Camera) {
+    let mut w = world();
+    w.light = Some(point_light(point(-5.0, 8.0, -8.0), color(1.0, 1.0, 1.0)));
+    let checks = w.add_pattern(Box::new(checker_pattern(color(1.0, 1.0, 1.0), color(0.2, 0.3, 0.8))));
+
+    let mut f = plane();
+    let mut m = material();
+    m.pattern = Some(checks);
+    f.set_material(m);
+    f.set_transform(translation(0.0, -1.0, 0.0));
+    w.add_object(Box::new(f));
+
+    let mut c = cube();
+    let mut m = material();
+    m.color = color(0.9, 0.4, 0.1);
+    c.set_material(m);
+    c.set_transform(rotation_x(PI / 5.0).mult(&scaling(0.7, 0.7, 0.7)));
+    w.add_object(Box::new(c));
+
+    (w, small_camera((2.0, 2.0, -5.0), (0.0, 0.0, 0.0)))
+}
+
+fn microfacet_scene() -> (World, Camera) {
+    let mut w = world();
+    w.light = Some(point_light(point(-5.0, 5.0, -5.0), color(1.0, 1.0, 1.0)));
+    floor(&mut w);
+
+    let mut metal = sphere();
+    let mut m = material();
+    m.microfacet = Some(gold(0.3));
+    metal.set_material(m);
+    metal.set_transform(translation(-1.1, 0.0, 0.0));
+    w.add_object(Box::new(metal));
+
+    let mut bumpy = sphere();
+    let mut m = material();
+    m.color = color(0.3, 0.8, 0.4);
+    m.bump = Some(noise_bump(0.2, 0.05));
+    bumpy.set_material(m);
+    bumpy.set_transform(translation(1.1, 0.0, 0.0));
+    w.add_object(Box::new(bumpy));
+
+    (w, small_camera((0.0, 1.0, -5.0), (0.0, 0.0, 0.0)))
+}
+
+fn fog_scene() -> (World, Camera) {
+    let mut w = world();
+    w.light = Some(point_light(point(0.0, 6.0, 0.0), color(1.0, 1.0, 1.0)));
+    w.fog = Some(fog(0.05));
+    floor(&mut w);
+
+    let mut s = sphere();
+    s.set_transform(translation(0.0, 1.5, 0.0).mult(&scaling(1.5, 0.3, 1.5)));
+    w.add_object(Box::new(s));
+
+    (w, small_camera((0.0, 1.0, -6.0), (0.0, 0.0, 0.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golden::{scenes, check, compare};
+    use crate::canvas::{canvas, canvas_from_pfm};
+    use crate::color::color;
+
+    #[test]
+    fn comparing_images(){
+        let mut a = canvas(2, 2);
+        let b = canvas(2, 2);
+        let same = compare(&a, &b, 0.01);
+        assert!(same.passed());
+        assert!(same.psnr.is_infinite());
+
+        a.write_pixel(1, 0, color(0.5, 0.0, 0.0));
+        let c = compare(&a, &b, 0.01);
+        assert_eq!(c.bad_pixels, 1);
+        assert!(c.diff.pixel_at(1, 0).equals(color(1.0, 1.0, 1.0)));
+        assert!((c.rmse - (0.25f64 / 12.0).sqrt()).abs() < 1e-9);
+        assert!(c.psnr > 0.0);
+
+        assert!(!compare(&a, &canvas(3, 2), 0.01).passed());
+    }
+
+    #[test]
+    fn golden_images(){
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+        let bless = std::env::var("BLESS").is_ok();
+        let failures: Vec<String> = scenes().iter()
+            .filter_map(|s| check(s, dir, 1.0e-3, bless).err())
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn golden_references_cover_the_whole_image(){
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+        let black = color(0.0, 0.0, 0.0);
+        for s in scenes() {
+            let image = canvas_from_pfm(&format!("{}/{}.pfm", dir, s.name)).unwrap();
+            let (w, h) = (image.width, image.height);
+            assert!((0..w).any(|x| image.pixel_at(x, h - 1) != black), "{}: last row is black", s.name);
+            assert!((0..h).any(|y| image.pixel_at(w - 1, y) != black), "{}: last column is black", s.name);
+        }
+    }
+}
@@ -3,6 +3,7 @@ use crate::shape_index::ShapeIndex;
 use crate::intersections::Intersection;
 use crate::tuple::{Tuple, vector};
 use crate::rays::Ray;
+use crate::stats::count_intersection;
 use crate::materials::Material;
 use crate::matrix::Matrix;
 
@@ -37,6 +38,7 @@ impl Shape for Group {
         let mut result = vec!();
 
         for s in &self.shapes {
+            count_intersection(s.get_kind());
             let mut xs = s.intersect(r2);
             if xs.len() > 0 {
                 result.append(&mut xs);
@@ -70,6 +72,14 @@ impl Shape for Group {
         self.supe.get_reference()
     }
 
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Group"
+    }
+
     fn add_child(&mut self, mut child: Box<dyn Shape>) -> usize {
         let current = self.shapes.len();
         child.set_index(current as usize);
@@ -0,0 +1,532 @@
+use crate::shapes::{Shape, Base, shape};
+use crate::shape_index::ShapeIndex;
+use crate::intersections::{Intersection, intersection, intersections};
+use crate::tuple::{Tuple, vector};
+use crate::rays::Ray;
+use crate::materials::Material;
+use crate::matrix::Matrix;
+use crate::canvas::Canvas;
+use crate::numeric::solve_quadratic;
+use crate::equals::EPSILON;
+
+// how each grid cell is filled in between its four corner heights
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CellSurface {
+    Triangles,   // split along the diagonal into two flat triangles
+    Bilinear,    // single curved patch through all four corners
+}
+
+// a grid of heights spread over the square from (-1, -1) to (1, 1) in
+// the xz plane - column i sits at x = -1 + i * dx, row j at z = -1 + j * dz,
+// and the surface height is scale * heights[j * cols + i]
+pub struct Heightfield {
+    supe: Base,
+    heights: Vec<f64>,
+    // lowest and highest raw heights, worked out once for the bounding box
+    min_height: f64,
+    max_height: f64,
+    // hash of the grid, so equality can tell terrains apart without
+    // params carrying every height
+    digest: u64,
+    // fixed once built, since heights has to stay cols * rows long
+    cols: usize,
+    rows: usize,
+    pub scale: f64,
+    pub surface: CellSurface,
+}
+
+impl Shape for Heightfield {
+    fn get_transform(&self) -> &Matrix {
+        self.supe.get_transform()
+    }
+
+    fn set_transform(&mut self, t: Matrix){
+        self.supe.set_transform( t );
+    }
+
+    fn get_material(&self) -> &Material {
+        self.supe.get_material()
+    }
+
+    fn set_material(&mut self, m: Material){
+        self.supe.set_material( m );
+    }
+
+    // vertex normals blended across the cell, so the facets don't show
+    fn local_normal_at(&self, object_point: Tuple) -> Tuple {
+        let (i, j, u, v) = self.locate(object_point.x, object_point.z);
+
+        let n00 = self.vertex_normal(i, j);
+        let n10 = self.vertex_normal(i + 1, j);
+        let n01 = self.vertex_normal(i, j + 1);
+        let n11 = self.vertex_normal(i + 1, j + 1);
+
+        n00 * ((1.0 - u) * (1.0 - v)) +
+        n10 * (u * (1.0 - v)) +
+        n01 * ((1.0 - u) * v) +
+        n11 * (u * v)
+    }
+
+    // clip to the bounding box, then walk the cells the ray passes
+    // over (2D DDA) and stop at the first one that it actually hits
+    fn intersect(&self, r: Ray) -> Vec<Intersection> {
+        let r2 = self.saved_ray(r);
+        let (tmin, tmax) = self.check_bounds(r2);
+        if tmin > tmax || tmax < 0.0 {
+            return vec!();
+        }
+
+        let dx = self.dx();
+        let dz = self.dz();
+        let start = tmin.max(0.0);
+        let p = r2.position(start);
+        let (mut i, mut j, _, _) = self.locate(p.x, p.z);
+
+        let d = r2.direction;
+        let (step_i, mut next_x, delta_x) = Self::setup_axis(r2.origin.x, d.x, i, dx);
+        let (step_j, mut next_z, delta_z) = Self::setup_axis(r2.origin.z, d.z, j, dz);
+
+        let mut t_in = start;
+        loop {
+            let t_out = next_x.min(next_z).min(tmax);
+
+            if let Some(t) = self.intersect_cell(r2, i, j, t_in, t_out) {
+                return intersections(&[intersection(t, self.get_index())]);
+            }
+
+            if t_out >= tmax {
+                break;
+            }
+            if next_x < next_z {
+                if (step_i < 0 && i == 0) || (step_i > 0 && i + 2 >= self.cols) {
+                    break;
+                }
+                i = (i as i64 + step_i) as usize;
+                next_x += delta_x;
+            } else {
+                if (step_j < 0 && j == 0) || (step_j > 0 && j + 2 >= self.rows) {
+                    break;
+                }
+                j = (j as i64 + step_j) as usize;
+                next_z += delta_z;
+            }
+            t_in = t_out;
+        }
+
+        vec!()
+    }
+
+    fn get_index(&self) -> usize {
+        self.supe.get_index()
+    }
+
+    fn set_index(&mut self, index: usize){
+        self.supe.set_index(index);
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.supe.get_parent()
+    }
+
+    fn set_parent(&mut self, parent_index: usize){
+        self.supe.set_parent(parent_index);
+    }
+
+    fn get_reference(&self) -> ShapeIndex {
+        self.supe.get_reference()
+    }
+
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Heightfield"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        vec![self.cols as f64,
+             self.rows as f64,
+             self.scale,
+             if self.surface == CellSurface::Bilinear { 1.0 } else { 0.0 },
+             self.min_height,
+             self.max_height,
+             // two halves so each is exact as an f64
+             (self.digest >> 32) as f64,
+             (self.digest & 0xFFFF_FFFF) as f64]
+    }
+
+    fn local_uv_at(&self, object_point: Tuple) -> Option<(f64, f64)> {
+        Some(((object_point.x + 1.0) / 2.0, (object_point.z + 1.0) / 2.0))
+    }
+
+    fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
+        0
+    }
+
+    fn get_object(&self, _index: usize) -> Option<&Box<dyn Shape>> {
+        None
+    }
+
+    fn get_size(&self) -> usize {
+        0
+    }
+}
+
+impl Heightfield {
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    // raw grid value, before scale is applied
+    pub fn height_at(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.cols + i]
+    }
+
+    fn dx(&self) -> f64 {
+        2.0 / (self.cols - 1) as f64
+    }
+
+    fn dz(&self) -> f64 {
+        2.0 / (self.rows - 1) as f64
+    }
+
+    // cell containing (x, z), plus the position within it from 0 to 1
+    fn locate(&self, x: f64, z: f64) -> (usize, usize, f64, f64) {
+        let fx = ((x + 1.0) / self.dx()).clamp(0.0, (self.cols - 1) as f64);
+        let fz = ((z + 1.0) / self.dz()).clamp(0.0, (self.rows - 1) as f64);
+        let i = (fx.floor() as usize).min(self.cols - 2);
+        let j = (fz.floor() as usize).min(self.rows - 2);
+        (i, j, fx - i as f64, fz - j as f64)
+    }
+
+    fn vertex_normal(&self, i: usize, j: usize) -> Tuple {
+        let il = i.saturating_sub(1);
+        let ir = (i + 1).min(self.cols - 1);
+        let jl = j.saturating_sub(1);
+        let jr = (j + 1).min(self.rows - 1);
+
+        let slope_x = (self.height_at(ir, j) - self.height_at(il, j)) * self.scale /
+                      ((ir - il) as f64 * self.dx());
+        let slope_z = (self.height_at(i, jr) - self.height_at(i, jl)) * self.scale /
+                      ((jr - jl) as f64 * self.dz());
+
+        vector(-slope_x, 1.0, -slope_z).normal()
+    }
+
+    // returns (step direction, t at the first cell boundary, t per cell)
+    fn setup_axis(o: f64, d: f64, index: usize, size: f64) -> (i64, f64, f64) {
+        if d.abs() < EPSILON {
+            return (0, f64::INFINITY, f64::INFINITY);
+        }
+        let lower = -1.0 + index as f64 * size;
+        if d > 0.0 {
+            (1, (lower + size - o) / d, size / d)
+        } else {
+            (-1, (lower - o) / d, -size / d)
+        }
+    }
+
+    fn intersect_cell(&self, r: Ray, i: usize, j: usize, t_in: f64, t_out: f64) -> Option<f64> {
+        let dx = self.dx();
+        let dz = self.dz();
+        let s = self.scale;
+
+        let h00 = self.height_at(i, j);
+        let h10 = self.height_at(i + 1, j);
+        let h01 = self.height_at(i, j + 1);
+        let h11 = self.height_at(i + 1, j + 1);
+
+        // cell-local u, v as linear functions of t
+        let u0 = (r.origin.x - (-1.0 + i as f64 * dx)) / dx;
+        let v0 = (r.origin.z - (-1.0 + j as f64 * dz)) / dz;
+        let du = r.direction.x / dx;
+        let dv = r.direction.z / dz;
+
+        let in_cell = |t: f64| t >= t_in - EPSILON && t <= t_out + EPSILON;
+        let mut best: Option<f64> = None;
+        let mut consider = |t: f64| {
+            if in_cell(t) && t >= 0.0 && best.is_none_or(|b| t < b) {
+                best = Some(t);
+            }
+        };
+
+        match self.surface {
+            CellSurface::Bilinear => {
+                // h = a + b u + c v + d u v, set equal to the ray's y
+                let a = h00;
+                let b = h10 - h00;
+                let c = h01 - h00;
+                let d = h00 - h10 - h01 + h11;
+
+                let qa = -s * d * du * dv;
+                let qb = r.direction.y - s * (b * du + c * dv + d * (u0 * dv + v0 * du));
+                let qc = r.origin.y - s * (a + b * u0 + c * v0 + d * u0 * v0);
+                for t in solve_quadratic(qa, qb, qc) {
+                    consider(t);
+                }
+            },
+            CellSurface::Triangles => {
+                // lower triangle (u + v <= 1) leans on h00, upper on h11
+                let tris = [(h00, h10 - h00, h01 - h00, 0.0, 0.0, true),
+                            (h11, h01 - h11, h10 - h11, 1.0, 1.0, false)];
+                for (base, bu, bv, su, sv, lower) in tris {
+                    // h = base + bu * |u - su| + bv * |v - sv|, written out
+                    // for the two cases so it stays linear in t
+                    let (ku, kv) = if lower { (1.0, 1.0) } else { (-1.0, -1.0) };
+                    let denom = r.direction.y - s * (bu * ku * du + bv * kv * dv);
+                    if denom.abs() < EPSILON {
+                        continue;
+                    }
+                    let num = s * (base + bu * ku * (u0 - su) + bv * kv * (v0 - sv)) - r.origin.y;
+                    let t = num / denom;
+                    let sum = u0 + t * du + v0 + t * dv;
+                    if (lower && sum <= 1.0 + EPSILON) || (!lower && sum >= 1.0 - EPSILON) {
+                        consider(t);
+                    }
+                }
+            },
+        }
+
+        best
+    }
+
+    fn check_bounds(&self, r: Ray) -> (f64, f64) {
+        let lo = self.min_height * self.scale;
+        let hi = self.max_height * self.scale;
+        let (ymin, ymax) = if lo < hi { (lo, hi) } else { (hi, lo) };
+
+        let xvals = Self::check_axis(r.origin.x, r.direction.x, -1.0, 1.0);
+        let yvals = Self::check_axis(r.origin.y, r.direction.y, ymin - EPSILON, ymax + EPSILON);
+        let zvals = Self::check_axis(r.origin.z, r.direction.z, -1.0, 1.0);
+
+        (xvals.0.max(yvals.0).max(zvals.0), xvals.1.min(yvals.1).min(zvals.1))
+    }
+
+    fn check_axis(o: f64, d: f64, min: f64, max: f64) -> (f64, f64) {
+        if d.abs() <= EPSILON {
+            if o < min || o > max {
+                return (f64::INFINITY, -f64::INFINITY);
+            }
+            return (-f64::INFINITY, f64::INFINITY);
+        }
+
+        let tmin = (min - o) / d;
+        let tmax = (max - o) / d;
+
+        if tmin > tmax { (tmax, tmin) } else { (tmin, tmax) }
+    }
+}
+
+pub fn heightfield(heights: Vec<f64>, cols: usize, rows: usize) -> Heightfield {
+    assert!(cols >= 2 && rows >= 2, "heightfield needs at least a 2x2 grid");
+    assert_eq!(heights.len(), cols * rows);
+
+    let min_height = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max_height = heights.iter().cloned().fold(-f64::INFINITY, f64::max);
+    let digest = digest(&heights);
+
+    Heightfield {
+        supe: shape(),
+        heights,
+        min_height,
+        max_height,
+        digest,
+        cols,
+        rows,
+        scale: 1.0,
+        surface: CellSurface::Triangles,
+    }
+}
+
+// FNV-1a over the bits of every height
+fn digest(heights: &[f64]) -> u64 {
+    let mut h = 0xCBF2_9CE4_8422_2325u64;
+    for v in heights {
+        for b in v.to_bits().to_le_bytes() {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+    h
+}
+
+// brightness of each pixel becomes the height - canvas x runs along
+// the x axis and canvas y along z
+pub fn heightfield_from_canvas(c: &Canvas) -> Heightfield {
+    let mut heights = vec!();
+    for y in 0..c.height {
+        for x in 0..c.width {
+            let p = c.pixel_at(x, y);
+            heights.push((p.r + p.g + p.b) / 3.0);
+        }
+    }
+    heightfield(heights, c.width, c.height)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::heightfields::{heightfield, heightfield_from_canvas, CellSurface};
+    use crate::shapes::Shape;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::canvas::canvas;
+    use crate::color::color;
+    use crate::equals::equals;
+
+    // height rises linearly with x, from 0 at x = -1 to 1 at x = 1
+    fn ramp(cols: usize, rows: usize) -> Vec<f64> {
+        let mut h = vec!();
+        for _ in 0..rows {
+            for i in 0..cols {
+                h.push(i as f64 / (cols - 1) as f64);
+            }
+        }
+        h
+    }
+
+    #[test]
+    fn flat_heightfield_acts_like_a_plane(){
+        let hf = heightfield(vec![0.0; 16], 4, 4);
+        let xs = hf.intersect(ray(point(0.3, 1.0, -0.2), vector(0.0, -1.0, 0.0), 0));
+
+        assert_eq!(xs.len(), 1);
+        assert!(equals(xs[0].t, 1.0));
+        assert!(hf.normal_at(point(0.3, 0.0, -0.2)).equals(vector(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn ray_outside_the_grid_misses(){
+        let hf = heightfield(vec![0.0; 16], 4, 4);
+        let xs = hf.intersect(ray(point(1.5, 1.0, 0.0), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn ray_straight_down_onto_a_ramp(){
+        let hf = heightfield(ramp(5, 5), 5, 5);
+        let xs = hf.intersect(ray(point(0.2, 5.0, 0.1), vector(0.0, -1.0, 0.0), 0));
+
+        assert_eq!(xs.len(), 1);
+        assert!(equals(xs[0].t, 5.0 - 0.6));
+    }
+
+    #[test]
+    fn ray_walks_across_cells_to_find_the_ramp(){
+        // skims in low from the left, climbing slower than the ramp,
+        // so it passes over several empty cells first
+        let hf = heightfield(ramp(9, 9), 9, 9);
+        let r = ray(point(-3.0, 0.7, 0.05), vector(1.0, 0.0, 0.0), 0);
+        let xs = hf.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        let p = r.position(xs[0].t);
+        assert!(equals(p.x, 0.4));
+    }
+
+    #[test]
+    fn ray_walks_backwards_across_cells(){
+        let hf = heightfield(ramp(9, 9), 9, 9);
+        let r = ray(point(3.0, 0.25, -0.3), vector(-1.0, 0.0, 0.0), 0);
+        let xs = hf.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        assert!(equals(r.position(xs[0].t).x, -0.5));
+    }
+
+    #[test]
+    fn ray_passing_over_the_top_misses(){
+        let hf = heightfield(ramp(5, 5), 5, 5);
+        let xs = hf.intersect(ray(point(-3.0, 1.5, 0.0), vector(1.0, 0.0, 0.2), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn terrains_with_the_same_bounds_are_not_equal(){
+        // same size and height range, different in the middle
+        let mut bumped = ramp(5, 5);
+        bumped[12] = 0.9;
+        let a = Box::new(heightfield(ramp(5, 5), 5, 5)) as Box<dyn Shape>;
+        let b = Box::new(heightfield(bumped, 5, 5)) as Box<dyn Shape>;
+        let c = Box::new(heightfield(ramp(5, 5), 5, 5)) as Box<dyn Shape>;
+        assert!(*a != *b);
+        assert!(*a == *c);
+
+        // and the grid itself stays out of the params
+        assert_eq!(a.get_params().len(), 8);
+    }
+
+    #[test]
+    fn vertical_scale_stretches_heights(){
+        let mut hf = heightfield(ramp(5, 5), 5, 5);
+        hf.scale = 3.0;
+        let xs = hf.intersect(ray(point(0.2, 5.0, 0.1), vector(0.0, -1.0, 0.0), 0));
+        assert!(equals(xs[0].t, 5.0 - 1.8));
+    }
+
+    #[test]
+    fn triangles_and_bilinear_disagree_on_a_saddle(){
+        // one raised corner - the triangle split and the bilinear
+        // patch only agree at the corners and edges
+        let mut hf = heightfield(vec![0.0, 0.0, 0.0, 1.0], 2, 2);
+        let r = ray(point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+
+        let xs = hf.intersect(r);
+        assert!(equals(xs[0].t, 5.0));            // on the diagonal of the lower triangle
+
+        hf.surface = CellSurface::Bilinear;
+        let xs = hf.intersect(r);
+        assert!(equals(xs[0].t, 5.0 - 0.25));     // u = v = 0.5, so h = 0.25
+    }
+
+    #[test]
+    fn bilinear_cell_hit_from_the_side(){
+        let mut hf = heightfield(vec![0.0, 0.0, 0.0, 1.0], 2, 2);
+        hf.surface = CellSurface::Bilinear;
+        let r = ray(point(-5.0, 0.5, 1.0), vector(1.0, 0.0, 0.0), 0);
+        let xs = hf.intersect(r);
+
+        // along z = 1 (v = 1) the height is just u, so y = 0.5 at x = 0
+        assert_eq!(xs.len(), 1);
+        assert!(equals(r.position(xs[0].t).x, 0.0));
+    }
+
+    #[test]
+    fn normals_follow_the_slope(){
+        let mut hf = heightfield(ramp(5, 5), 5, 5);
+        hf.scale = 2.0;
+        let n = hf.normal_at(point(0.1, 1.1, 0.3));
+        assert!(n.equals(vector(-1.0, 1.0, 0.0).normal()));
+    }
+
+    #[test]
+    fn normals_are_smooth_across_cells(){
+        // a ridge along x = 0 - the vertex normal there points straight up,
+        // and the blend leans over as we move away from it
+        let hf = heightfield(vec![0.0, 1.0, 0.0,
+                                  0.0, 1.0, 0.0], 3, 2);
+        let top = hf.normal_at(point(0.0, 1.0, 0.0));
+        assert!(top.equals(vector(0.0, 1.0, 0.0)));
+
+        let side = hf.normal_at(point(0.5, 0.5, 0.0));
+        assert!(side.x > 0.0 && side.x < vector(1.0, 1.0, 0.0).normal().x);
+    }
+
+    #[test]
+    fn heightfield_from_a_grayscale_canvas(){
+        let mut c = canvas(3, 2);
+        c.write_pixel(1, 0, color(0.5, 0.5, 0.5));
+        c.write_pixel(2, 1, color(1.0, 1.0, 1.0));
+        let hf = heightfield_from_canvas(&c);
+
+        assert_eq!(hf.cols(), 3);
+        assert_eq!(hf.rows(), 2);
+        assert!(equals(hf.height_at(1, 0), 0.5));
+        assert!(equals(hf.height_at(2, 1), 1.0));
+        assert!(equals(hf.height_at(0, 0), 0.0));
+    }
+}
@@ -0,0 +1,260 @@
+use crate::canvas::{Canvas, canvas};
+use crate::color::{Color, color};
+
+// side of the square window SSIM is worked out over
+const SSIM_WINDOW: usize = 7;
+
+// how far apart two images are, in a few of the usual ways. Channels are
+// clamped to 0-1 first so a few very bright pixels don't swamp the rest,
+// except for max which is the raw largest channel difference.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Difference {
+    pub mae: f64,
+    pub rmse: f64,
+    pub psnr: f64,              // dB, infinite for identical images
+    pub ssim: f64,              // 1 for identical, lower is worse
+    pub max: f64,               // NaN if any pixel compared as NaN
+    pub max_at: (usize, usize), // x, y of the pixel with the biggest difference
+}
+
+// None when the sizes don't match
+pub fn difference(a: &Canvas, b: &Canvas) -> Option<Difference> {
+    if a.width != b.width || a.height != b.height {
+        return None;
+    }
+
+    let mut absolute = 0.0;
+    let mut squares = 0.0;
+    let mut max: f64 = 0.0;
+    let mut max_at = (0, 0);
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let p = a.pixel_at(x, y);
+            let q = b.pixel_at(x, y);
+            for (u, v) in [(p.r, q.r), (p.g, q.g), (p.b, q.b)] {
+                let d = unit(u) - unit(v);
+                absolute += d.abs();
+                squares += d * d;
+            }
+            // the first NaN sticks, so it can't hide behind a bigger number
+            let m = channel_max(p, q);
+            if !max.is_nan() && (m > max || m.is_nan()) {
+                max = m;
+                max_at = (x, y);
+            }
+        }
+    }
+
+    let n = (a.width * a.height * 3).max(1) as f64;
+    let rmse = (squares / n).sqrt();
+    Some(Difference {
+        mae: absolute / n,
+        rmse,
+        psnr: psnr(rmse),
+        ssim: ssim(a, b),
+        max,
+        max_at,
+    })
+}
+
+pub fn psnr(rmse: f64) -> f64 {
+    if rmse == 0.0 { f64::INFINITY } else { -20.0 * rmse.log10() }
+}
+
+// structural similarity of the luminance, averaged over every 7x7 window
+// (or the whole image if it's smaller than that). Images are assumed to
+// be the same size.
+pub fn ssim(a: &Canvas, b: &Canvas) -> f64 {
+    let c1 = 0.01f64.powi(2);
+    let c2 = 0.03f64.powi(2);
+    let wx = SSIM_WINDOW.min(a.width);
+    let wy = SSIM_WINDOW.min(a.height);
+    if wx == 0 || wy == 0 {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y0 in 0..=(a.height - wy) {
+        for x0 in 0..=(a.width - wx) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y0 + wy {
+                for x in x0..x0 + wx {
+                    let p = luminance(a.pixel_at(x, y));
+                    let q = luminance(b.pixel_at(x, y));
+                    sa += p;
+                    sb += q;
+                    saa += p * p;
+                    sbb += q * q;
+                    sab += p * q;
+                }
+            }
+            let n = (wx * wy) as f64;
+            let (ma, mb) = (sa / n, sb / n);
+            let va = saa / n - ma * ma;
+            let vb = sbb / n - mb * mb;
+            let cov = sab / n - ma * mb;
+            total += ((2.0 * ma * mb + c1) * (2.0 * cov + c2)) /
+                     ((ma * ma + mb * mb + c1) * (va + vb + c2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+// false color picture of where two images differ - black where they
+// match, then through blue, green and yellow up to red at the biggest
+// finite difference. NaN pixels are always red, and don't change the
+// scale for everything else. None when the sizes don't match.
+pub fn heatmap(a: &Canvas, b: &Canvas) -> Option<Canvas> {
+    if a.width != b.width || a.height != b.height {
+        return None;
+    }
+    let mut max = 0.0;
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let m = channel_max(a.pixel_at(x, y), b.pixel_at(x, y));
+            if m.is_finite() && m > max {
+                max = m;
+            }
+        }
+    }
+
+    let mut image = canvas(a.width, a.height);
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let d = channel_max(a.pixel_at(x, y), b.pixel_at(x, y));
+            let t = if d.is_nan() || d.is_infinite() { 1.0 }
+                    else if max > 0.0 { d / max }
+                    else { 0.0 };
+            image.write_pixel(x, y, heat(t));
+        }
+    }
+    Some(image)
+}
+
+// t from 0 to 1 along the heatmap's color ramp
+pub fn heat(t: f64) -> Color {
+    let ramp = [color(0.0, 0.0, 0.0), color(0.0, 0.0, 1.0), color(0.0, 1.0, 0.0),
+                color(1.0, 1.0, 0.0), color(1.0, 0.0, 0.0)];
+    let t = if t.is_nan() { 1.0 } else { t.clamp(0.0, 1.0) };
+    let s = t * (ramp.len() - 1) as f64;
+    let i = (s.floor() as usize).min(ramp.len() - 2);
+    let f = s - i as f64;
+    ramp[i] * (1.0 - f) + ramp[i + 1] * f
+}
+
+// f64::max quietly drops NaN, so check for it first
+fn channel_max(p: Color, q: Color) -> f64 {
+    let d = [(p.r - q.r).abs(), (p.g - q.g).abs(), (p.b - q.b).abs()];
+    if d.iter().any(|v| v.is_nan()) {
+        return f64::NAN;
+    }
+    d[0].max(d[1]).max(d[2])
+}
+
+fn unit(v: f64) -> f64 {
+    v.clamp(0.0, 1.0)
+}
+
+fn luminance(c: Color) -> f64 {
+    0.2126 * unit(c.r) + 0.7152 * unit(c.g) + 0.0722 * unit(c.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::imagediff::{difference, heatmap, heat, ssim};
+    use crate::canvas::canvas;
+    use crate::color::color;
+    use crate::equals::equals;
+
+    #[test]
+    fn identical_images(){
+        let mut a = canvas(4, 3);
+        a.write_pixel(1, 1, color(0.3, 0.6, 0.9));
+        let d = difference(&a, &a).unwrap();
+        assert!(equals(d.mae, 0.0) && equals(d.rmse, 0.0) && equals(d.max, 0.0));
+        assert!(d.psnr.is_infinite());
+        assert!(equals(d.ssim, 1.0));
+    }
+
+    #[test]
+    fn one_pixel_off(){
+        let a = canvas(2, 2);
+        let mut b = canvas(2, 2);
+        b.write_pixel(1, 0, color(0.0, 0.6, 0.0));
+        let d = difference(&a, &b).unwrap();
+        assert!(equals(d.mae, 0.6 / 12.0));
+        assert!(equals(d.rmse, (0.36f64 / 12.0).sqrt()));
+        assert!(equals(d.psnr, -20.0 * (0.36f64 / 12.0).sqrt().log10()));
+        assert!(equals(d.max, 0.6));
+        assert_eq!(d.max_at, (1, 0));
+        assert!(d.ssim < 1.0);
+    }
+
+    #[test]
+    fn sizes_must_match(){
+        assert!(difference(&canvas(2, 2), &canvas(2, 3)).is_none());
+        assert!(heatmap(&canvas(2, 2), &canvas(3, 2)).is_none());
+    }
+
+    #[test]
+    fn ssim_prefers_structure_over_brightness(){
+        // a checkerboard, the same a little brighter, and one that's flat
+        let mut a = canvas(8, 8);
+        let mut brighter = canvas(8, 8);
+        let mut flat = canvas(8, 8);
+        for y in 0..8 {
+            for x in 0..8 {
+                let v = if (x + y) % 2 == 0 { 0.2 } else { 0.8 };
+                a.write_pixel(x, y, color(v, v, v));
+                brighter.write_pixel(x, y, color(v + 0.05, v + 0.05, v + 0.05));
+                flat.write_pixel(x, y, color(0.5, 0.5, 0.5));
+            }
+        }
+        assert!(ssim(&a, &brighter) > 0.9);
+        assert!(ssim(&a, &flat) < 0.1);
+    }
+
+    #[test]
+    fn heatmap_runs_black_to_red(){
+        let a = canvas(3, 1);
+        let mut b = canvas(3, 1);
+        b.write_pixel(1, 0, color(0.25, 0.0, 0.0));
+        b.write_pixel(2, 0, color(0.0, 0.0, 1.0));
+        let h = heatmap(&a, &b).unwrap();
+        assert!(h.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)));
+        assert!(h.pixel_at(1, 0).equals(color(0.0, 0.0, 1.0)));
+        assert!(h.pixel_at(2, 0).equals(color(1.0, 0.0, 0.0)));
+        assert!(heat(0.5).equals(color(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn one_nan_channel_is_not_hidden(){
+        let a = canvas(3, 1);
+        let mut b = canvas(3, 1);
+        b.write_pixel(1, 0, color(0.0, f64::NAN, 0.0));
+        b.write_pixel(2, 0, color(0.5, 0.0, 0.0));
+
+        let d = difference(&a, &b).unwrap();
+        assert!(d.max.is_nan());
+        assert_eq!(d.max_at, (1, 0));
+
+        // red for the NaN, and the real difference still scales to red
+        let h = heatmap(&a, &b).unwrap();
+        assert!(h.pixel_at(0, 0).equals(color(0.0, 0.0, 0.0)));
+        assert!(h.pixel_at(1, 0).equals(color(1.0, 0.0, 0.0)));
+        assert!(h.pixel_at(2, 0).equals(color(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn only_nan_pixels_go_red(){
+        let a = canvas(2, 1);
+        let mut b = canvas(2, 1);
+        b.write_pixel(0, 0, color(f64::NAN, f64::NAN, f64::NAN));
+
+        let h = heatmap(&a, &b).unwrap();
+        assert!(h.pixel_at(0, 0).equals(color(1.0, 0.0, 0.0)));
+        assert!(h.pixel_at(1, 0).equals(color(0.0, 0.0, 0.0)));
+    }
+}
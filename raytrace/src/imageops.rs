@@ -0,0 +1,460 @@
+use crate::canvas::{Canvas, canvas};
+use crate::color::{Color, color};
+use std::f64::consts::PI;
+
+// reconstruction filter for resize
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Filter {
+    // averages the source pixels each new pixel covers, nearest when
+    // enlarging
+    Box,
+    Bilinear,
+    // Lanczos with a = 3 - sharpest, but can ring a little at hard edges
+    Lanczos,
+}
+
+impl Filter {
+    fn support(&self) -> f64 {
+        match self {
+            Filter::Box      => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::Lanczos  => 3.0,
+        }
+    }
+
+    fn weight(&self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            Filter::Box      => if x < 0.5 { 1.0 } else { 0.0 },
+            Filter::Bilinear => (1.0 - x).max(0.0),
+            Filter::Lanczos  => if x < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 },
+        }
+    }
+}
+
+// image operations for post-processing renders and preparing textures.
+// All of these make a new canvas apart from blit and composite, which
+// draw into this one.
+impl Canvas {
+    // the part inside the rectangle, clipped to the canvas
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Canvas {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        self.map_positions(width, height, |i, j| (x + i, y + j))
+    }
+
+    pub fn resize(&self, width: usize, height: usize, f: Filter) -> Canvas {
+        // one direction at a time, since all the filters are separable
+        let wide = resample_rows(self, width, f);
+        let mut c = transpose(&resample_rows(&transpose(&wide), height, f));
+        if let Some(a) = self.alpha_canvas() {
+            c.set_alpha_canvas(&a.resize(width, height, f));
+        }
+        c
+    }
+
+    pub fn flip_horizontal(&self) -> Canvas {
+        self.map_positions(self.width, self.height, |x, y| (self.width - 1 - x, y))
+    }
+
+    pub fn flip_vertical(&self) -> Canvas {
+        self.map_positions(self.width, self.height, |x, y| (x, self.height - 1 - y))
+    }
+
+    // a quarter turn clockwise
+    pub fn rotate_90(&self) -> Canvas {
+        self.map_positions(self.height, self.width, |x, y| (y, self.height - 1 - x))
+    }
+
+    pub fn rotate_180(&self) -> Canvas {
+        self.map_positions(self.width, self.height, |x, y| (self.width - 1 - x, self.height - 1 - y))
+    }
+
+    pub fn rotate_270(&self) -> Canvas {
+        self.map_positions(self.height, self.width, |x, y| (self.width - 1 - y, x))
+    }
+
+    // copies src in with its top left corner at (x, y) - either can be
+    // negative, and whatever falls off the edge is dropped
+    pub fn blit(&mut self, src: &Canvas, x: i64, y: i64) {
+        self.composite(src, x, y, 1.0);
+    }
+
+    // src laid over this canvas at (x, y) with a constant opacity, on
+    // top of src's own alpha if it has one
+    pub fn composite(&mut self, src: &Canvas, x: i64, y: i64, opacity: f64) {
+        let mut mask = canvas(src.width, src.height);
+        for j in 0..src.height {
+            for i in 0..src.width {
+                mask.write_pixel(i, j, color(opacity, opacity, opacity));
+            }
+        }
+        self.composite_masked(src, &mask, x, y);
+    }
+
+    // like composite, but the opacity comes from each pixel of `mask`
+    // (per channel), which has to be the size of src. This is "over" with
+    // premultiplied colors, so what's underneath shows through wherever
+    // src is transparent.
+    pub fn composite_masked(&mut self, src: &Canvas, mask: &Canvas, x: i64, y: i64) {
+        for j in 0..src.height {
+            for i in 0..src.width {
+                let (tx, ty) = (x + i as i64, y + j as i64);
+                if tx < 0 || ty < 0 || tx >= self.width as i64 || ty >= self.height as i64 {
+                    continue;
+                }
+                let (tx, ty) = (tx as usize, ty as usize);
+                let a = mask.pixel_at(i, j);
+                let coverage = src.alpha_at(i, j);
+                let under = self.pixel_at(tx, ty);
+                let over = src.pixel_at(i, j);
+                self.write_pixel(tx, ty, color(over.r * a.r + under.r * (1.0 - a.r * coverage),
+                                               over.g * a.g + under.g * (1.0 - a.g * coverage),
+                                               over.b * a.b + under.b * (1.0 - a.b * coverage)));
+                if self.alpha.is_some() {
+                    let opacity = (a.r + a.g + a.b) / 3.0 * coverage;
+                    let below = self.alpha_at(tx, ty);
+                    self.write_alpha(tx, ty, opacity + below * (1.0 - opacity));
+                }
+            }
+        }
+    }
+
+    // any kernel, centred on each pixel, with the edges of the canvas
+    // stretched outwards to cover what the kernel reaches past them
+    pub fn convolve(&self, kernel: &[Vec<f64>]) -> Canvas {
+        let kh = kernel.len() as i64;
+        let kw = kernel.first().map_or(0, |row| row.len()) as i64;
+        let mut c = canvas(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = color(0.0, 0.0, 0.0);
+                for (j, row) in kernel.iter().enumerate() {
+                    for (i, k) in row.iter().enumerate() {
+                        let sx = x as i64 + i as i64 - kw / 2;
+                        let sy = y as i64 + j as i64 - kh / 2;
+                        sum = sum + self.clamped_pixel(sx, sy) * *k;
+                    }
+                }
+                c.write_pixel(x, y, sum);
+            }
+        }
+        if let Some(a) = self.alpha_canvas() {
+            c.set_alpha_canvas(&a.convolve(kernel));
+        }
+        c
+    }
+
+    // separable, so two thin passes rather than one big kernel
+    pub fn gaussian_blur(&self, sigma: f64) -> Canvas {
+        if sigma <= 0.0 {
+            return self.crop(0, 0, self.width, self.height);
+        }
+        let radius = (sigma * 3.0).ceil() as i64;
+        let mut weights: Vec<f64> = (-radius..=radius)
+            .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: f64 = weights.iter().sum();
+        weights.iter_mut().for_each(|w| *w /= total);
+
+        let across = self.convolve(&[weights.clone()]);
+        let column: Vec<Vec<f64>> = weights.iter().map(|w| vec![*w]).collect();
+        across.convolve(&column)
+    }
+
+    // glow around highlights - whatever is brighter than `threshold` is
+    // blurred and added back on top
+    pub fn bloom(&self, threshold: f64, sigma: f64, strength: f64) -> Canvas {
+        let mut bright = canvas(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let p = self.pixel_at(x, y);
+                bright.write_pixel(x, y, color((p.r - threshold).max(0.0),
+                                               (p.g - threshold).max(0.0),
+                                               (p.b - threshold).max(0.0)));
+            }
+        }
+
+        let glow = bright.gaussian_blur(sigma);
+        let mut c = canvas(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                c.write_pixel(x, y, self.pixel_at(x, y) + glow.pixel_at(x, y) * strength);
+            }
+        }
+        c.alpha = self.alpha.clone();
+        c
+    }
+
+    fn clamped_pixel(&self, x: i64, y: i64) -> Color {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.pixel_at(x, y)
+    }
+
+    // new canvas where each pixel is copied from wherever `from` says
+    fn map_positions<F: Fn(usize, usize) -> (usize, usize)>(&self, width: usize, height: usize, from: F) -> Canvas {
+        let mut c = canvas(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = from(x, y);
+                c.write_pixel(x, y, self.pixel_at(sx, sy));
+                if self.alpha.is_some() {
+                    c.write_alpha(x, y, self.alpha_at(sx, sy));
+                }
+            }
+        }
+        c
+    }
+
+    // alpha as a grey canvas, so it can go through the same operations
+    // as the color
+    fn alpha_canvas(&self) -> Option<Canvas> {
+        self.alpha.as_ref()?;
+        let mut a = canvas(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let v = self.alpha_at(x, y);
+                a.write_pixel(x, y, color(v, v, v));
+            }
+        }
+        Some(a)
+    }
+
+    fn set_alpha_canvas(&mut self, a: &Canvas) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.write_alpha(x, y, a.pixel_at(x, y).r);
+            }
+        }
+    }
+}
+
+// resizes horizontally only
+fn resample_rows(src: &Canvas, width: usize, f: Filter) -> Canvas {
+    let mut c = canvas(width, src.height);
+    if width == 0 || src.width == 0 {
+        return c;
+    }
+
+    // when shrinking, the filter is stretched to cover every source pixel
+    let scale = src.width as f64 / width as f64;
+    let stretch = scale.max(1.0);
+    let support = f.support() * stretch;
+
+    for x in 0..width {
+        let centre = (x as f64 + 0.5) * scale;
+        let first = (centre - support).floor().max(0.0) as usize;
+        let last = ((centre + support).ceil() as usize).min(src.width);
+
+        let mut weights = vec!();
+        for sx in first..last {
+            weights.push((sx, f.weight((sx as f64 + 0.5 - centre) / stretch)));
+        }
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        if total == 0.0 {
+            // box filter landing between pixels when enlarging
+            let sx = (centre as usize).min(src.width - 1);
+            weights = vec![(sx, 1.0)];
+        }
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+
+        for y in 0..src.height {
+            let mut sum = color(0.0, 0.0, 0.0);
+            for (sx, w) in &weights {
+                sum = sum + src.pixel_at(*sx, y) * (*w / total);
+            }
+            c.write_pixel(x, y, sum);
+        }
+    }
+    c
+}
+
+fn transpose(src: &Canvas) -> Canvas {
+    src.map_positions(src.height, src.width, |x, y| (y, x))
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::imageops::Filter;
+    use crate::canvas::{Canvas, canvas};
+    use crate::color::color;
+    use crate::equals::equals;
+
+    // pixel values 0, 1, 2... across and 10, 20... down so moves are easy
+    // to follow
+    fn numbered(width: usize, height: usize) -> Canvas {
+        let mut c = canvas(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = x as f64 + 10.0 * y as f64;
+                c.write_pixel(x, y, color(v, v, v));
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn cropping_is_clipped_to_the_canvas(){
+        let c = numbered(4, 3).crop(2, 1, 5, 5);
+        assert_eq!((c.width, c.height), (2, 2));
+        assert!(equals(c.pixel_at(0, 0).r, 12.0));
+        assert!(equals(c.pixel_at(1, 1).r, 23.0));
+    }
+
+    #[test]
+    fn flips_and_rotations(){
+        let c = numbered(3, 2);
+        assert!(equals(c.flip_horizontal().pixel_at(0, 0).r, 2.0));
+        assert!(equals(c.flip_vertical().pixel_at(0, 0).r, 10.0));
+
+        let r = c.rotate_90();
+        assert_eq!((r.width, r.height), (2, 3));
+        assert!(equals(r.pixel_at(0, 0).r, 10.0));
+        assert!(equals(r.pixel_at(1, 0).r, 0.0));
+        assert!(equals(c.rotate_180().pixel_at(0, 0).r, 12.0));
+        assert!(equals(c.rotate_270().pixel_at(0, 0).r, 2.0));
+        assert!(c.rotate_90().rotate_270().equals(numbered(3, 2)));
+    }
+
+    #[test]
+    fn box_downsampling_averages(){
+        let c = numbered(4, 2).resize(2, 1, Filter::Box);
+        assert_eq!((c.width, c.height), (2, 1));
+        assert!(equals(c.pixel_at(0, 0).r, 5.5));
+        assert!(equals(c.pixel_at(1, 0).r, 7.5));
+    }
+
+    #[test]
+    fn flat_images_stay_flat_with_every_filter(){
+        let mut c = canvas(5, 4);
+        for y in 0..4 {
+            for x in 0..5 {
+                c.write_pixel(x, y, color(0.3, 0.6, 0.9));
+            }
+        }
+        for f in [Filter::Box, Filter::Bilinear, Filter::Lanczos] {
+            for (w, h) in [(2, 2), (11, 7)] {
+                let r = c.resize(w, h, f);
+                assert_eq!((r.width, r.height), (w, h));
+                for y in 0..h {
+                    for x in 0..w {
+                        assert!(r.pixel_at(x, y).equals(color(0.3, 0.6, 0.9)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bilinear_enlarging_blends_neighbours(){
+        let mut c = canvas(2, 1);
+        c.write_pixel(1, 0, color(1.0, 1.0, 1.0));
+        let r = c.resize(4, 1, Filter::Bilinear);
+        assert!(equals(r.pixel_at(0, 0).r, 0.0));
+        assert!(equals(r.pixel_at(1, 0).r, 0.25));
+        assert!(equals(r.pixel_at(2, 0).r, 0.75));
+        assert!(equals(r.pixel_at(3, 0).r, 1.0));
+    }
+
+    #[test]
+    fn blitting_clips_at_the_edges(){
+        let mut dst = canvas(3, 3);
+        let src = numbered(2, 2);
+        dst.blit(&src, -1, 2);
+        assert!(equals(dst.pixel_at(0, 2).r, 1.0));
+        assert!(equals(dst.pixel_at(1, 2).r, 0.0));
+        assert!(equals(dst.pixel_at(0, 1).r, 0.0));
+    }
+
+    #[test]
+    fn compositing_with_opacity_and_masks(){
+        let mut dst = canvas(1, 1);
+        dst.write_pixel(0, 0, color(1.0, 0.0, 0.0));
+        let mut src = canvas(1, 1);
+        src.write_pixel(0, 0, color(0.0, 0.0, 1.0));
+
+        dst.composite(&src, 0, 0, 0.25);
+        assert!(dst.pixel_at(0, 0).equals(color(0.75, 0.0, 0.25)));
+
+        let mut mask = canvas(1, 1);
+        mask.write_pixel(0, 0, color(1.0, 0.0, 1.0));
+        dst.composite_masked(&src, &mask, 0, 0);
+        assert!(dst.pixel_at(0, 0).equals(color(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn convolving_with_a_kernel(){
+        let c = numbered(3, 1);
+        let edge = c.convolve(&[vec![-1.0, 0.0, 1.0]]);
+        assert!(equals(edge.pixel_at(1, 0).r, 2.0));
+        assert!(equals(edge.pixel_at(0, 0).r, 1.0));     // edge pixel repeated
+        let same = c.convolve(&[vec![0.0, 1.0, 0.0]]);
+        assert!(same.equals(numbered(3, 1)));
+    }
+
+    #[test]
+    fn blur_spreads_light_but_keeps_the_total(){
+        let mut c = canvas(21, 21);
+        c.write_pixel(10, 10, color(1.0, 1.0, 1.0));
+        let b = c.gaussian_blur(1.5);
+        let mut total = 0.0;
+        for y in 0..21 {
+            for x in 0..21 {
+                total += b.pixel_at(x, y).r;
+            }
+        }
+        assert!(equals(total, 1.0));
+        assert!(b.pixel_at(10, 10).r < 1.0);
+        assert!(b.pixel_at(11, 10).r > 0.0);
+        assert!(equals(b.pixel_at(11, 10).r, b.pixel_at(10, 9).r));
+    }
+
+    #[test]
+    fn bloom_only_spreads_highlights(){
+        let mut c = canvas(9, 9);
+        for y in 0..9 {
+            for x in 0..9 {
+                c.write_pixel(x, y, color(0.5, 0.5, 0.5));
+            }
+        }
+        c.write_pixel(4, 4, color(5.0, 5.0, 5.0));
+        let b = c.bloom(1.0, 1.0, 1.0);
+        assert!(b.pixel_at(5, 4).r > 0.5);
+        assert!(equals(b.pixel_at(0, 0).r, 0.5));
+    }
+
+    #[test]
+    fn alpha_follows_the_pixels(){
+        let mut c = numbered(3, 2);
+        c.write_alpha(2, 0, 0.5);
+        assert!(equals(c.flip_horizontal().alpha_at(0, 0), 0.5));
+        assert!(equals(c.rotate_90().alpha_at(1, 2), 0.5));
+        assert!(equals(c.crop(1, 0, 2, 2).alpha_at(1, 0), 0.5));
+        assert!(equals(c.resize(6, 4, Filter::Box).alpha_at(5, 0), 0.5));
+        assert!(canvas(2, 2).resize(3, 3, Filter::Bilinear).alpha.is_none());
+    }
+
+    #[test]
+    fn transparent_parts_show_what_is_underneath(){
+        let mut dst = canvas(2, 1);
+        dst.write_pixel(0, 0, color(1.0, 0.0, 0.0));
+        dst.write_pixel(1, 0, color(1.0, 0.0, 0.0));
+
+        // premultiplied: half covered blue, and nothing at all
+        let mut src = canvas(2, 1);
+        src.write_pixel(0, 0, color(0.0, 0.0, 0.5));
+        src.write_alpha(0, 0, 0.5);
+        src.write_alpha(1, 0, 0.0);
+
+        dst.composite(&src, 0, 0, 1.0);
+        assert!(dst.pixel_at(0, 0).equals(color(0.5, 0.0, 0.5)));
+        assert!(dst.pixel_at(1, 0).equals(color(1.0, 0.0, 0.0)));
+    }
+}
@@ -2,6 +2,7 @@ use crate::equals::EPSILON;
 use crate::rays::Ray;
 use crate::tuple::Tuple;
 use crate::world::World;
+use crate::bumps::perturb_normal;
 
 #[derive(Debug,Copy,Clone)]
 pub struct Intersection {
@@ -60,7 +61,7 @@ pub fn schlick(comps: Computations) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cos).powf(5.0)
 }
 
-#[derive(Debug)]
+#[derive(Debug,Clone)]
 pub struct Computations {
     pub t: f64,
     pub object: usize,
@@ -88,6 +89,13 @@ pub fn prepare_computations( hit: Intersection,
     }
     let op = r.position(hit.t) + n * EPSILON;
     let up = r.position(hit.t) - n * EPSILON;
+
+    // bump and normal maps only change the normal used for shading - the
+    // offset points stay with the real surface
+    let object = w.get_object(hit.object);
+    if let Some(b) = &object.get_material().bump {
+        n = perturb_normal(b, object.as_ref(), r.position(hit.t), n, w);
+    }
     let rv = r.direction.reflect(&n);
 
     let mut containers: Vec<usize> = vec!();
@@ -150,6 +158,9 @@ mod tests {
     use crate::shapes::Shape;
     use crate::transform::{scaling, translation};
     use crate::materials::material;
+    use crate::bumps::normal_map;
+    use crate::canvas::canvas;
+    use crate::color::color;
     use crate::equals::EPSILON;
     use std::f64::consts::SQRT_2;
 
@@ -335,6 +346,29 @@ mod tests {
         assert_eq!(comps.n2, 1.0);
     }
 
+    #[test]
+    fn bumped_normal_only_affects_shading(){
+        let mut w = world();
+        let mut c = canvas(1, 1);
+        c.write_pixel(0, 0, color(1.0, 0.5, 1.0));
+        let tex = w.add_texture(c);
+
+        let mut p = plane();
+        let mut m = material();
+        m.bump = Some(normal_map(tex));
+        p.set_material(m);
+        w.add_object(Box::new(p));
+
+        let r = ray(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+        let i = intersection(1.0, 0);
+        let comps = prepare_computations(i, r, &w, &intersections(&[i]));
+
+        assert!(comps.normalv.equals(vector(SQRT_2 / 2.0, SQRT_2 / 2.0, 0.0)));
+        assert!(comps.reflectv.equals(vector(1.0, 0.0, 0.0)));
+        assert!(comps.over_point.equals(point(0.0, EPSILON, 0.0)));
+        assert!(comps.under_point.equals(point(0.0, -EPSILON, 0.0)));
+    }
+
     #[test]
     fn under_point_offset_below_surface(){
         let mut w = world();
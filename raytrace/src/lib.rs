@@ -25,6 +25,33 @@ pub mod cylinders;
 pub mod cones;
 pub mod groups;
 pub mod shape_index;
+pub mod numeric;
+pub mod tori;
+pub mod disks;
+pub mod rectangles;
+pub mod annuli;
+pub mod quadrics;
+pub mod sdfs;
+pub mod heightfields;
+pub mod sampling;
+pub mod pathtracer;
+pub mod occlusion;
+pub mod backgrounds;
+pub mod microfacet;
+pub mod noise;
+pub mod bumps;
+pub mod media;
+pub mod aovs;
+pub mod tonemap;
+pub mod progressive;
+pub mod stats;
+pub mod golden;
+pub mod imagediff;
+pub mod imageops;
+pub mod png;
+pub mod denoise;
+pub mod animation;
+pub mod gif;
 
 mod equals {
     pub const EPSILON: f64 = 0.00001;
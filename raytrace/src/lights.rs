@@ -1,29 +1,66 @@
 use crate::tuple::Tuple;
 use crate::color::Color;
 
+// turns a point light into a cone. Inside `inner` radians of the axis
+// it's full strength, fading smoothly to nothing at `outer`.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Spot {
+    pub direction: Tuple,
+    pub inner: f64,
+    pub outer: f64,
+}
+
 #[derive(Debug)]
 pub struct Light {
     pub position: Tuple,
     pub intensity: Color,
+    pub spot: Option<Spot>,
 }
 
 impl Light {
     pub fn equals(&self, l: Light) -> bool {
         self.position.equals( l.position ) &&
-        self.intensity.equals( l.intensity )
+        self.intensity.equals( l.intensity ) &&
+        self.spot == l.spot
+    }
+
+    // how much of the light reaches p, ignoring anything in the way -
+    // the same everywhere for a point light
+    pub fn intensity_at(&self, p: Tuple) -> Color {
+        let spot = match &self.spot {
+            Some(s) => s,
+            None    => return self.intensity,
+        };
+        let cos = (p - self.position).normal().dot(&spot.direction);
+        let (lo, hi) = (spot.outer.cos(), spot.inner.cos());
+        if cos >= hi {
+            return self.intensity;
+        }
+        if cos <= lo {
+            return self.intensity * 0.0;
+        }
+        let f = (cos - lo) / (hi - lo);
+        self.intensity * (f * f * (3.0 - 2.0 * f))
     }
 }
 
 pub fn point_light(position: Tuple, intensity: Color) -> Light {
-    Light { position, intensity }
+    Light { position, intensity, spot: None }
+}
+
+// inner and outer are half angles of the cone, in radians
+pub fn spot_light(position: Tuple, direction: Tuple, inner: f64, outer: f64, intensity: Color) -> Light {
+    let spot = Spot { direction: direction.normal(), inner, outer: outer.max(inner) };
+    Light { position, intensity, spot: Some(spot) }
 }
 
 
 #[cfg(test)]
 mod tests {
     use crate::color::color;
-    use crate::tuple::point;
-    use crate::lights::point_light;
+    use crate::tuple::{point, vector};
+    use crate::lights::{point_light, spot_light};
+    use std::f64::consts::PI;
 
     #[test]
     fn point_light_has_position_and_intensity(){
@@ -33,4 +70,20 @@ mod tests {
         assert!( light.position.equals( position ));
         assert!( light.intensity.equals( intensity ));
     }
+
+    #[test]
+    fn spot_light_fades_across_the_cone(){
+        let white = color(1.0, 1.0, 1.0);
+        let l = spot_light(point(0.0, 10.0, 0.0), vector(0.0, -1.0, 0.0), PI / 8.0, PI / 4.0, white);
+
+        // straight below, at 30 degrees off (between inner and outer), and outside
+        assert!( l.intensity_at(point(0.0, 0.0, 0.0)).equals(white) );
+        let partial = l.intensity_at(point(10.0 * (PI / 6.0).tan(), 0.0, 0.0));
+        assert!( partial.r > 0.0 && partial.r < 1.0 );
+        assert!( l.intensity_at(point(20.0, 0.0, 0.0)).equals(color(0.0, 0.0, 0.0)) );
+
+        // point lights shine everywhere
+        let p = point_light(point(0.0, 10.0, 0.0), white);
+        assert!( p.intensity_at(point(20.0, 0.0, 0.0)).equals(white) );
+    }
 }
@@ -12,6 +12,7 @@ use raytrace::materials::material;
 use raytrace::lights::point_light;
 use raytrace::cylinders::cylinder;
 use raytrace::cones::cone;
+use raytrace::stats::Progress;
 use std::time::Instant;
 
 fn main() {
@@ -108,11 +109,17 @@ fn main() {
     let up = vector(0.0, 1.0, 0.0);
     c.transform = view_transform(from, to, up);
 
-    let image = c.render(w);
+    let (image, stats) = c.render_observed(&w, &mut |p: &Progress| {
+        let eta = p.eta().map(|d| d.as_secs()).unwrap_or(0);
+        eprint!("\r{:5.1}%  eta {} s  {:.0} rays/s   ",
+                p.fraction() * 100.0, eta, p.rays_per_second());
+    });
+    eprintln!();
 
     let _ = image.to_ppm("pencil_2.ppm");
 
     let elapsed = now.elapsed();
     println!("Size: {} x {}", c.hsize, c.vsize);
     println!("Rendering time: {} seconds", elapsed.as_secs());
+    print!("{}", stats.report());
 }
@@ -4,6 +4,9 @@ use crate::lights::Light;
 use crate::tuple::Tuple;
 use crate::shapes::Shape;
 use crate::world::World;
+use crate::microfacet::Microfacet;
+use crate::bumps::Bump;
+use crate::media::Medium;
 
 #[derive(Debug,Clone,PartialEq)]
 pub struct Material {
@@ -16,6 +19,10 @@ pub struct Material {
     pub reflective: f64,        // typical range 0-1
     pub transparency: f64,      // typical range 0-1
     pub refractive_index: f64,  // typical range 1-3
+    pub emissive: Color,        // light given off, black for most things
+    pub microfacet: Option<Microfacet>, // physically based shading instead of Phong
+    pub bump: Option<Bump>,     // bends the shading normal
+    pub medium: Option<Medium>, // fills the inside, e.g. tint for thick glass
 }
 
 // refractive indices for reference (RTC p. 150):
@@ -35,7 +42,11 @@ impl Material {
         self.pattern == m.pattern &&
         equals(self.reflective, m.reflective) &&
         equals(self.transparency, m.transparency) &&
-        equals(self.refractive_index, m.refractive_index)
+        equals(self.refractive_index, m.refractive_index) &&
+        self.emissive.equals( m.emissive ) &&
+        self.microfacet == m.microfacet &&
+        self.bump == m.bump &&
+        self.medium == m.medium
     }
 }
 
@@ -50,6 +61,18 @@ pub fn material() -> Material {
         reflective: 0.0,
         transparency: 0.0,
         refractive_index: 1.0,
+        emissive: color(0.0, 0.0, 0.0),
+        microfacet: None,
+        bump: None,
+        medium: None,
+    }
+}
+
+// the material's color at a point, with any pattern taken into account
+pub fn surface_color(m: &Material, o: &dyn Shape, p: Tuple, world: &World) -> Color {
+    match m.pattern {
+        Some(pat) => world.get_pattern(pat).pattern_at_shape(o, p),
+        None      => m.color,
     }
 }
 
@@ -62,19 +85,20 @@ pub fn lighting(m: Material,
                 in_shadow: bool,
                 world: &World,
   ) -> Color {
-    let true_color = match m.pattern {
-        Some(pat) => world.get_pattern(pat).pattern_at_shape(o, p),
-        None      => m.color,
-    };
-    let effective_color = true_color * l.intensity;
+    let true_color = surface_color(&m, o.as_ref(), p, world);
+    let ambient = true_color * l.intensity * m.ambient;
+    // a spotlight only lights up what's inside its cone
+    let arriving = l.intensity_at(p);
+    let effective_color = true_color * arriving;
     let lightv = (l.position - p).normal();
-    let ambient = effective_color * m.ambient;
 
     let light_dot_normal = lightv.dot( &normal );
     let mut diffuse = color(0.0, 0.0, 0.0);
     let mut specular = color(0.0, 0.0, 0.0);
 
-    if light_dot_normal >= 0.0 {
+    if let Some(mf) = &m.microfacet {
+        (diffuse, specular) = mf.shade(true_color, arriving, lightv, eye, normal);
+    } else if light_dot_normal >= 0.0 {
         diffuse = effective_color * m.diffuse * light_dot_normal;
 
         let reflectv = -lightv.reflect(&normal);
@@ -82,7 +106,7 @@ pub fn lighting(m: Material,
 
         if reflect_dot_eye > 0.0 {
             let factor = reflect_dot_eye.powf( m.shininess );
-            specular = l.intensity * m.specular * factor;
+            specular = arriving * m.specular * factor;
         }
     }
     
@@ -106,6 +130,7 @@ mod tests {
     use crate::spheres::sphere;
     use crate::shapes::Shape;
     use crate::world::world;
+    use crate::microfacet::microfacet;
 
     #[test]
     fn default_material(){
@@ -238,6 +263,26 @@ mod tests {
         assert!( result.equals(color(0.1, 0.1, 0.1)) );
     }
 
+    #[test]
+    fn lighting_with_microfacet_material(){
+        let mut m = material();
+        m.microfacet = Some(microfacet(1.0, 0.0));
+        let p = origin();
+        let eyev = vector(0.0, 0.0, -1.0);
+        let normalv = vector(0.0, 0.0, -1.0);
+        let light = point_light(point(0.0, 0.0, -10.0), color(1.0, 1.0, 1.0));
+        let o: Box<dyn Shape> = Box::new(sphere());
+
+        // ambient 0.1, diffuse (1 - 0.04) and the GGX highlight
+        // 0.04 * pi * D * G / 4 with D = 1/pi, G = 1
+        let result = lighting(m.clone(), &o, &light, p, eyev, normalv, false, &world());
+        assert!( result.equals( color(1.07, 1.07, 1.07) ));
+
+        // only ambient in shadow, like Phong
+        let result = lighting(m, &o, &light, p, eyev, normalv, true, &world());
+        assert!( result.equals( color(0.1, 0.1, 0.1) ));
+    }
+
     #[test]
     fn lighting_with_pattern_applied(){
         let mut w = world();
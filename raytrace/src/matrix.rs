@@ -1,5 +1,7 @@
 use crate::equals;
 use crate::tuple::Tuple;
+use crate::stats::{collecting, record};
+use std::time::Instant;
 
 #[derive(Debug,PartialEq,Clone)]
 pub struct Matrix {
@@ -137,6 +139,9 @@ impl Matrix {
             panic!();
         }
 
+        // only worth timing while someone is collecting stats
+        let start = if collecting() { Some(Instant::now()) } else { None };
+
         let size = self.cols;
         let det = self.determinant();
         let mut result = matrix(size, size);
@@ -149,6 +154,13 @@ impl Matrix {
                 result.set(col, row, c / det);
             }
         }
+
+        if let Some(t) = start {
+            record(|s| {
+                s.inverse_calls += 1;
+                s.inverse_time += t.elapsed();
+            });
+        }
         result
     }
 }
@@ -0,0 +1,332 @@
+use crate::color::{Color, color};
+use crate::tuple::Tuple;
+use crate::rays::{Ray, ray};
+use crate::world::World;
+use crate::noise::fbm;
+use crate::intersections::Intersection;
+use crate::stats::record;
+use std::f64::consts::PI;
+
+// how thick the medium is from place to place, as a multiplier on the
+// coefficients
+#[derive(Debug,Clone,PartialEq)]
+pub enum Density {
+    Uniform,
+    // fbm noise, clamped so it never goes negative
+    Noise { scale: f64, octaves: usize },
+    // thick near y = base and thinning out above, like ground fog
+    HeightFalloff { base: f64, falloff: f64 },
+}
+
+// participating medium - fog, smoke, or the stuff inside tinted glass.
+// Coefficients are per unit distance.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    pub emission: Color,
+    pub anisotropy: f64,    // Henyey-Greenstein g: -1 back, 0 even, 1 forward
+    pub density: Density,
+    pub steps: usize,       // ray marching steps per segment
+    pub max_distance: f64,  // how far rays that hit nothing march
+}
+
+impl Medium {
+    pub fn density_at(&self, p: Tuple) -> f64 {
+        match self.density {
+            Density::Uniform => 1.0,
+            Density::Noise { scale, octaves } => (fbm(p * (1.0 / scale), octaves) + 0.5).max(0.0),
+            Density::HeightFalloff { base, falloff } => (-(p.y - base) * falloff).exp().min(1.0e6),
+        }
+    }
+
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    // fraction of light that makes it `distance` along the ray
+    pub fn transmittance(&self, origin: Tuple, direction: Tuple, distance: f64) -> Color {
+        if self.density == Density::Uniform {
+            return exp_color(self.extinction() * -distance);
+        }
+
+        let steps = self.steps.max(1);
+        let dt = distance / steps as f64;
+        let r = ray(origin, direction, 0);
+        let mut optical_depth = 0.0;
+        for i in 0..steps {
+            optical_depth += self.density_at(r.position((i as f64 + 0.5) * dt)) * dt;
+        }
+        exp_color(self.extinction() * -optical_depth)
+    }
+
+    // Henyey-Greenstein, cos is between the ray and the direction to the light
+    pub fn phase(&self, cos: f64) -> f64 {
+        let g = self.anisotropy;
+        let denom = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    // nothing to march for if it only soaks up light evenly
+    fn absorbs_only(&self) -> bool {
+        self.density == Density::Uniform &&
+        is_black(self.scattering) && is_black(self.emission)
+    }
+}
+
+pub fn medium(absorption: Color, scattering: Color) -> Medium {
+    Medium {
+        absorption,
+        scattering,
+        emission: color(0.0, 0.0, 0.0),
+        anisotropy: 0.0,
+        density: Density::Uniform,
+        steps: 32,
+        max_distance: 100.0,
+    }
+}
+
+// grey fog that scatters a little forward, good for light shafts
+pub fn fog(thickness: f64) -> Medium {
+    let mut m = medium(color(0.1, 0.1, 0.1) * thickness, color(0.9, 0.9, 0.9) * thickness);
+    m.anisotropy = 0.5;
+    m
+}
+
+// Beer-Lambert only, for tinted glass and colored liquids. `tint` is the
+// color left after light travels one unit through it.
+pub fn absorbing(tint: Color) -> Medium {
+    let a = color(-tint.r.max(1e-6).ln(), -tint.g.max(1e-6).ln(), -tint.b.max(1e-6).ln());
+    medium(a, color(0.0, 0.0, 0.0))
+}
+
+// the medium a ray starts out in - the innermost shape with a medium that
+// the ray's origin is inside of, otherwise the world's fog. Inside means
+// an odd number of crossings behind the origin.
+pub fn medium_at_origin<'a>(w: &'a World, xs: &[Intersection]) -> Option<(&'a Medium, Option<usize>)> {
+    let mut best: Option<(f64, usize)> = None;
+    for i in xs.iter().filter(|i| i.t < 0.0) {
+        if w.get_object(i.object).get_material().medium.is_none() {
+            continue;
+        }
+        // xs is sorted, so this keeps whichever shape was crossed last
+        let behind = xs.iter().filter(|x| x.object == i.object && x.t < 0.0).count();
+        if behind % 2 == 1 && best.is_none_or(|(t, _)| i.t > t) {
+            best = Some((i.t, i.object));
+        }
+    }
+
+    match best {
+        Some((_, object)) => w.get_object(object).get_material().medium.as_ref().map(|m| (m, Some(object))),
+        None              => w.fog.as_ref().map(|m| (m, None)),
+    }
+}
+
+// light reaching the eye along r over [0, end], given what's at the far
+// end. Single scattering from the light, with `container` being the
+// shape holding the medium (it doesn't count as a shadow caster).
+pub fn march(w: &World, r: Ray, end: f64, m: &Medium, container: Option<usize>, behind: Color) -> Color {
+    let (scattered, transmittance) = march_segment(w, r, end, m, container);
+    scattered + transmittance * behind
+}
+
+// the two halves of march - light picked up along the way, and how much
+// of whatever is at the far end gets through. The path tracer needs them
+// apart since it doesn't know what's behind yet.
+pub fn march_segment(w: &World, r: Ray, end: f64, m: &Medium, container: Option<usize>) -> (Color, Color) {
+    let end = end.min(m.max_distance);
+    if m.absorbs_only() {
+        return (color(0.0, 0.0, 0.0), exp_color(m.extinction() * -end));
+    }
+
+    let steps = m.steps.max(1);
+    let dt = end / steps as f64;
+    let extinction = m.extinction();
+    let mut result = color(0.0, 0.0, 0.0);
+    let mut transmittance = color(1.0, 1.0, 1.0);
+
+    for i in 0..steps {
+        let x = r.position((i as f64 + 0.5) * dt);
+        let d = m.density_at(x);
+        if d <= 0.0 {
+            continue;
+        }
+
+        let source = m.scattering * in_scattered(w, r, x, m, container) * d + m.emission * d;
+        let sigma = extinction * d;
+        let step = exp_color(sigma * -dt);
+
+        // exact integral over the step for a constant source
+        result = result + transmittance * source * color(fraction(sigma.r, step.r, dt),
+                                                         fraction(sigma.g, step.g, dt),
+                                                         fraction(sigma.b, step.b, dt));
+        transmittance = transmittance * step;
+    }
+
+    (result, transmittance)
+}
+
+// light from the point light or spotlight arriving at x and turned
+// towards the eye - outside a spotlight's cone there's nothing to scatter
+fn in_scattered(w: &World, r: Ray, x: Tuple, m: &Medium, container: Option<usize>) -> Color {
+    let light = match &w.light {
+        Some(l) => l,
+        None    => return color(0.0, 0.0, 0.0),
+    };
+    let v = light.position - x;
+    let distance = v.mag();
+    let direction = v.normal();
+    let arriving = light.intensity_at(x);
+    if is_black(arriving) {
+        return arriving;
+    }
+
+    // anything but the container in the way blocks the light, and only
+    // the stretch inside the container is attenuated
+    record(|s| s.shadow_rays += 1);
+    let xs = w.intersect(ray(x, direction, 0));
+    let mut travel = distance;
+    for i in xs.iter().filter(|i| i.t >= 0.0 && i.t < distance) {
+        if Some(i.object) == container {
+            travel = travel.min(i.t);
+        } else {
+            return color(0.0, 0.0, 0.0);
+        }
+    }
+
+    arriving * m.transmittance(x, direction, travel) * m.phase(r.direction.normal().dot(&direction))
+}
+
+fn fraction(sigma: f64, step: f64, dt: f64) -> f64 {
+    if sigma.abs() < 1e-9 { dt } else { (1.0 - step) / sigma }
+}
+
+fn exp_color(c: Color) -> Color {
+    color(c.r.exp(), c.g.exp(), c.b.exp())
+}
+
+fn is_black(c: Color) -> bool {
+    c.r == 0.0 && c.g == 0.0 && c.b == 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::media::{medium, fog, absorbing, march, medium_at_origin, Density};
+    use crate::world::world;
+    use crate::color::color;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::lights::{point_light, spot_light};
+    use crate::spheres::sphere;
+    use crate::shapes::Shape;
+    use crate::materials::material;
+    use crate::transform::{translation, scaling};
+    use crate::equals::equals;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn uniform_transmittance_is_beer_lambert(){
+        let m = medium(color(0.5, 1.0, 0.0), color(0.5, 0.0, 0.0));
+        let t = m.transmittance(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0), 2.0);
+        assert!(t.equals(color((-2.0f64).exp(), (-2.0f64).exp(), 1.0)));
+    }
+
+    #[test]
+    fn marched_transmittance_matches_for_even_density(){
+        let mut m = medium(color(0.3, 0.3, 0.3), color(0.0, 0.0, 0.0));
+        m.density = Density::HeightFalloff { base: 0.0, falloff: 0.0 };
+        let t = m.transmittance(point(0.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), 3.0);
+        assert!(equals(t.r, (-0.9f64).exp()));
+    }
+
+    #[test]
+    fn absorbing_leaves_the_tint_after_one_unit(){
+        let m = absorbing(color(0.9, 0.5, 0.2));
+        let t = m.transmittance(point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0), 1.0);
+        assert!(t.equals(color(0.9, 0.5, 0.2)));
+    }
+
+    #[test]
+    fn phase_function_integrates_to_one(){
+        for g in [-0.5, 0.0, 0.7] {
+            let mut m = fog(1.0);
+            m.anisotropy = g;
+            let steps = 10000;
+            let mut sum = 0.0;
+            for i in 0..steps {
+                let theta = (i as f64 + 0.5) / steps as f64 * PI;
+                sum += m.phase(theta.cos()) * 2.0 * PI * theta.sin() * (PI / steps as f64);
+            }
+            assert!((sum - 1.0).abs() < 1e-3);
+        }
+
+        // forward scattering favours looking towards the light
+        let m = fog(1.0);
+        assert!(m.phase(1.0) > m.phase(-1.0));
+    }
+
+    #[test]
+    fn emission_only_medium_glows(){
+        // no extinction, so the glow just adds up along the ray
+        let w = world();
+        let mut m = medium(color(0.0, 0.0, 0.0), color(0.0, 0.0, 0.0));
+        m.emission = color(0.1, 0.2, 0.0);
+        let r = ray(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0), 0);
+        let c = march(&w, r, 5.0, &m, None, color(0.0, 0.0, 0.0));
+        assert!(c.equals(color(0.5, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn lit_fog_brightens_and_shadows_block_it(){
+        let mut w = world();
+        w.light = Some(point_light(point(0.0, 10.0, 0.0), color(1.0, 1.0, 1.0)));
+        let m = medium(color(0.0, 0.0, 0.0), color(0.1, 0.1, 0.1));
+        let r = ray(point(-5.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), 0);
+        let lit = march(&w, r, 10.0, &m, None, color(0.0, 0.0, 0.0));
+        assert!(lit.r > 0.0);
+
+        // a big blocker over the light path
+        let mut s = sphere();
+        s.set_transform(translation(0.0, 5.0, 0.0).mult(&scaling(20.0, 1.0, 20.0)));
+        w.add_object(Box::new(s));
+        let dark = march(&w, r, 10.0, &m, None, color(0.0, 0.0, 0.0));
+        assert!(equals(dark.r, 0.0));
+    }
+
+    #[test]
+    fn spotlights_only_light_fog_inside_their_cone(){
+        let mut w = world();
+        w.light = Some(spot_light(point(0.0, 10.0, 0.0), vector(0.0, -1.0, 0.0),
+                                  PI / 16.0, PI / 12.0, color(1.0, 1.0, 1.0)));
+        let m = medium(color(0.0, 0.0, 0.0), color(0.1, 0.1, 0.1));
+
+        // crossing the beam picks up light, running alongside it doesn't
+        let across = ray(point(-5.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), 0);
+        assert!(march(&w, across, 10.0, &m, None, color(0.0, 0.0, 0.0)).r > 0.0);
+        let beside = ray(point(-5.0, 0.0, -5.0), vector(1.0, 0.0, 0.0), 0);
+        assert!(equals(march(&w, beside, 10.0, &m, None, color(0.0, 0.0, 0.0)).r, 0.0));
+    }
+
+    #[test]
+    fn finding_the_medium_a_ray_starts_in(){
+        let mut w = world();
+        let mut s = sphere();
+        let mut mat = material();
+        mat.medium = Some(absorbing(color(0.5, 0.5, 0.5)));
+        s.set_material(mat);
+        w.add_object(Box::new(s));
+
+        let inside = ray(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0), 0);
+        let xs = w.intersect(inside);
+        let (_, container) = medium_at_origin(&w, &xs).unwrap();
+        assert_eq!(container, Some(0));
+
+        let outside = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let xs = w.intersect(outside);
+        assert!(medium_at_origin(&w, &xs).is_none());
+
+        w.fog = Some(fog(0.1));
+        let (_, container) = medium_at_origin(&w, &xs).unwrap();
+        assert_eq!(container, None);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::color::{Color, color};
+use crate::tuple::Tuple;
+use crate::sampling::orthonormal_basis;
+use std::f64::consts::PI;
+
+// physically based alternative to Phong - GGX (Trowbridge-Reitz) normal
+// distribution, Smith masking-shadowing and Fresnel. Set on a Material
+// to switch it over; the material's color (or pattern) is the base color
+// and diffuse / specular / shininess are ignored.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Microfacet {
+    pub roughness: f64,     // 0 polished - 1 chalky
+    pub metallic: f64,      // 0 dielectric - 1 metal, in between blends
+    // complex index of refraction (eta, k) per channel for real metals -
+    // when set it replaces the base color as the metal's reflectance
+    pub conductor: Option<(Color, Color)>,
+}
+
+impl Microfacet {
+    fn alpha(&self) -> f64 {
+        // squaring makes roughness feel more linear; the floor keeps
+        // D finite for perfectly smooth surfaces
+        (self.roughness * self.roughness).max(1e-4)
+    }
+
+    // reflectance at a given cosine between the view and half vectors
+    pub fn fresnel(&self, base: Color, cos: f64) -> Color {
+        let dielectric = schlick_fresnel(cos, color(0.04, 0.04, 0.04));
+        let metal = match self.conductor {
+            Some((eta, k)) => conductor_fresnel(cos, eta, k),
+            None           => schlick_fresnel(cos, base),
+        };
+        dielectric * (1.0 - self.metallic) + metal * self.metallic
+    }
+
+    // base color left for the diffuse lobe - metals have none
+    pub fn diffuse_albedo(&self, base: Color) -> Color {
+        base * (1.0 - self.metallic)
+    }
+
+    // diffuse + specular from a light of the given intensity, scaled like
+    // Phong's (no 1/pi on diffuse) so a rough white dielectric comes out
+    // about as bright as the default material does
+    pub fn shade(&self, base: Color, intensity: Color, lightv: Tuple, eyev: Tuple, normalv: Tuple) -> (Color, Color) {
+        let black = color(0.0, 0.0, 0.0);
+        let n_dot_l = normalv.dot(&lightv);
+        let n_dot_v = normalv.dot(&eyev);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return (black, black);
+        }
+
+        let halfway = (lightv + eyev).normal();
+        let n_dot_h = normalv.dot(&halfway).max(0.0);
+        let v_dot_h = eyev.dot(&halfway).max(0.0);
+
+        let alpha = self.alpha();
+        let f = self.fresnel(base, v_dot_h);
+        let d = ggx_distribution(n_dot_h, alpha);
+        let g = smith_g1(n_dot_l, alpha) * smith_g1(n_dot_v, alpha);
+
+        let kd = (color(1.0, 1.0, 1.0) - f) * (1.0 - self.metallic);
+        let diffuse = base * kd * intensity * n_dot_l;
+        let specular = f * intensity * (PI * d * g / (4.0 * n_dot_v));
+        (diffuse, specular)
+    }
+
+    // picks a reflected direction by sampling the half vector from D, and
+    // returns it with the throughput weight F * G * (v.h) / (n.v * n.h).
+    // None when the direction ends up below the surface.
+    pub fn sample(&self, base: Color, eyev: Tuple, normalv: Tuple, u1: f64, u2: f64) -> Option<(Tuple, Color)> {
+        let alpha = self.alpha();
+        let tan2 = alpha * alpha * u1 / (1.0 - u1).max(1e-12);
+        let cos_theta = 1.0 / (1.0 + tan2).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let (t, b) = orthonormal_basis(normalv);
+        let halfway = (t * (sin_theta * phi.cos()) +
+                       b * (sin_theta * phi.sin()) +
+                       normalv * cos_theta).normal();
+        let direction = (-eyev).reflect(&halfway);
+
+        let n_dot_l = normalv.dot(&direction);
+        let n_dot_v = normalv.dot(&eyev);
+        let v_dot_h = eyev.dot(&halfway);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 || v_dot_h <= 0.0 {
+            return None;
+        }
+
+        let g = smith_g1(n_dot_l, alpha) * smith_g1(n_dot_v, alpha);
+        let weight = self.fresnel(base, v_dot_h) * (g * v_dot_h / (n_dot_v * cos_theta));
+        Some((direction, weight))
+    }
+}
+
+pub fn microfacet(roughness: f64, metallic: f64) -> Microfacet {
+    Microfacet { roughness, metallic, conductor: None }
+}
+
+// measured (eta, k) at roughly 650 / 550 / 450 nm
+pub fn gold(roughness: f64) -> Microfacet {
+    metal(roughness, color(0.143, 0.374, 1.442), color(3.983, 2.385, 1.603))
+}
+
+pub fn silver(roughness: f64) -> Microfacet {
+    metal(roughness, color(0.155, 0.116, 0.138), color(4.828, 3.122, 2.147))
+}
+
+pub fn copper(roughness: f64) -> Microfacet {
+    metal(roughness, color(0.200, 0.924, 1.102), color(3.912, 2.452, 2.142))
+}
+
+pub fn aluminium(roughness: f64) -> Microfacet {
+    metal(roughness, color(1.657, 0.880, 0.521), color(9.224, 6.270, 4.837))
+}
+
+fn metal(roughness: f64, eta: Color, k: Color) -> Microfacet {
+    Microfacet { roughness, metallic: 1.0, conductor: Some((eta, k)) }
+}
+
+// fraction of microfacets facing along the half vector
+pub fn ggx_distribution(n_dot_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * denom * denom)
+}
+
+// Smith masking for one direction
+pub fn smith_g1(n_dot_v: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (a2 + (1.0 - a2) * n_dot_v * n_dot_v).sqrt())
+}
+
+pub fn schlick_fresnel(cos: f64, f0: Color) -> Color {
+    let factor = (1.0 - cos).clamp(0.0, 1.0).powf(5.0);
+    f0 + (color(1.0, 1.0, 1.0) - f0) * factor
+}
+
+// exact Fresnel reflectance for a conductor, unpolarised, per channel
+pub fn conductor_fresnel(cos: f64, eta: Color, k: Color) -> Color {
+    color(conductor_channel(cos, eta.r, k.r),
+          conductor_channel(cos, eta.g, k.g),
+          conductor_channel(cos, eta.b, k.b))
+}
+
+fn conductor_channel(cos: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos.clamp(0.0, 1.0).powf(2.0);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = ((a2b2 + t0) * 0.5).max(0.0).sqrt();
+
+    let t1 = a2b2 + cos2;
+    let t2 = 2.0 * a * cos2.sqrt();
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    (rs + rp) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::microfacet::{microfacet, gold, ggx_distribution, smith_g1,
+                            schlick_fresnel, conductor_fresnel};
+    use crate::color::color;
+    use crate::tuple::vector;
+    use crate::sampling::rng;
+    use crate::equals::equals;
+    use std::f64::consts::{PI, SQRT_2};
+
+    #[test]
+    fn ggx_integrates_to_one_over_projected_area(){
+        // integral of D(h) (n.h) over the hemisphere is 1
+        for alpha in [0.1, 0.5, 1.0] {
+            let steps = 20000;
+            let mut sum = 0.0;
+            for i in 0..steps {
+                let theta = (i as f64 + 0.5) / steps as f64 * PI / 2.0;
+                let c = theta.cos();
+                sum += ggx_distribution(c, alpha) * c * theta.sin() * 2.0 * PI * (PI / 2.0 / steps as f64);
+            }
+            assert!((sum - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn smith_masking_bounds(){
+        assert!(equals(smith_g1(1.0, 0.5), 1.0));
+        assert!(smith_g1(0.1, 0.5) < smith_g1(0.5, 0.5));
+        assert!(equals(smith_g1(0.3, 0.0), 1.0));
+    }
+
+    #[test]
+    fn schlick_goes_white_at_grazing(){
+        let f0 = color(0.04, 0.04, 0.04);
+        assert!(schlick_fresnel(1.0, f0).equals(f0));
+        assert!(schlick_fresnel(0.0, f0).equals(color(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn conductor_fresnel_at_normal_incidence(){
+        // ((n-1)^2 + k^2) / ((n+1)^2 + k^2)
+        let f = conductor_fresnel(1.0, color(0.143, 0.143, 0.143), color(3.983, 3.983, 3.983));
+        let expected = ((0.143f64 - 1.0).powf(2.0) + 3.983f64.powf(2.0)) /
+                       ((0.143f64 + 1.0).powf(2.0) + 3.983f64.powf(2.0));
+        assert!(equals(f.r, expected));
+
+        // and every metal is a mirror at grazing angles
+        let f = conductor_fresnel(0.0, color(0.2, 0.9, 1.1), color(3.9, 2.4, 2.1));
+        assert!(f.equals(color(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn gold_is_yellow(){
+        let g = gold(0.2);
+        let f = g.fresnel(color(1.0, 1.0, 1.0), 1.0);
+        assert!(f.r > f.g && f.g > f.b);
+    }
+
+    #[test]
+    fn rough_white_dielectric_is_mostly_diffuse(){
+        let mf = microfacet(1.0, 0.0);
+        let n = vector(0.0, 1.0, 0.0);
+        let (d, s) = mf.shade(color(1.0, 1.0, 1.0), color(1.0, 1.0, 1.0), n, n, n);
+        assert!(equals(d.r, 0.96));
+        assert!(s.r > 0.0 && s.r < 0.1);
+    }
+
+    #[test]
+    fn smooth_surfaces_have_sharp_highlights(){
+        let n = vector(0.0, 1.0, 0.0);
+        let lightv = vector(0.0, SQRT_2 / 2.0, -SQRT_2 / 2.0);
+        let mirror = vector(0.0, SQRT_2 / 2.0, SQRT_2 / 2.0);
+        let off = vector(0.5, 0.5, SQRT_2 / 2.0).normal();
+
+        let smooth = microfacet(0.1, 1.0);
+        let rough = microfacet(0.8, 1.0);
+        let base = color(1.0, 1.0, 1.0);
+        let white = color(1.0, 1.0, 1.0);
+        let (sd, s1) = smooth.shade(base, white, lightv, mirror, n);
+        let (_, s2) = smooth.shade(base, white, lightv, off, n);
+        let (_, r1) = rough.shade(base, white, lightv, mirror, n);
+
+        assert!(sd.equals(color(0.0, 0.0, 0.0)));   // metals have no diffuse
+        assert!(s1.r > 10.0 * s2.r);
+        assert!(s1.r > r1.r);
+    }
+
+    #[test]
+    fn light_below_the_surface_gives_nothing(){
+        let mf = microfacet(0.5, 0.0);
+        let n = vector(0.0, 1.0, 0.0);
+        let (d, s) = mf.shade(color(1.0, 1.0, 1.0), color(1.0, 1.0, 1.0), -n, n, n);
+        assert!(d.equals(color(0.0, 0.0, 0.0)) && s.equals(color(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn sampled_directions_stay_above_the_surface(){
+        let mf = microfacet(0.4, 1.0);
+        let n = vector(0.0, 1.0, 0.0);
+        let eye = vector(0.3, 1.0, 0.0).normal();
+        let mut r = rng(4);
+        let mut sum = 0.0;
+        let count = 4000;
+        for _ in 0..count {
+            if let Some((dir, w)) = mf.sample(color(1.0, 1.0, 1.0), eye, n, r.next_f64(), r.next_f64()) {
+                assert!(dir.dot(&n) > 0.0);
+                assert!(equals(dir.mag(), 1.0));
+                sum += w.r;
+            }
+        }
+        // a white metal loses a little energy to masking, but not much
+        let albedo = sum / count as f64;
+        assert!(albedo > 0.8 && albedo <= 1.0);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::tuple::Tuple;
+
+// Ken Perlin's improved gradient noise. The permutation table is swapped
+// for an integer hash of the lattice point, so there's no table to build
+// and the noise doesn't repeat every 256 units.
+pub fn perlin(p: Tuple) -> f64 {
+    let (xi, yi, zi) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (p.x - xi, p.y - yi, p.z - zi);
+    let (xi, yi, zi) = (xi as i64, yi as i64, zi as i64);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let g = |dx: i64, dy: i64, dz: i64| {
+        grad(hash(xi + dx, yi + dy, zi + dz), x - dx as f64, y - dy as f64, z - dz as f64)
+    };
+
+    lerp(w, lerp(v, lerp(u, g(0, 0, 0), g(1, 0, 0)),
+                    lerp(u, g(0, 1, 0), g(1, 1, 0))),
+            lerp(v, lerp(u, g(0, 0, 1), g(1, 0, 1)),
+                    lerp(u, g(0, 1, 1), g(1, 1, 1))))
+}
+
+// several octaves of noise added up, each at double the frequency and
+// half the strength of the last
+pub fn fbm(p: Tuple, octaves: usize) -> f64 {
+    let mut total = 0.0;
+    let mut frequency = 1.0;
+    let mut amplitude = 1.0;
+    for _ in 0..octaves {
+        total += perlin(p * frequency) * amplitude;
+        frequency *= 2.0;
+        amplitude *= 0.5;
+    }
+    total
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn hash(x: i64, y: i64, z: i64) -> u64 {
+    let mut h = (x as u64).wrapping_mul(0x8DA6_B343)
+              ^ (y as u64).wrapping_mul(0xD816_3841)
+              ^ (z as u64).wrapping_mul(0xCB1A_B31F);
+    h ^= h >> 29;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^ (h >> 32)
+}
+
+// dot product with one of the 12 cube-edge gradients
+fn grad(h: u64, x: f64, y: f64, z: f64) -> f64 {
+    let h = h & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::noise::{perlin, fbm};
+    use crate::tuple::point;
+    use crate::equals::equals;
+
+    #[test]
+    fn noise_is_zero_on_lattice_points(){
+        assert!(equals(perlin(point(0.0, 0.0, 0.0)), 0.0));
+        assert!(equals(perlin(point(3.0, -2.0, 7.0)), 0.0));
+    }
+
+    #[test]
+    fn noise_is_smooth_and_bounded(){
+        let mut any_nonzero = false;
+        for i in 0..200 {
+            let p = point(i as f64 * 0.37, i as f64 * 0.11 - 3.0, i as f64 * 0.23);
+            let n = perlin(p);
+            assert!(n.abs() <= 1.5);
+            any_nonzero |= n.abs() > 0.1;
+
+            let nearby = perlin(point(p.x + 1e-4, p.y, p.z));
+            assert!((n - nearby).abs() < 1e-3);
+        }
+        assert!(any_nonzero);
+    }
+
+    #[test]
+    fn noise_is_repeatable(){
+        let p = point(1.3, 2.7, -0.4);
+        assert_eq!(perlin(p), perlin(p));
+        assert_eq!(fbm(p, 4), fbm(p, 4));
+        assert!(equals(fbm(p, 1), perlin(p)));
+    }
+}
@@ -0,0 +1,225 @@
+use std::f64::consts::PI;
+
+// coefficients this small are treated as zero when deciding the
+// degree of a polynomial - EPSILON from the equals module is far
+// too coarse for this job
+const TINY: f64 = 1e-12;
+
+// all of the solvers take coefficients highest power first and return
+// the real roots in ascending order (repeated roots are repeated)
+
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < TINY {
+        if b.abs() < TINY {
+            return vec!();
+        }
+        return vec![-c / b];
+    }
+
+    let mut disc = b * b - 4.0 * a * c;
+
+    // tangent rays land right on zero, and rounding can push them
+    // slightly negative - treat those as a double root
+    let scale = (b * b).max((4.0 * a * c).abs());
+    if disc < 0.0 && disc > -TINY * scale.max(1.0) {
+        disc = 0.0;
+    }
+    if disc < 0.0 {
+        return vec!();
+    }
+
+    // avoid cancellation by never subtracting nearly equal values
+    let q = -0.5 * (b + b.signum() * disc.sqrt());
+    let mut roots = if q.abs() < TINY {
+        vec![-b / (2.0 * a), -b / (2.0 * a)]
+    } else {
+        vec![q / a, c / q]
+    };
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a.abs() < TINY {
+        return solve_quadratic(b, c, d);
+    }
+
+    // normalize to x^3 + a2 x^2 + a1 x + a0
+    let a2 = b / a;
+    let a1 = c / a;
+    let a0 = d / a;
+
+    let q = (a2 * a2 - 3.0 * a1) / 9.0;
+    let r = (2.0 * a2.powi(3) - 9.0 * a2 * a1 + 27.0 * a0) / 54.0;
+    let shift = a2 / 3.0;
+
+    let mut roots;
+    if r * r < q.powi(3) {
+        // three real roots - trigonometric form
+        let theta = (r / q.powi(3).sqrt()).clamp(-1.0, 1.0).acos();
+        let m = -2.0 * q.sqrt();
+        roots = vec![m * (theta / 3.0).cos() - shift,
+                     m * ((theta + 2.0 * PI) / 3.0).cos() - shift,
+                     m * ((theta - 2.0 * PI) / 3.0).cos() - shift];
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q.powi(3)).sqrt()).cbrt();
+        let big_b = if big_a.abs() < TINY { 0.0 } else { q / big_a };
+        roots = vec![(big_a + big_b) - shift];
+    }
+
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+// Ferrari's method via the resolvent cubic, followed by a couple of
+// Newton steps against the original polynomial to win back the
+// precision that the reduction loses
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a.abs() < TINY {
+        return solve_cubic(b, c, d, e);
+    }
+
+    // normalize to x^4 + a3 x^3 + a2 x^2 + a1 x + a0
+    let a3 = b / a;
+    let a2 = c / a;
+    let a1 = d / a;
+    let a0 = e / a;
+
+    // substitute x = y - a3/4 to get the depressed quartic
+    // y^4 + p y^2 + q y + r
+    let shift = a3 / 4.0;
+    let p = a2 - 6.0 * shift * shift;
+    let q = a1 - 2.0 * a2 * shift + 8.0 * shift.powi(3);
+    let r = a0 - a1 * shift + a2 * shift * shift - 3.0 * shift.powi(4);
+
+    let mut ys = vec!();
+    if q.abs() < TINY {
+        // biquadratic - solve for y^2 and take square roots
+        for y2 in solve_quadratic(1.0, p, r) {
+            if y2 >= 0.0 {
+                let y = y2.sqrt();
+                ys.push(-y);
+                ys.push(y);
+            } else if y2 > -TINY {
+                ys.push(0.0);
+                ys.push(0.0);
+            }
+        }
+    } else {
+        // the largest root of the resolvent is always positive when q != 0
+        let zs = solve_cubic(1.0, 2.0 * p, p * p - 4.0 * r, -q * q);
+        let z = zs.iter().cloned().fold(0.0, f64::max);
+        if z <= 0.0 {
+            return vec!();
+        }
+        let u = z.sqrt();
+        let s = (p + z) / 2.0;
+        let t = q / (2.0 * u);
+        ys.append(&mut solve_quadratic(1.0,  u, s - t));
+        ys.append(&mut solve_quadratic(1.0, -u, s + t));
+    }
+
+    let mut roots: Vec<f64> = ys.iter()
+                                .map(|y| polish(y - shift, a3, a2, a1, a0))
+                                .collect();
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+fn polish(mut x: f64, a3: f64, a2: f64, a1: f64, a0: f64) -> f64 {
+    for _ in 0..3 {
+        let f = (((x + a3) * x + a2) * x + a1) * x + a0;
+        let df = ((4.0 * x + 3.0 * a3) * x + 2.0 * a2) * x + a1;
+        // flat spot means a repeated root - Newton won't help there
+        if df.abs() < TINY {
+            break;
+        }
+        let next = x - f / df;
+        if !next.is_finite() {
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::numeric::{solve_quadratic, solve_cubic, solve_quartic};
+    use crate::equals::equals;
+
+    fn all_equal(a: &[f64], b: &[f64]) -> bool {
+        a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| equals(*x, *y))
+    }
+
+    #[test]
+    fn quadratic_with_two_roots(){
+        let xs = solve_quadratic(1.0, -3.0, 2.0);
+        assert!(all_equal(&xs, &[1.0, 2.0]));
+    }
+
+    #[test]
+    fn quadratic_with_double_root(){
+        let xs = solve_quadratic(1.0, -4.0, 4.0);
+        assert!(all_equal(&xs, &[2.0, 2.0]));
+    }
+
+    #[test]
+    fn quadratic_without_real_roots(){
+        assert_eq!(solve_quadratic(1.0, 0.0, 1.0).len(), 0);
+    }
+
+    #[test]
+    fn degenerate_quadratic_is_linear(){
+        let xs = solve_quadratic(0.0, 2.0, -4.0);
+        assert!(all_equal(&xs, &[2.0]));
+    }
+
+    #[test]
+    fn cubic_with_three_roots(){
+        // (x + 1)(x - 2)(x - 3)
+        let xs = solve_cubic(1.0, -4.0, 1.0, 6.0);
+        assert!(all_equal(&xs, &[-1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn cubic_with_one_root(){
+        // (x - 1)(x^2 + 1)
+        let xs = solve_cubic(1.0, -1.0, 1.0, -1.0);
+        assert!(all_equal(&xs, &[1.0]));
+    }
+
+    #[test]
+    fn quartic_with_four_roots(){
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let xs = solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0);
+        assert!(all_equal(&xs, &[1.0, 2.0, 3.0, 4.0]));
+    }
+
+    #[test]
+    fn quartic_with_two_roots(){
+        // (x + 2)(x - 5)(x^2 + 1)
+        let xs = solve_quartic(1.0, -3.0, -9.0, -3.0, -10.0);
+        assert!(all_equal(&xs, &[-2.0, 5.0]));
+    }
+
+    #[test]
+    fn quartic_without_real_roots(){
+        // (x^2 + 1)(x^2 + 4)
+        assert_eq!(solve_quartic(1.0, 0.0, 5.0, 0.0, 4.0).len(), 0);
+    }
+
+    #[test]
+    fn quartic_with_repeated_roots(){
+        // (x - 4)^2 (x - 6)^2
+        let xs = solve_quartic(1.0, -20.0, 148.0, -480.0, 576.0);
+        assert!(all_equal(&xs, &[4.0, 4.0, 6.0, 6.0]));
+    }
+
+    #[test]
+    fn quartic_with_scaled_coefficients(){
+        // 3 (x + 0.5)(x - 0.25)(x - 7)(x - 8)
+        let xs = solve_quartic(3.0, -44.25, 156.375, 47.625, -21.0);
+        assert!(all_equal(&xs, &[-0.5, 0.25, 7.0, 8.0]));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::world::World;
+use crate::tuple::Tuple;
+use crate::rays::ray;
+use crate::sampling::{Rng, cosine_hemisphere};
+use crate::stats::record;
+
+// ambient occlusion settings - `samples` rays are shot into the hemisphere
+// around the normal, and anything closer than `distance` counts as a blocker
+#[derive(Debug,Clone,PartialEq)]
+pub struct Occlusion {
+    pub samples: usize,
+    pub distance: f64,
+    pub seed: u64,
+}
+
+pub fn occlusion() -> Occlusion {
+    Occlusion {
+        samples: 16,
+        distance: 1.0,
+        seed: 0,
+    }
+}
+
+// fraction of the hemisphere above p that's open: 1.0 for nothing
+// nearby, 0.0 for completely buried. p should already be nudged off the
+// surface (over_point) so the rays don't hit what they start on.
+pub fn occlusion_at(w: &World, p: Tuple, normal: Tuple, ao: &Occlusion, rng: &mut Rng) -> f64 {
+    if ao.samples == 0 {
+        return 1.0;
+    }
+
+    let mut blocked = 0;
+    for _ in 0..ao.samples {
+        let direction = cosine_hemisphere(normal, rng.next_f64(), rng.next_f64());
+        record(|s| s.shadow_rays += 1);
+        let xs = w.intersect(ray(p, direction, 0));
+        if xs.iter().any(|i| i.t >= 0.0 && i.t < ao.distance) {
+            blocked += 1;
+        }
+    }
+
+    1.0 - blocked as f64 / ao.samples as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::occlusion::{occlusion, occlusion_at};
+    use crate::sampling::rng;
+    use crate::world::world;
+    use crate::planes::plane;
+    use crate::shapes::Shape;
+    use crate::tuple::{point, vector};
+    use crate::transform::translation;
+    use crate::equals::equals;
+
+    #[test]
+    fn open_plane_is_not_occluded(){
+        let mut w = world();
+        w.add_object(Box::new(plane()));
+
+        let ao = occlusion();
+        let a = occlusion_at(&w, point(0.0, 0.00001, 0.0), vector(0.0, 1.0, 0.0), &ao, &mut rng(1));
+        assert!(equals(a, 1.0));
+    }
+
+    #[test]
+    fn ceiling_blocks_depending_on_distance(){
+        let mut w = world();
+        w.add_object(Box::new(plane()));
+        let mut ceiling = plane();
+        ceiling.set_transform(translation(0.0, 0.5, 0.0));
+        w.add_object(Box::new(ceiling));
+
+        let p = point(0.0, 0.00001, 0.0);
+        let n = vector(0.0, 1.0, 0.0);
+        let mut ao = occlusion();
+        ao.samples = 64;
+        ao.distance = 100.0;
+        let a = occlusion_at(&w, p, n, &ao, &mut rng(1));
+        assert!(equals(a, 0.0));
+
+        // the ceiling is out of reach
+        ao.distance = 0.4;
+        let a = occlusion_at(&w, p, n, &ao, &mut rng(1));
+        assert!(equals(a, 1.0));
+
+        // partially in reach - only the steeper rays get there
+        ao.distance = 1.0;
+        let a = occlusion_at(&w, p, n, &ao, &mut rng(1));
+        assert!(a > 0.0 && a < 1.0);
+    }
+
+    #[test]
+    fn zero_samples_means_no_occlusion(){
+        let mut w = world();
+        w.add_object(Box::new(plane()));
+        let mut ao = occlusion();
+        ao.samples = 0;
+        let a = occlusion_at(&w, point(0.0, -1.0, 0.0), vector(0.0, 1.0, 0.0), &ao, &mut rng(1));
+        assert!(equals(a, 1.0));
+    }
+}
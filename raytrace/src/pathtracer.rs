@@ -0,0 +1,591 @@
+use crate::world::World;
+use crate::camera::Camera;
+use crate::canvas::{Canvas, canvas};
+use crate::color::{Color, color};
+use crate::rays::{Ray, ray};
+use crate::tuple::Tuple;
+use crate::intersections::{Intersection, Computations, prepare_computations, schlick};
+use crate::materials::{lighting, surface_color};
+use crate::sampling::{Rng, rng, pixel_seed, cosine_hemisphere};
+use crate::media::{medium_at_origin, march_segment};
+use crate::equals::EPSILON;
+use crate::stats::record;
+use std::f64::consts::PI;
+
+// Monte Carlo alternative to World::color_at. Direct light is sampled
+// at every bounce with the usual Phong lighting (minus the flat ambient
+// term) plus one sample on an emissive shape, and indirect light comes
+// from following one random bounce per hit - cosine weighted for
+// diffuse, mirror or refraction otherwise.
+#[derive(Debug,Clone)]
+pub struct PathTracer {
+    pub samples: usize,         // rays per pixel
+    pub max_depth: usize,       // hard limit on bounces
+    pub roulette_depth: usize,  // bounces before russian roulette starts
+    pub seed: u64,
+}
+
+impl PathTracer {
+    // one ray on its own - renders should find the emitters once up front
+    // and call trace instead
+    pub fn color_at(&self, w: &World, r: Ray, rng: &mut Rng) -> Color {
+        self.trace(w, r, &emitters(w), rng)
+    }
+
+    // lights is what emitters(w) returns
+    pub fn trace(&self, w: &World, r: Ray, lights: &[(usize, f64)], rng: &mut Rng) -> Color {
+        let mut radiance = color(0.0, 0.0, 0.0);
+        let mut throughput = color(1.0, 1.0, 1.0);
+        let mut current = r;
+        let mut after_diffuse = false;
+
+        for depth in 0..self.max_depth {
+            let xs = w.intersect(current);
+
+            // fog, or a shape's medium, between here and the next hit -
+            // what it scatters in is added now, and everything further
+            // along is seen through it
+            if let Some((m, container)) = medium_at_origin(w, &xs) {
+                let end = first_hit(&xs).map_or(f64::INFINITY, |h| h.t);
+                let (scattered, transmittance) = march_segment(w, current, end, m, container);
+                radiance = radiance + throughput * scattered;
+                throughput = throughput * transmittance;
+            }
+
+            let hit = match first_hit(&xs) {
+                Some(h) => h,
+                None    => {
+                    radiance = radiance + throughput * w.background_color(current);
+                    break;
+                },
+            };
+            let comps = prepare_computations(hit, current, w, &xs);
+
+            // lights we sample directly were already counted at the
+            // previous diffuse bounce, so don't count them twice
+            let sampled = lights.iter().any(|(i, _)| *i == comps.object);
+            if !(after_diffuse && sampled) {
+                radiance = radiance + throughput * w.get_object(comps.object).get_material().emissive;
+            }
+
+            radiance = radiance + throughput * self.direct_light(w, &comps);
+            radiance = radiance + throughput * self.sample_emitters(w, &comps, lights, rng);
+
+            let (next, weight, diffuse) = match self.scatter(w, &comps, depth, rng) {
+                Some(s) => s,
+                None    => break,
+            };
+            throughput = throughput * weight;
+            after_diffuse = diffuse;
+
+            // past roulette_depth, dim paths get killed off at random and
+            // the survivors boosted so the average stays the same
+            if depth + 1 >= self.roulette_depth {
+                let p = throughput.r.max(throughput.g).max(throughput.b).clamp(0.05, 0.95);
+                if rng.next_f64() >= p {
+                    break;
+                }
+                throughput = throughput * (1.0 / p);
+            }
+
+            // anything heading into the surface went through it
+            if next.direction.dot(&comps.normalv) < 0.0 {
+                record(|s| s.refraction_rays += 1);
+            } else {
+                record(|s| s.reflection_rays += 1);
+            }
+            record(|s| s.max_depth = s.max_depth.max(next.count));
+            current = next;
+        }
+
+        radiance
+    }
+
+    // Phong lighting from the point light, without the ambient term -
+    // the bounces take care of that now
+    fn direct_light(&self, w: &World, comps: &Computations) -> Color {
+        let l = match &w.light {
+            Some(l) => l,
+            None    => return color(0.0, 0.0, 0.0),
+        };
+        if w.is_shadowed(comps.over_point) {
+            return color(0.0, 0.0, 0.0);
+        }
+
+        let object = w.get_object(comps.object);
+        let mut m = object.get_material().clone();
+        m.ambient = 0.0;
+        lighting(m, object, l, comps.point, comps.eyev, comps.normalv, false, w)
+    }
+
+    // light from one point picked on one emissive shape, with brighter and
+    // bigger emitters picked more often. Only the diffuse part of the
+    // material responds to these.
+    fn sample_emitters(&self, w: &World, comps: &Computations, lights: &[(usize, f64)], rng: &mut Rng) -> Color {
+        let black = color(0.0, 0.0, 0.0);
+        let total: f64 = lights.iter().map(|(_, p)| p).sum();
+        if total <= 0.0 {
+            return black;
+        }
+
+        let albedo = diffuse_albedo(w, comps);
+
+        let mut pick = rng.next_f64() * total;
+        let mut chosen = lights[lights.len() - 1];
+        for l in lights {
+            if pick < l.1 {
+                chosen = *l;
+                break;
+            }
+            pick -= l.1;
+        }
+        let (index, power) = chosen;
+        let u1 = rng.next_f64();
+        let u2 = rng.next_f64();
+        if index == comps.object {
+            return black;
+        }
+
+        let light = w.get_object(index);
+        let target = match light.sample_point(u1, u2) {
+            Some(p) => p,
+            None    => return black,
+        };
+        let to_light = target - comps.over_point;
+        let distance = to_light.mag();
+        let direction = to_light.normal();
+
+        let cos_here = direction.dot(&comps.normalv);
+        let cos_there = light.normal_at(target).dot(&direction).abs();
+        if cos_here <= 0.0 || cos_there <= 0.0 {
+            return black;
+        }
+
+        // anything in the way, including the near side of the light itself
+        record(|s| s.shadow_rays += 1);
+        let xs = w.intersect(ray(comps.over_point, direction, 0));
+        if xs.iter().any(|i| i.t >= 0.0 && i.t < distance - EPSILON) {
+            return black;
+        }
+
+        // converting the pick from "per unit area" to "per unit solid angle"
+        let pdf = (power / total) / light.area();
+        let geometry = cos_here * cos_there / distance.powf(2.0);
+        light.get_material().emissive * albedo * (geometry / (PI * pdf))
+    }
+
+    // picks one of diffuse / glossy / mirror / refraction in proportion to how much
+    // each contributes, and returns the new ray, the throughput weight and
+    // whether it was a diffuse bounce
+    fn scatter(&self, w: &World, comps: &Computations, depth: usize, rng: &mut Rng) -> Option<(Ray, Color, bool)> {
+        let object = w.get_object(comps.object);
+        let m = object.get_material();
+
+        let albedo = diffuse_albedo(w, comps);
+        let base = surface_color(m, object.as_ref(), comps.point, w);
+        let glossy = match &m.microfacet {
+            Some(mf) => {
+                let f = mf.fresnel(base, comps.eyev.dot(&comps.normalv));
+                f.r.max(f.g).max(f.b)
+            },
+            None => 0.0,
+        };
+        let (reflective, transparency) = if m.reflective > 0.0 && m.transparency > 0.0 {
+            let reflectance = schlick(comps.clone());
+            (reflectance, 1.0 - reflectance)
+        } else {
+            (m.reflective, m.transparency)
+        };
+        let transparency = if refract_direction(comps).is_some() { transparency } else { 0.0 };
+
+        let diffuse = albedo.r.max(albedo.g).max(albedo.b).max(0.0);
+        let total = diffuse + glossy + reflective + transparency;
+        if total <= 0.0 {
+            return None;
+        }
+
+        let choice = rng.next_f64() * total;
+        if choice < diffuse {
+            let direction = cosine_hemisphere(comps.normalv, rng.next_f64(), rng.next_f64());
+            Some((ray(comps.over_point, direction, depth + 1), albedo * (total / diffuse), true))
+        } else if choice < diffuse + glossy {
+            let mf = m.microfacet.as_ref()?;
+            let (direction, weight) = mf.sample(base, comps.eyev, comps.normalv, rng.next_f64(), rng.next_f64())?;
+            Some((ray(comps.over_point, direction, depth + 1), weight * (total / glossy), false))
+        } else if choice < diffuse + glossy + reflective {
+            Some((ray(comps.over_point, comps.reflectv, depth + 1), color(total, total, total), false))
+        } else {
+            let direction = refract_direction(comps)?;
+            Some((ray(comps.under_point, direction, depth + 1), color(total, total, total), false))
+        }
+    }
+
+    // averages `samples` jittered rays per pixel. Every pixel gets its own
+    // seed, so the result only depends on self.seed
+    pub fn render(&self, c: &Camera, w: &World) -> Canvas {
+        let width: usize = c.image_width().try_into().unwrap();
+        let height: usize = c.vsize.try_into().unwrap();
+        let mut image = canvas(width, height);
+        let samples = self.samples.max(1);
+        let lights = emitters(w);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut r = rng(pixel_seed(self.seed, x, y));
+                let mut sum = color(0.0, 0.0, 0.0);
+                for _ in 0..samples {
+                    let px = x as f64 + r.next_f64();
+                    let py = y as f64 + r.next_f64();
+                    if let Some(ray) = c.ray_for_point(px, py) {
+                        record(|s| s.primary_rays += 1);
+                        sum = sum + self.trace(w, ray, &lights, &mut r);
+                    }
+                }
+                image.write_pixel(x, y, sum * (1.0 / samples as f64));
+            }
+        }
+
+        image
+    }
+}
+
+pub fn path_tracer() -> PathTracer {
+    PathTracer {
+        samples: 16,
+        max_depth: 8,
+        roulette_depth: 3,
+        seed: 0,
+    }
+}
+
+// color reflected by the diffuse part of the material
+fn diffuse_albedo(w: &World, comps: &Computations) -> Color {
+    let object = w.get_object(comps.object);
+    let m = object.get_material();
+    let base = surface_color(m, object.as_ref(), comps.point, w);
+    match &m.microfacet {
+        Some(mf) => mf.diffuse_albedo(base),
+        None     => base * m.diffuse,
+    }
+}
+
+// top level shapes that glow and know how to pick points on themselves,
+// with how much light each gives off overall. Emissive shapes that can't
+// be sampled (planes, cubes, anything in a group) still light the scene,
+// just only when a bounce happens to hit them.
+pub fn emitters(w: &World) -> Vec<(usize, f64)> {
+    let mut result = vec!();
+    for i in 0..w.get_objects_len() {
+        let o = w.get_object(i);
+        let e = o.get_material().emissive;
+        let brightness = e.r.max(e.g).max(e.b);
+        let area = o.area();
+        if brightness > 0.0 && area > 0.0 && o.local_sample(0.5, 0.5).is_some() {
+            result.push((i, brightness * area));
+        }
+    }
+    result
+}
+
+fn first_hit(xs: &[Intersection]) -> Option<Intersection> {
+    xs.iter().find(|i| i.t >= 0.0).copied()
+}
+
+// same math as World::refracted_color, None on total internal reflection
+fn refract_direction(comps: &Computations) -> Option<Tuple> {
+    let n_ratio = comps.n1 / comps.n2;
+    let cos_i = comps.eyev.dot(&comps.normalv);
+    let sin2_t = n_ratio.powf(2.0) * (1.0 - cos_i.powf(2.0));
+    if sin2_t > 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(comps.normalv * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pathtracer::{path_tracer, emitters};
+    use crate::groups::group;
+    use crate::sampling::rng;
+    use crate::world::world;
+    use crate::lights::point_light;
+    use crate::planes::plane;
+    use crate::spheres::sphere;
+    use crate::disks::disk;
+    use crate::backgrounds::solid_background;
+    use crate::microfacet::microfacet;
+    use crate::media::fog;
+    use crate::shapes::Shape;
+    use crate::materials::{Material, material};
+    use crate::color::{Color, color};
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::camera::camera;
+    use crate::transform::{translation, rotation_z};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn ray_that_misses_is_black(){
+        let w = world();
+        let pt = path_tracer();
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        assert!(pt.color_at(&w, r, &mut rng(1)).equals(color(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn lone_plane_only_gets_direct_light(){
+        // every bounce off the plane escapes, so what's left is Phong
+        // diffuse + specular with no ambient
+        let mut w = world();
+        w.light = Some(point_light(point(0.0, 10.0, 0.0), color(1.0, 1.0, 1.0)));
+        w.add_object(Box::new(plane()));
+
+        let pt = path_tracer();
+        let r = ray(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+        let mut g = rng(3);
+        for _ in 0..10 {
+            assert!(pt.color_at(&w, r, &mut g).equals(color(1.8, 1.8, 1.8)));
+        }
+    }
+
+    fn room_corner() -> crate::world::World {
+        let mut w = world();
+        w.light = Some(point_light(point(-5.0, 10.0, 0.0), color(1.0, 1.0, 1.0)));
+
+        let mut floor = plane();
+        let mut m = material();
+        m.specular = 0.0;
+        floor.set_material(m);
+        w.add_object(Box::new(floor));
+
+        let mut wall = plane();
+        wall.set_transform(translation(1.0, 0.0, 0.0).mult(&rotation_z(PI / 2.0)));
+        let mut m = material();
+        m.color = color(1.0, 0.0, 0.0);
+        m.specular = 0.0;
+        wall.set_material(m);
+        w.add_object(Box::new(wall));
+        w
+    }
+
+    #[test]
+    fn red_wall_bleeds_onto_white_floor(){
+        let w = room_corner();
+        let pt = path_tracer();
+        let r = ray(point(0.5, 1.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+
+        let mut g = rng(5);
+        let mut sum = color(0.0, 0.0, 0.0);
+        for _ in 0..400 {
+            sum = sum + pt.color_at(&w, r, &mut g);
+        }
+        let avg = sum * (1.0 / 400.0);
+
+        // direct light on the floor is white, the extra red is bounced
+        assert!(avg.r > avg.g + 0.1);
+        assert!((avg.g - avg.b).abs() < 0.05);
+    }
+
+    #[test]
+    fn depth_of_one_is_direct_light_only(){
+        let w = room_corner();
+        let mut pt = path_tracer();
+        pt.max_depth = 1;
+        let r = ray(point(0.5, 1.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+
+        let c = pt.color_at(&w, r, &mut rng(5));
+        assert!((c.r - c.g).abs() < 1e-9 && (c.g - c.b).abs() < 1e-9);
+    }
+
+    #[test]
+    fn render_fills_whole_canvas_and_is_repeatable(){
+        let w = room_corner();
+        let mut c = camera(6, 4, PI / 2.0);
+        c.transform = crate::transform::view_transform(point(-2.0, 2.0, 0.0),
+                                                       point(1.0, 0.0, 0.0),
+                                                       vector(0.0, 1.0, 0.0));
+        let mut pt = path_tracer();
+        pt.samples = 4;
+
+        let a = pt.render(&c, &w);
+        let b = pt.render(&c, &w);
+        assert_eq!(a.width, 6);
+        assert_eq!(a.height, 4);
+        assert!(a.pixels == b.pixels);
+
+        // the bottom right pixel is rendered too
+        assert!(a.pixel_at(5, 3).r > 0.0);
+
+        pt.seed = 9;
+        let d = pt.render(&c, &w);
+        assert!(a.pixels != d.pixels);
+    }
+
+    #[test]
+    fn render_counts_its_rays(){
+        let w = room_corner();
+        let mut c = camera(6, 4, PI / 2.0);
+        c.transform = crate::transform::view_transform(point(-2.0, 2.0, 0.0),
+                                                       point(1.0, 0.0, 0.0),
+                                                       vector(0.0, 1.0, 0.0));
+        let mut pt = path_tracer();
+        pt.samples = 3;
+
+        crate::stats::start();
+        pt.render(&c, &w);
+        let s = crate::stats::finish();
+        assert_eq!(s.primary_rays, 6 * 4 * 3);
+        assert!(s.reflection_rays > 0);
+        assert!(s.shadow_rays >= s.primary_rays);
+        assert!(s.max_depth >= 1);
+    }
+
+    fn dull(c: Color, diffuse: f64) -> Material {
+        let mut m = material();
+        m.color = c;
+        m.diffuse = diffuse;
+        m.specular = 0.0;
+        m
+    }
+
+    #[test]
+    fn emissive_shape_seen_directly(){
+        let mut w = world();
+        let mut s = sphere();
+        let mut m = dull(color(1.0, 1.0, 1.0), 0.0);
+        m.emissive = color(2.0, 1.0, 0.5);
+        s.set_material(m);
+        w.add_object(Box::new(s));
+
+        let pt = path_tracer();
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        assert!(pt.color_at(&w, r, &mut rng(1)).equals(color(2.0, 1.0, 0.5)));
+    }
+
+    #[test]
+    fn disk_light_over_a_floor(){
+        // a unit disk glowing with radiance 1 at height 1 puts
+        // irradiance pi * r^2 / (r^2 + h^2) on the floor below, so a
+        // white floor reflects 0.5
+        let mut w = world();
+        let mut floor = plane();
+        floor.set_material(dull(color(1.0, 1.0, 1.0), 1.0));
+        w.add_object(Box::new(floor));
+
+        let mut d = disk();
+        d.set_transform(translation(0.0, 1.0, 0.0));
+        let mut m = dull(color(1.0, 1.0, 1.0), 0.0);
+        m.emissive = color(1.0, 1.0, 1.0);
+        d.set_material(m);
+        w.add_object(Box::new(d));
+
+        let mut pt = path_tracer();
+        pt.max_depth = 1;
+        let r = ray(point(0.0, 0.5, 0.0), vector(0.0, -1.0, 0.0), 0);
+        let mut g = rng(11);
+        let mut sum = 0.0;
+        for _ in 0..4000 {
+            sum += pt.color_at(&w, r, &mut g).r;
+        }
+        assert!((sum / 4000.0 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn grouped_emitters_are_not_sampled(){
+        // sample_point doesn't know about parent transforms, so anything
+        // inside a group is left to be found by bounces
+        let glowing = || {
+            let mut d = disk();
+            let mut m = material();
+            m.emissive = color(1.0, 1.0, 1.0);
+            d.set_material(m);
+            d
+        };
+        let mut w = world();
+        let mut g = group();
+        g.set_transform(translation(0.0, 3.0, 0.0));
+        g.add_child(Box::new(glowing()));
+        w.add_object(Box::new(g));
+        assert!(emitters(&w).is_empty());
+
+        w.add_object(Box::new(glowing()));
+        assert_eq!(emitters(&w), vec![(1, PI)]);
+    }
+
+    #[test]
+    fn emissive_plane_lights_by_bouncing(){
+        // planes can't be sampled, but a bounce off the floor always
+        // reaches the glowing ceiling
+        let mut w = world();
+        let mut floor = plane();
+        floor.set_material(dull(color(1.0, 1.0, 1.0), 0.5));
+        w.add_object(Box::new(floor));
+
+        let mut ceiling = plane();
+        ceiling.set_transform(translation(0.0, 1.0, 0.0));
+        let mut m = dull(color(1.0, 1.0, 1.0), 0.0);
+        m.emissive = color(1.0, 1.0, 1.0);
+        ceiling.set_material(m);
+        w.add_object(Box::new(ceiling));
+
+        let pt = path_tracer();
+        let r = ray(point(0.0, 0.5, 0.0), vector(0.0, -1.0, 0.0), 0);
+        let mut g = rng(2);
+        for _ in 0..10 {
+            assert!(pt.color_at(&w, r, &mut g).equals(color(0.5, 0.5, 0.5)));
+        }
+    }
+
+    #[test]
+    fn misses_see_the_background(){
+        let mut w = world();
+        w.background = Some(solid_background(color(0.5, 0.5, 1.0)));
+        let pt = path_tracer();
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        assert!(pt.color_at(&w, r, &mut rng(1)).equals(color(0.5, 0.5, 1.0)));
+
+        // a floor under a uniform sky with nothing else around reflects
+        // albedo * sky, plus the sky's light off any further bounces
+        let mut floor = plane();
+        floor.set_material(dull(color(1.0, 1.0, 1.0), 0.5));
+        w.add_object(Box::new(floor));
+        let r = ray(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+        assert!(pt.color_at(&w, r, &mut rng(1)).equals(color(0.25, 0.25, 0.5)));
+    }
+
+    #[test]
+    fn polished_metal_reflects_its_surroundings(){
+        let mut w = world();
+        w.background = Some(solid_background(color(0.5, 0.5, 0.5)));
+        let mut floor = plane();
+        let mut m = dull(color(1.0, 1.0, 1.0), 0.9);
+        m.microfacet = Some(microfacet(0.05, 1.0));
+        floor.set_material(m);
+        w.add_object(Box::new(floor));
+
+        let pt = path_tracer();
+        let r = ray(point(0.0, 1.0, -1.0), vector(0.0, -1.0, 1.0).normal(), 0);
+        let mut g = rng(8);
+        let mut sum = 0.0;
+        for _ in 0..200 {
+            sum += pt.color_at(&w, r, &mut g).r;
+        }
+        assert!((sum / 200.0 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn fog_is_marched_like_the_whitted_renderer(){
+        let mut w = world();
+        w.background = Some(solid_background(color(0.5, 0.5, 1.0)));
+        w.light = Some(point_light(point(0.0, 5.0, 0.0), color(1.0, 1.0, 1.0)));
+        w.fog = Some(fog(0.1));
+
+        // a miss is just the march over the background, no sampling involved
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let c = path_tracer().color_at(&w, r, &mut rng(1));
+        assert!(c.equals(w.color_at(r)));
+        assert!(!c.equals(color(0.5, 0.5, 1.0)));
+    }
+}
@@ -58,7 +58,7 @@ pub fn pattern(a: Color, b: Color) -> Base {
 pub trait Pattern {
     fn pattern_at(&self, p: Tuple) -> Color;
 
-    fn pattern_at_shape(&self, o: &Box<dyn Shape>, p: Tuple) -> Color {
+    fn pattern_at_shape(&self, o: &dyn Shape, p: Tuple) -> Color {
         let object_point = o.get_transform().inverse().multup( &p );
         let pattern_point = self.get_pattern_transform().inverse().multup( &object_point );
         self.pattern_at( pattern_point )
@@ -118,7 +118,7 @@ mod tests {
         let black = color(0.0, 0.0, 0.0);
         let p = pattern(white, black);
 
-        let c = p.pattern_at_shape( &s, point(2.0, 3.0, 4.0) );
+        let c = p.pattern_at_shape( s.as_ref(), point(2.0, 3.0, 4.0) );
 
         assert_eq!(c, color(1.0, 1.5, 2.0));
     }
@@ -132,7 +132,7 @@ mod tests {
         let mut p = pattern(white, black);
         p.set_pattern_transform( scaling(2.0, 2.0, 2.0) );
 
-        let c = p.pattern_at_shape( &s, point(2.0, 3.0, 4.0) );
+        let c = p.pattern_at_shape( s.as_ref(), point(2.0, 3.0, 4.0) );
 
         assert_eq!(c, color(1.0, 1.5, 2.0));
     }
@@ -147,7 +147,7 @@ mod tests {
         let mut p = pattern(white, black);
         p.set_pattern_transform( translation(0.5, 1.0, 1.5) );
 
-        let c = p.pattern_at_shape( &s, point(2.5, 3.0, 3.5) );
+        let c = p.pattern_at_shape( s.as_ref(), point(2.5, 3.0, 3.5) );
 
         assert_eq!(c, color(0.75, 0.5, 0.25));
     }
@@ -65,6 +65,19 @@ impl Shape for Plane {
         self.supe.get_reference()
     }
     
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Plane"
+    }
+
+    // tiles every unit square
+    fn local_uv_at(&self, object_point: Tuple) -> Option<(f64, f64)> {
+        Some((object_point.x.rem_euclid(1.0), object_point.z.rem_euclid(1.0)))
+    }
+
     fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
         0
     }
@@ -146,4 +159,13 @@ mod tests {
         assert!( xs[0].t == 1.0 );
         assert!( xs[0].object == 0 );
     }
+
+    #[test]
+    fn uv_mapping_on_a_plane(){
+        let p = plane();
+        let (u, v) = p.local_uv_at( point(0.25, 0.0, 0.5) ).unwrap();
+        assert!( u == 0.25 && v == 0.5 );
+        let (u, v) = p.local_uv_at( point(-0.25, 0.0, 3.75) ).unwrap();
+        assert!( u == 0.75 && v == 0.75 );
+    }
 }
@@ -0,0 +1,114 @@
+// just enough PNG to write 8-bit RGBA images. The image data goes into
+// "stored" (uncompressed) deflate blocks, which every decoder has to
+// accept, so there's no compressor to carry around. Files are about the
+// size of a PAM.
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const MAX_STORED: usize = 65535;
+
+// rgba is 4 bytes a pixel, rows top to bottom
+pub fn png_bytes(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut ihdr = vec!();
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);   // 8 bit, RGBA, deflate, no filter, no interlace
+
+    // every row starts with its filter type, always 0 (none) here. Rows
+    // are sliced by hand rather than with chunks() so a 0 wide image
+    // still works - it's just the filter bytes.
+    let stride = width * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * height);
+    for y in 0..height {
+        raw.push(0);
+        raw.extend_from_slice(&rgba[y * stride..(y + 1) * stride]);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(MAX_STORED).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        out.push(if i == blocks.len() - 1 { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for d in data {
+        a = (a + *d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::png::{png_bytes, zlib_stored, crc32, adler32};
+
+    #[test]
+    fn checksums(){
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn stored_blocks_split_at_64k(){
+        let data = vec![7u8; 70000];
+        let z = zlib_stored(&data);
+        // header, two block headers, data and the checksum
+        assert_eq!(z.len(), 2 + 5 + 5 + 70000 + 4);
+        assert_eq!(z[2], 0);
+        assert_eq!(z[2 + 5 + 65535], 1);
+    }
+
+    #[test]
+    fn png_layout(){
+        let bytes = png_bytes(1, 1, &[255, 0, 0, 128]);
+        assert_eq!(&bytes[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(&bytes[12..16], b"IHDR");
+        assert_eq!(&bytes[16..24], &[0, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(bytes[25], 6);
+        assert_eq!(&bytes[bytes.len() - 8..bytes.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn empty_images_dont_panic(){
+        for (w, h) in [(0, 3), (3, 0), (0, 0)] {
+            let bytes = png_bytes(w, h, &[]);
+            assert_eq!(&bytes[12..16], b"IHDR");
+        }
+    }
+}
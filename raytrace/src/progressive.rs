@@ -0,0 +1,242 @@
+use crate::camera::Camera;
+use crate::world::World;
+use crate::pathtracer::{PathTracer, emitters};
+use crate::canvas::{Canvas, canvas};
+use crate::color::{Color, color};
+use crate::tonemap::Output;
+use crate::sampling::{rng, pixel_seed};
+use crate::stats::record;
+use std::fs::{File, read, rename};
+use std::io::{Write, Result, Error, ErrorKind};
+
+const MAGIC: &[u8] = b"RTCHECKPOINT1\n";
+
+// a render built up a pass at a time. Each pass adds the path tracer's
+// `samples` to every pixel, so the image can be looked at (or saved and
+// picked up again later) between passes.
+#[derive(Debug,Clone)]
+pub struct Progressive {
+    pub width: usize,
+    pub height: usize,
+    pub passes: usize,
+    pub samples: usize,     // per pixel, over all passes so far
+    sums: Vec<Color>,       // row by row, divided by samples for the image
+}
+
+impl Progressive {
+    pub fn add_pass(&mut self, c: &Camera, w: &World, pt: &PathTracer) -> Result<()> {
+        let width: usize = c.image_width().try_into().unwrap();
+        let height: usize = c.vsize.try_into().unwrap();
+        if width != self.width || height != self.height {
+            return Err(Error::new(ErrorKind::InvalidInput, "camera doesn't match the progressive render"));
+        }
+
+        let samples = pt.samples.max(1);
+        let seed = pass_seed(pt.seed, self.passes);
+        let lights = emitters(w);
+        for y in 0..height {
+            for x in 0..width {
+                let mut r = rng(pixel_seed(seed, x, y));
+                let mut sum = color(0.0, 0.0, 0.0);
+                for _ in 0..samples {
+                    let px = x as f64 + r.next_f64();
+                    let py = y as f64 + r.next_f64();
+                    if let Some(ray) = c.ray_for_point(px, py) {
+                        record(|s| s.primary_rays += 1);
+                        sum = sum + pt.trace(w, ray, &lights, &mut r);
+                    }
+                }
+                let i = y * width + x;
+                self.sums[i] = self.sums[i] + sum;
+            }
+        }
+
+        self.passes += 1;
+        self.samples += samples;
+        Ok(())
+    }
+
+    // keeps adding passes until there are `passes` of them, writing a
+    // checkpoint every `every` passes (and at the end) when there's a
+    // file to write it to
+    pub fn run(&mut self, c: &Camera, w: &World, pt: &PathTracer,
+               passes: usize, checkpoint: Option<(&str, usize)>) -> Result<()> {
+        while self.passes < passes {
+            self.add_pass(c, w, pt)?;
+            if let Some((name, every)) = checkpoint {
+                if self.passes.is_multiple_of(every.max(1)) || self.passes == passes {
+                    self.save(name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the average so far - black until the first pass is in
+    pub fn image(&self) -> Canvas {
+        let mut image = canvas(self.width, self.height);
+        if self.samples == 0 {
+            return image;
+        }
+        let scale = 1.0 / self.samples as f64;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                image.write_pixel(x, y, self.sums[y * self.width + x] * scale);
+            }
+        }
+        image
+    }
+
+    pub fn preview(&self, name: &str, out: &Output) -> Result<File> {
+        self.image().to_ppm_with(name, out)
+    }
+
+    // writes to a temporary file first and renames it over the old one,
+    // so being killed halfway through never leaves a broken checkpoint
+    pub fn save(&self, name: &str) -> Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(format!("{} {} {} {}\n", self.width, self.height,
+                                        self.passes, self.samples).as_bytes());
+        for c in &self.sums {
+            for v in [c.r, c.g, c.b] {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        let temp = format!("{}.tmp", name);
+        let mut f = File::create(&temp)?;
+        f.write_all(&bytes)?;
+        f.sync_all()?;
+        rename(&temp, name)
+    }
+}
+
+pub fn progressive(width: usize, height: usize) -> Progressive {
+    Progressive {
+        width,
+        height,
+        passes: 0,
+        samples: 0,
+        sums: vec![color(0.0, 0.0, 0.0); width * height],
+    }
+}
+
+// an empty one the right size for the camera
+pub fn progressive_for(c: &Camera) -> Progressive {
+    progressive(c.image_width().try_into().unwrap(), c.vsize.try_into().unwrap())
+}
+
+// picks up a render from a checkpoint written by save
+pub fn resume(name: &str) -> Result<Progressive> {
+    checkpoint_to_progressive(&read(name)?)
+}
+
+pub fn checkpoint_to_progressive(bytes: &[u8]) -> Result<Progressive> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(bad_checkpoint("not a checkpoint"));
+    }
+    let rest = &bytes[MAGIC.len()..];
+    let end = rest.iter().position(|b| *b == b'\n').ok_or_else(|| bad_checkpoint("missing header"))?;
+    let header: Vec<usize> = String::from_utf8_lossy(&rest[..end])
+        .split_whitespace()
+        .map(|s| s.parse::<usize>().map_err(|_| bad_checkpoint("bad header")))
+        .collect::<Result<Vec<usize>>>()?;
+    if header.len() != 4 {
+        return Err(bad_checkpoint("bad header"));
+    }
+
+    // check the size against the data before allocating anything, so a
+    // corrupt header can't ask for a huge image
+    let data = &rest[end + 1..];
+    let expected = header[0].checked_mul(header[1]).and_then(|n| n.checked_mul(24));
+    if expected != Some(data.len()) {
+        return Err(bad_checkpoint("wrong amount of pixel data"));
+    }
+
+    let mut p = progressive(header[0], header[1]);
+    p.passes = header[2];
+    p.samples = header[3];
+    let values: Vec<f64> = data.chunks_exact(8)
+                               .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                               .collect();
+    for (i, v) in values.chunks_exact(3).enumerate() {
+        p.sums[i] = color(v[0], v[1], v[2]);
+    }
+    Ok(p)
+}
+
+// pass 0 uses the path tracer's own seed, so one pass matches its render
+fn pass_seed(seed: u64, pass: usize) -> u64 {
+    seed.wrapping_add((pass as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+fn bad_checkpoint(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progressive::{progressive_for, resume, checkpoint_to_progressive};
+    use crate::pathtracer::path_tracer;
+    use crate::world::default_world;
+    use crate::camera::camera;
+    use crate::tuple::{point, vector};
+    use crate::transform::view_transform;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn one_pass_is_the_plain_render(){
+        let w = default_world();
+        let mut c = camera(6, 4, PI / 2.0);
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        let mut pt = path_tracer();
+        pt.samples = 2;
+
+        let mut p = progressive_for(&c);
+        p.add_pass(&c, &w, &pt).unwrap();
+        assert_eq!(p.samples, 2);
+        assert!(p.image().equals(pt.render(&c, &w)));
+    }
+
+    #[test]
+    fn passes_must_match_the_camera(){
+        let w = default_world();
+        let c = camera(6, 4, PI / 2.0);
+        let mut p = progressive_for(&camera(4, 4, PI / 2.0));
+        assert!(p.add_pass(&c, &w, &path_tracer()).is_err());
+    }
+
+    #[test]
+    fn resuming_from_a_checkpoint_carries_on_the_same(){
+        let w = default_world();
+        let mut c = camera(5, 3, PI / 2.0);
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        let mut pt = path_tracer();
+        pt.samples = 1;
+
+        let mut straight = progressive_for(&c);
+        straight.run(&c, &w, &pt, 3, None).unwrap();
+
+        // interrupted after two passes, then picked up again
+        let mut first = progressive_for(&c);
+        first.run(&c, &w, &pt, 2, Some(("resume.ckpt", 1))).unwrap();
+        let mut second = resume("resume.ckpt").unwrap();
+        let _ = std::fs::remove_file("resume.ckpt");
+        assert_eq!(second.passes, 2);
+        second.run(&c, &w, &pt, 3, None).unwrap();
+
+        assert_eq!(second.samples, 3);
+        assert!(second.image().equals(straight.image()));
+    }
+
+    #[test]
+    fn broken_checkpoints_are_errors(){
+        assert!(checkpoint_to_progressive(b"P3\n1 1\n255\n").is_err());
+        assert!(checkpoint_to_progressive(b"RTCHECKPOINT1\n1 1 1 1\n\0\0").is_err());
+        assert!(checkpoint_to_progressive(b"RTCHECKPOINT1\n1 x 1 1\n").is_err());
+        // would overflow, or ask for an enormous image
+        let huge = format!("RTCHECKPOINT1\n{} {} 1 1\n", usize::MAX, 2);
+        assert!(checkpoint_to_progressive(huge.as_bytes()).is_err());
+        assert!(checkpoint_to_progressive(b"RTCHECKPOINT1\n100000 100000 1 1\n").is_err());
+    }
+}
@@ -0,0 +1,397 @@
+use crate::shapes::{Shape, Base, shape};
+use crate::shape_index::ShapeIndex;
+use crate::intersections::{Intersection, intersection, intersections};
+use crate::tuple::{Tuple, vector};
+use crate::rays::Ray;
+use crate::materials::Material;
+use crate::matrix::Matrix;
+use crate::numeric::solve_quadratic;
+use crate::equals::{equals, EPSILON};
+
+// general second-degree surface:
+//   Ax² + By² + Cz² + Dxy + Exz + Fyz + Gx + Hy + Iz + J = 0
+// coefficients are stored in that order. The inside of the surface is
+// wherever the left-hand side is negative, which is what the end caps
+// use to decide how much of the cutting plane to fill in.
+pub struct Quadric {
+    supe: Base,
+    pub coefficients: [f64; 10],
+    pub minimum: f64,
+    pub maximum: f64,
+    pub closed: bool,
+}
+
+impl Shape for Quadric {
+    fn get_transform(&self) -> &Matrix {
+        self.supe.get_transform()
+    }
+
+    fn set_transform(&mut self, t: Matrix){
+        self.supe.set_transform( t );
+    }
+
+    fn get_material(&self) -> &Material {
+        self.supe.get_material()
+    }
+
+    fn set_material(&mut self, m: Material){
+        self.supe.set_material( m );
+    }
+
+    fn local_normal_at(&self, object_point: Tuple) -> Tuple {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        let (x, y, z) = (object_point.x, object_point.y, object_point.z);
+
+        if self.closed && self.value_at(object_point) <= EPSILON {
+            if y >= self.maximum - EPSILON {
+                return vector(0.0, 1.0, 0.0);
+            } else if y <= self.minimum + EPSILON {
+                return vector(0.0, -1.0, 0.0);
+            }
+        }
+
+        vector(2.0 * a * x + d * y + e * z + g,
+               2.0 * b * y + d * x + f * z + h,
+               2.0 * c * z + e * x + f * y + i)
+    }
+
+    fn intersect(&self, r: Ray) -> Vec<Intersection> {
+        let r2 = self.saved_ray(r);
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        let (ox, oy, oz) = (r2.origin.x, r2.origin.y, r2.origin.z);
+        let (dx, dy, dz) = (r2.direction.x, r2.direction.y, r2.direction.z);
+
+        let qa = a * dx * dx + b * dy * dy + c * dz * dz +
+                 d * dx * dy + e * dx * dz + f * dy * dz;
+        let qb = 2.0 * (a * ox * dx + b * oy * dy + c * oz * dz) +
+                 d * (ox * dy + oy * dx) +
+                 e * (ox * dz + oz * dx) +
+                 f * (oy * dz + oz * dy) +
+                 g * dx + h * dy + i * dz;
+        let qc = a * ox * ox + b * oy * oy + c * oz * oz +
+                 d * ox * oy + e * ox * oz + f * oy * oz +
+                 g * ox + h * oy + i * oz + j;
+
+        let mut xs = intersections(&[]);
+
+        // solve_quadratic drops down to the linear case on its own -
+        // that's the ray running parallel to a cone's side. qa grows with
+        // the square of the direction, which a scaling transform shrinks,
+        // so "nearly zero" has to be measured against that
+        let scale = (dx * dx + dy * dy + dz * dz) *
+                    [a, b, c, d, e, f].iter().fold(0.0, |m: f64, k| m.max(k.abs()));
+        let qa = if qa.abs() <= EPSILON * scale { 0.0 } else { qa };
+        for t in solve_quadratic(qa, qb, qc) {
+            let y = r2.origin.y + t * r2.direction.y;
+            if self.minimum < y && y < self.maximum {
+                xs.push(intersection(t, self.get_index()));
+            }
+        }
+
+        self.intersect_caps(r2, xs)
+    }
+
+    fn get_index(&self) -> usize {
+        self.supe.get_index()
+    }
+
+    fn set_index(&mut self, index: usize){
+        self.supe.set_index(index);
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.supe.get_parent()
+    }
+
+    fn set_parent(&mut self, parent_index: usize){
+        self.supe.set_parent(parent_index);
+    }
+
+    fn get_reference(&self) -> ShapeIndex {
+        self.supe.get_reference()
+    }
+
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Quadric"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        let mut params = self.coefficients.to_vec();
+        params.push(self.minimum);
+        params.push(self.maximum);
+        params.push(if self.closed { 1.0 } else { 0.0 });
+        params
+    }
+
+    fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
+        0
+    }
+
+    fn get_object(&self, _index: usize) -> Option<&Box<dyn Shape>> {
+        None
+    }
+
+    fn get_size(&self) -> usize {
+        0
+    }
+}
+
+impl Quadric {
+    // left-hand side of the surface equation at p - negative inside
+    pub fn value_at(&self, p: Tuple) -> f64 {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        a * p.x * p.x + b * p.y * p.y + c * p.z * p.z +
+        d * p.x * p.y + e * p.x * p.z + f * p.y * p.z +
+        g * p.x + h * p.y + i * p.z + j
+    }
+
+    fn intersect_caps(&self, r: Ray, mut xs: Vec<Intersection>) -> Vec<Intersection> {
+        if !self.closed || equals(r.direction.y, 0.0) {
+            return xs
+        }
+
+        // check lower end cap
+        let t = (self.minimum - r.origin.y) / r.direction.y;
+        if self.check_cap(r, t){
+            xs.push(intersection(t, self.get_index()));
+        }
+
+        // check upper end cap
+        let t = (self.maximum - r.origin.y) / r.direction.y;
+        if self.check_cap(r, t){
+            xs.push(intersection(t, self.get_index()));
+        }
+
+        xs
+    }
+
+    fn check_cap(&self, r: Ray, t: f64) -> bool {
+        t.is_finite() && self.value_at(r.position(t)) <= 0.0
+    }
+}
+
+pub fn quadric(coefficients: [f64; 10]) -> Quadric {
+    Quadric {
+        supe: shape(),
+        coefficients,
+        minimum: -f64::INFINITY,
+        maximum:  f64::INFINITY,
+        closed: false,
+    }
+}
+
+// x² + z² - y = 0, opening upward from the origin - a dish reflector
+// once the y range is clipped
+pub fn paraboloid() -> Quadric {
+    quadric([1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0])
+}
+
+// x² - y² + z² - 1 = 0, hyperboloid of one sheet with its waist
+// (radius 1) at y = 0 - the cooling tower shape
+pub fn hyperboloid() -> Quadric {
+    quadric([1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quadrics::{quadric, paraboloid, hyperboloid};
+    use crate::spheres::sphere;
+    use crate::cylinders::cylinder;
+    use crate::cones::cone;
+    use crate::shapes::Shape;
+    use crate::tuple::{point, vector};
+    use crate::rays::{Ray, ray};
+    use crate::equals::equals;
+    use crate::transform::scaling;
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    fn test_rays() -> Vec<Ray> {
+        vec![ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0),
+             ray(point(0.5, 0.0, -5.0), vector(0.1, 1.0, 1.0).normal(), 0),
+             ray(point(1.0, 1.0, -5.0), vector(-0.5, -1.0, 1.0).normal(), 0),
+             ray(point(0.0, 3.0, 0.0), vector(0.0, -1.0, 0.0), 0),
+             ray(point(0.0, 1.5, -2.0), vector(0.0, 0.0, 1.0), 0),
+             ray(point(0.0, -1.0, -2.0), vector(0.0, 1.0, 2.0).normal(), 0),
+             ray(point(0.0, 0.0, 0.0), vector(1.0, 0.2, 0.3).normal(), 0),
+             ray(point(3.0, 0.5, 0.0), vector(-1.0, 0.0, 0.0), 0),
+             ray(point(0.0, 2.0, -5.0), vector(0.0, 0.0, 1.0), 0)]
+    }
+
+    fn same_hits(a: &dyn Shape, b: &dyn Shape) -> bool {
+        for r in test_rays() {
+            let mut xa: Vec<f64> = a.intersect(r).iter().map(|i| i.t).collect();
+            let mut xb: Vec<f64> = b.intersect(r).iter().map(|i| i.t).collect();
+            xa.sort_by(|x, y| x.total_cmp(y));
+            xb.sort_by(|x, y| x.total_cmp(y));
+            if xa.len() != xb.len() ||
+               !xa.iter().zip(xb.iter()).all(|(x, y)| equals(*x, *y)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    #[test]
+    fn scaled_quadric_sphere_still_gets_two_hits(){
+        // shrinks the object space direction to 0.001, so qa is tiny
+        let mut q = quadric([1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0]);
+        q.set_transform(scaling(1000.0, 1000.0, 1000.0));
+        let xs = q.intersect(ray(point(0.0, 0.0, -5000.0), vector(0.0, 0.0, 1.0), 0));
+        assert_eq!(xs.len(), 2);
+        assert!(equals(xs[0].t, 4000.0));
+        assert!(equals(xs[1].t, 6000.0));
+    }
+
+    #[test]
+    fn sphere_is_a_quadric(){
+        let q = quadric([1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0]);
+        assert!(same_hits(&q, &sphere()));
+
+        let p = point(0.0, 0.6, 0.8);
+        assert!(q.normal_at(p).equals(sphere().normal_at(p)));
+    }
+
+    #[test]
+    fn closed_cylinder_is_a_quadric(){
+        let mut q = quadric([1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0]);
+        q.minimum = 1.0;
+        q.maximum = 2.0;
+        q.closed = true;
+
+        let mut c = cylinder();
+        c.minimum = 1.0;
+        c.maximum = 2.0;
+        c.closed = true;
+
+        assert!(same_hits(&q, &c));
+        for p in [point(1.0, 1.5, 0.0), point(0.5, 2.0, 0.0), point(0.0, 1.0, 0.5)] {
+            assert!(q.normal_at(p).equals(c.normal_at(p)));
+        }
+    }
+
+    #[test]
+    fn open_cylinder_is_a_quadric(){
+        let q = quadric([1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0]);
+        assert!(same_hits(&q, &cylinder()));
+    }
+
+    #[test]
+    fn cone_is_a_quadric(){
+        let mut q = quadric([1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        q.minimum = -1.0;
+        q.maximum = 1.0;
+        q.closed = true;
+
+        let mut c = cone();
+        c.minimum = -1.0;
+        c.maximum = 1.0;
+        c.closed = true;
+
+        assert!(same_hits(&q, &c));
+        let p = point(0.4, -0.4, 0.0);
+        assert!(q.normal_at(p).equals(c.normal_at(p)));
+    }
+
+    // Cone follows the text here and uses t = -c/(2b), which gives
+    // 0.35355 - but with a = 0 the equation is just bt + c = 0, and
+    // only t = -c/b actually lands on the surface at (0, 0.5, -0.5)
+    #[test]
+    fn ray_parallel_to_side_of_cone_quadric(){
+        let q = quadric([1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        let r = ray(point(0.0, 0.0, -1.0), vector(0.0, 1.0, 1.0).normal(), 0);
+        let xs = q.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        assert!(equals(xs[0].t, FRAC_1_SQRT_2));
+        assert!(equals(q.value_at(r.position(xs[0].t)), 0.0));
+    }
+
+    #[test]
+    fn ray_into_a_paraboloid_dish(){
+        let mut p = paraboloid();
+        p.maximum = 1.0;
+
+        // straight down the axis hits the bottom of the bowl
+        let xs = p.intersect(ray(point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 1);
+        assert!(equals(xs[0].t, 5.0));
+
+        // sideways through the bowl at y = 0.25 crosses at x = ±0.5
+        let xs = p.intersect(ray(point(-5.0, 0.25, 0.0), vector(1.0, 0.0, 0.0), 0));
+        assert_eq!(xs.len(), 2);
+        assert!(equals(xs[0].t, 4.5));
+        assert!(equals(xs[1].t, 5.5));
+
+        // above the rim there's nothing left
+        let xs = p.intersect(ray(point(-5.0, 2.0, 0.0), vector(1.0, 0.0, 0.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn normal_on_a_paraboloid(){
+        let p = paraboloid();
+        let n = p.normal_at(point(0.0, 0.0, 0.0));
+        assert!(n.equals(vector(0.0, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn ray_through_a_hyperboloid(){
+        let mut h = hyperboloid();
+        h.minimum = -1.0;
+        h.maximum =  1.0;
+
+        // at the waist the radius is 1
+        let xs = h.intersect(ray(point(-5.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), 0));
+        assert_eq!(xs.len(), 2);
+        assert!(equals(xs[0].t, 4.0));
+        assert!(equals(xs[1].t, 6.0));
+
+        // flared out to sqrt(2) at y = 1
+        let xs = h.intersect(ray(point(-5.0, 0.99999, 0.0), vector(1.0, 0.0, 0.0), 0));
+        assert_eq!(xs.len(), 2);
+        assert!(equals(xs[0].t, 5.0 - 2.0_f64.sqrt()));
+
+        // straight down the middle never touches the wall
+        let xs = h.intersect(ray(point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn closed_hyperboloid_caps(){
+        let mut h = hyperboloid();
+        h.minimum = -1.0;
+        h.maximum =  1.0;
+        h.closed = true;
+
+        // straight down the middle now goes in the top and out the bottom
+        // (caps come back lower one first, same as Cylinder)
+        let xs = h.intersect(ray(point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 2);
+        assert!(equals(xs[0].t, 6.0));
+        assert!(equals(xs[1].t, 4.0));
+
+        // further out it also crosses the pinched-in wall twice
+        let mut xs: Vec<f64> = h.intersect(ray(point(1.2, 5.0, 0.0), vector(0.0, -1.0, 0.0), 0))
+                                .iter().map(|i| i.t).collect();
+        xs.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(xs.len(), 4);
+        assert!(equals(xs[0], 4.0));
+        assert!(equals(xs[1], 5.0 - 0.44_f64.sqrt()));
+        assert!(equals(xs[3], 6.0));
+
+        let n = h.normal_at(point(1.2, 1.0, 0.0));
+        assert!(n.equals(vector(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn default_quadric_is_unbounded_and_open(){
+        let q = paraboloid();
+        assert_eq!(q.minimum, -f64::INFINITY);
+        assert_eq!(q.maximum,  f64::INFINITY);
+        assert!(!q.closed);
+    }
+}
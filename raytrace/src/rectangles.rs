@@ -0,0 +1,201 @@
+use crate::shapes::{Base, Shape, shape, xz_area_scale};
+use crate::shape_index::ShapeIndex;
+use crate::intersections::{Intersection, intersection, intersections};
+use crate::tuple::{Tuple, point, vector};
+use crate::rays::Ray;
+use crate::materials::Material;
+use crate::matrix::Matrix;
+use crate::equals::EPSILON;
+
+// flat rectangle in the xz plane, centered on the origin - width
+// runs along x and depth along z
+pub struct Rectangle {
+    supe: Base,
+    pub width: f64,
+    pub depth: f64,
+}
+
+impl Shape for Rectangle {
+    fn get_transform(&self) -> &Matrix {
+        self.supe.get_transform()
+    }
+
+    fn set_transform(&mut self, t: Matrix){
+        self.supe.set_transform( t );
+    }
+
+    fn get_material(&self) -> &Material {
+        self.supe.get_material()
+    }
+
+    fn set_material(&mut self, m: Material){
+        self.supe.set_material( m );
+    }
+
+    fn local_normal_at(&self, _object_point: Tuple) -> Tuple {
+        vector(0.0, 1.0, 0.0)
+    }
+
+    fn intersect(&self, r: Ray) -> Vec<Intersection> {
+        let r2 = self.saved_ray(r);
+        if r2.direction.y.abs() < EPSILON {
+            return vec!();
+        }
+
+        let t = -r2.origin.y / r2.direction.y;
+        let p = r2.position(t);
+        if p.x.abs() > self.width / 2.0 || p.z.abs() > self.depth / 2.0 {
+            return vec!();
+        }
+
+        intersections(&[intersection(t, self.get_index())])
+    }
+
+    fn get_index(&self) -> usize {
+        self.supe.get_index()
+    }
+
+    fn set_index(&mut self, index: usize){
+        self.supe.set_index(index);
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.supe.get_parent()
+    }
+
+    fn set_parent(&mut self, parent_index: usize){
+        self.supe.set_parent(parent_index);
+    }
+
+    fn get_reference(&self) -> ShapeIndex {
+        self.supe.get_reference()
+    }
+
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Rectangle"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        vec![self.width, self.depth]
+    }
+
+    fn local_uv_at(&self, object_point: Tuple) -> Option<(f64, f64)> {
+        Some((object_point.x / self.width + 0.5,
+              object_point.z / self.depth + 0.5))
+    }
+
+    fn local_sample(&self, u: f64, v: f64) -> Option<Tuple> {
+        Some(point((u - 0.5) * self.width, 0.0, (v - 0.5) * self.depth))
+    }
+
+    fn area(&self) -> f64 {
+        self.width * self.depth * xz_area_scale(self.get_transform())
+    }
+
+    fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
+        0
+    }
+
+    fn get_object(&self, _index: usize) -> Option<&Box<dyn Shape>> {
+        None
+    }
+
+    fn get_size(&self) -> usize {
+        0
+    }
+}
+
+pub fn rectangle() -> Rectangle {
+    Rectangle {
+        supe: shape(),
+        width: 1.0,
+        depth: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rectangles::rectangle;
+    use crate::shapes::Shape;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::transform::{scaling, rotation_x};
+    use crate::equals::equals;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn default_rectangle_size(){
+        let r = rectangle();
+        assert_eq!(r.width, 1.0);
+        assert_eq!(r.depth, 1.0);
+    }
+
+    #[test]
+    fn normal_of_rectangle_is_constant(){
+        let r = rectangle();
+        assert_eq!(r.local_normal_at(point(0.0, 0.0, 0.0)), vector(0.0, 1.0, 0.0));
+        assert_eq!(r.local_normal_at(point(0.4, 0.0, -0.4)), vector(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn ray_hits_a_rectangle(){
+        let r = rectangle();
+        let xs = r.intersect(ray(point(0.4, 2.0, -0.4), vector(0.0, -1.0, 0.0), 0));
+
+        assert_eq!(xs.len(), 1);
+        assert!(equals(xs[0].t, 2.0));
+    }
+
+    #[test]
+    fn ray_misses_a_rectangle(){
+        let mut r = rectangle();
+        r.width = 2.0;
+
+        // inside along x now, but still outside along z
+        let xs = r.intersect(ray(point(0.9, 1.0, 0.6), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 0);
+
+        let xs = r.intersect(ray(point(0.9, 1.0, 0.4), vector(0.0, -1.0, 0.0), 0));
+        assert_eq!(xs.len(), 1);
+    }
+
+    #[test]
+    fn ray_parallel_to_rectangle(){
+        let r = rectangle();
+        let xs = r.intersect(ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn uv_mapping_on_a_rectangle(){
+        let r = rectangle();
+        let (u, v) = r.local_uv_at(point(-0.5, 0.0, -0.5)).unwrap();
+        assert!(equals(u, 0.0) && equals(v, 0.0));
+
+        let (u, v) = r.local_uv_at(point(0.25, 0.0, 0.5)).unwrap();
+        assert!(equals(u, 0.75) && equals(v, 1.0));
+    }
+
+    #[test]
+    fn samples_map_to_world_space(){
+        let mut r = rectangle();
+        r.set_transform(rotation_x(PI / 2.0));
+
+        let p = r.sample_point(1.0, 1.0).unwrap();
+        assert!(p.equals(point(0.5, -0.5, 0.0)));
+    }
+
+    #[test]
+    fn area_of_a_scaled_rectangle(){
+        let mut r = rectangle();
+        r.width = 2.0;
+        assert!(equals(r.area(), 2.0));
+
+        r.set_transform(scaling(3.0, 5.0, 0.5));
+        assert!(equals(r.area(), 3.0));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::tuple::{Tuple, vector};
+use std::f64::consts::PI;
+
+// small xorshift64* generator - plenty for Monte Carlo sampling, and
+// keeping it in-house means renders are reproducible from a seed
+#[derive(Debug,Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub fn rng(seed: u64) -> Rng {
+    // zero is a fixed point for xorshift, so scramble the seed first
+    Rng { state: mix(seed) | 1 }
+}
+
+// seed for one pixel of one pass, so pixels can be rendered in any
+// order and still come out the same
+pub fn pixel_seed(seed: u64, x: usize, y: usize) -> u64 {
+    mix(seed ^ mix((x as u64) << 32 | y as u64))
+}
+
+// seed for work done at a point in space rather than at a pixel, e.g.
+// sampling from inside shade_hit
+pub fn point_seed(seed: u64, p: Tuple) -> u64 {
+    mix(seed ^ mix(p.x.to_bits() ^ mix(p.y.to_bits() ^ mix(p.z.to_bits()))))
+}
+
+// splitmix64 finalizer
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// two unit vectors perpendicular to n and to each other
+pub fn orthonormal_basis(n: Tuple) -> (Tuple, Tuple) {
+    let helper = if n.x.abs() > 0.9 { vector(0.0, 1.0, 0.0) } else { vector(1.0, 0.0, 0.0) };
+    let t = helper.cross(&n).normal();
+    let b = n.cross(&t);
+    (t, b)
+}
+
+// direction in the hemisphere around n, more likely near n (pdf is
+// cos(theta) / pi) - the right distribution for diffuse bounces
+pub fn cosine_hemisphere(n: Tuple, u1: f64, u2: f64) -> Tuple {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let (t, b) = orthonormal_basis(n);
+    (t * (r * phi.cos()) + b * (r * phi.sin()) + n * (1.0 - u1).max(0.0).sqrt()).normal()
+}
+
+// direction anywhere in the hemisphere around n with equal probability
+pub fn uniform_hemisphere(n: Tuple, u1: f64, u2: f64) -> Tuple {
+    let z = u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    let (t, b) = orthonormal_basis(n);
+    (t * (r * phi.cos()) + b * (r * phi.sin()) + n * z).normal()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampling::{rng, pixel_seed, point_seed, orthonormal_basis,
+                          cosine_hemisphere, uniform_hemisphere};
+    use crate::tuple::{point, vector};
+    use crate::equals::equals;
+
+    #[test]
+    fn rng_is_repeatable(){
+        let mut a = rng(42);
+        let mut b = rng(42);
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        let mut c = rng(43);
+        assert_ne!(rng(42).next_u64(), c.next_u64());
+    }
+
+    #[test]
+    fn rng_floats_are_in_unit_range(){
+        let mut r = rng(0);
+        let mut sum = 0.0;
+        for _ in 0..10000 {
+            let f = r.next_f64();
+            assert!((0.0..1.0).contains(&f));
+            sum += f;
+        }
+        assert!((sum / 10000.0 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn pixel_seeds_differ(){
+        assert_ne!(pixel_seed(1, 0, 1), pixel_seed(1, 1, 0));
+        assert_ne!(pixel_seed(1, 3, 4), pixel_seed(2, 3, 4));
+        assert_eq!(pixel_seed(1, 3, 4), pixel_seed(1, 3, 4));
+
+        assert_ne!(point_seed(1, point(1.0, 2.0, 3.0)), point_seed(1, point(3.0, 2.0, 1.0)));
+        assert_eq!(point_seed(1, point(1.0, 2.0, 3.0)), point_seed(1, point(1.0, 2.0, 3.0)));
+    }
+
+    #[test]
+    fn basis_is_orthonormal(){
+        for n in [vector(0.0, 1.0, 0.0), vector(1.0, 0.0, 0.0), vector(1.0, 2.0, 3.0).normal()] {
+            let (t, b) = orthonormal_basis(n);
+            assert!(equals(t.mag(), 1.0) && equals(b.mag(), 1.0));
+            assert!(equals(t.dot(&n), 0.0));
+            assert!(equals(b.dot(&n), 0.0));
+            assert!(equals(t.dot(&b), 0.0));
+        }
+    }
+
+    #[test]
+    fn hemisphere_samples_face_the_normal(){
+        let n = vector(0.0, 0.0, -1.0);
+        let mut r = rng(7);
+        let mut cos_sum = 0.0;
+        for _ in 0..2000 {
+            let d = cosine_hemisphere(n, r.next_f64(), r.next_f64());
+            assert!(equals(d.mag(), 1.0));
+            assert!(d.dot(&n) >= 0.0);
+            cos_sum += d.dot(&n);
+
+            let d = uniform_hemisphere(n, r.next_f64(), r.next_f64());
+            assert!(d.dot(&n) >= 0.0);
+        }
+        // mean cosine of a cosine-weighted hemisphere is 2/3
+        assert!((cos_sum / 2000.0 - 2.0 / 3.0).abs() < 0.02);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::shapes::{Shape, Base, shape};
+use crate::shape_index::ShapeIndex;
+use crate::intersections::{Intersection, intersection};
+use crate::tuple::{Tuple, point, vector};
+use crate::rays::Ray;
+use crate::materials::Material;
+use crate::matrix::Matrix;
+
+// a signed distance function, built up as a tree of primitives and
+// operators - distance() is negative inside the surface, positive
+// outside, and never more than the true distance to the surface
+// (twist bends that last rule, see step_scale on SdfShape)
+#[derive(Debug,Clone,PartialEq)]
+pub enum Sdf {
+    Sphere(f64),
+    Box(Tuple),
+    RoundBox(Tuple, f64),
+    Torus(f64, f64),
+    Capsule(Tuple, Tuple, f64),
+    Translate(Box<Sdf>, Tuple),
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f64),
+    SmoothSubtraction(Box<Sdf>, Box<Sdf>, f64),
+    Twist(Box<Sdf>, f64),
+    Repeat(Box<Sdf>, Tuple),
+}
+
+impl Sdf {
+    pub fn distance(&self, p: Tuple) -> f64 {
+        match self {
+            Sdf::Sphere(r) => length(p.x, p.y, p.z) - r,
+            Sdf::Box(b) => box_distance(p, *b),
+            Sdf::RoundBox(b, r) => {
+                box_distance(p, vector(b.x - r, b.y - r, b.z - r)) - r
+            },
+            Sdf::Torus(major, minor) => {
+                length(length(p.x, 0.0, p.z) - major, p.y, 0.0) - minor
+            },
+            Sdf::Capsule(a, b, r) => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let h = (dot(pa, ba) / dot(ba, ba)).clamp(0.0, 1.0);
+                let d = pa - ba * h;
+                length(d.x, d.y, d.z) - r
+            },
+            Sdf::Translate(s, offset) => s.distance(p - *offset),
+            Sdf::SmoothUnion(a, b, k) => {
+                let da = a.distance(p);
+                let db = b.distance(p);
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                mix(db, da, h) - k * h * (1.0 - h)
+            },
+            Sdf::SmoothSubtraction(base, cut, k) => {
+                let da = cut.distance(p);
+                let db = base.distance(p);
+                let h = (0.5 - 0.5 * (db + da) / k).clamp(0.0, 1.0);
+                mix(db, -da, h) + k * h * (1.0 - h)
+            },
+            Sdf::Twist(s, k) => {
+                let c = (k * p.y).cos();
+                let sn = (k * p.y).sin();
+                s.distance(point(c * p.x - sn * p.z, p.y, sn * p.x + c * p.z))
+            },
+            Sdf::Repeat(s, spacing) => {
+                s.distance(point(wrap(p.x, spacing.x),
+                                 wrap(p.y, spacing.y),
+                                 wrap(p.z, spacing.z)))
+            },
+        }
+    }
+}
+
+fn length(x: f64, y: f64, z: f64) -> f64 {
+    (x * x + y * y + z * z).sqrt()
+}
+
+// Tuple::dot folds w in, which we don't want for points
+fn dot(a: Tuple, b: Tuple) -> f64 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+fn mix(a: f64, b: f64, h: f64) -> f64 {
+    a * (1.0 - h) + b * h
+}
+
+fn box_distance(p: Tuple, b: Tuple) -> f64 {
+    let qx = p.x.abs() - b.x;
+    let qy = p.y.abs() - b.y;
+    let qz = p.z.abs() - b.z;
+    length(qx.max(0.0), qy.max(0.0), qz.max(0.0)) + qx.max(qy).max(qz).min(0.0)
+}
+
+// spacing of zero leaves that axis alone
+fn wrap(v: f64, spacing: f64) -> f64 {
+    if spacing == 0.0 {
+        v
+    } else {
+        v - spacing * (v / spacing).round()
+    }
+}
+
+pub fn sd_sphere(radius: f64) -> Sdf {
+    Sdf::Sphere(radius)
+}
+
+// half-extents along each axis, so sd_box(1, 1, 1) matches Cube
+pub fn sd_box(x: f64, y: f64, z: f64) -> Sdf {
+    Sdf::Box(vector(x, y, z))
+}
+
+pub fn sd_round_box(x: f64, y: f64, z: f64, radius: f64) -> Sdf {
+    Sdf::RoundBox(vector(x, y, z), radius)
+}
+
+pub fn sd_torus(major: f64, minor: f64) -> Sdf {
+    Sdf::Torus(major, minor)
+}
+
+pub fn sd_capsule(a: Tuple, b: Tuple, radius: f64) -> Sdf {
+    Sdf::Capsule(a, b, radius)
+}
+
+pub fn translate(s: Sdf, x: f64, y: f64, z: f64) -> Sdf {
+    Sdf::Translate(Box::new(s), vector(x, y, z))
+}
+
+// k is the size of the blend region
+pub fn smooth_union(a: Sdf, b: Sdf, k: f64) -> Sdf {
+    Sdf::SmoothUnion(Box::new(a), Box::new(b), k)
+}
+
+// carves cut out of base
+pub fn smooth_subtraction(base: Sdf, cut: Sdf, k: f64) -> Sdf {
+    Sdf::SmoothSubtraction(Box::new(base), Box::new(cut), k)
+}
+
+// rotates each slice around the y axis by k radians per unit of height
+pub fn twist(s: Sdf, k: f64) -> Sdf {
+    Sdf::Twist(Box::new(s), k)
+}
+
+pub fn repeat(s: Sdf, x: f64, y: f64, z: f64) -> Sdf {
+    Sdf::Repeat(Box::new(s), vector(x, y, z))
+}
+
+pub struct SdfShape {
+    supe: Base,
+    pub sdf: Sdf,
+    pub max_steps: usize,
+    pub hit_epsilon: f64,
+    pub max_distance: f64,
+    // fraction of the reported distance actually stepped - twisted
+    // fields overestimate, so dial this down (0.5 or so) for those
+    pub step_scale: f64,
+}
+
+impl Shape for SdfShape {
+    fn get_transform(&self) -> &Matrix {
+        self.supe.get_transform()
+    }
+
+    fn set_transform(&mut self, t: Matrix){
+        self.supe.set_transform( t );
+    }
+
+    fn get_material(&self) -> &Material {
+        self.supe.get_material()
+    }
+
+    fn set_material(&mut self, m: Material){
+        self.supe.set_material( m );
+    }
+
+    // central differences on the distance field
+    fn local_normal_at(&self, object_point: Tuple) -> Tuple {
+        let h = self.hit_epsilon;
+        let p = object_point;
+        let f = |x: f64, y: f64, z: f64| self.sdf.distance(point(x, y, z));
+
+        vector(f(p.x + h, p.y, p.z) - f(p.x - h, p.y, p.z),
+               f(p.x, p.y + h, p.z) - f(p.x, p.y - h, p.z),
+               f(p.x, p.y, p.z + h) - f(p.x, p.y, p.z - h))
+    }
+
+    // sphere tracing - march along the ray by the distance to the
+    // nearest surface until we're close enough to call it a hit. Every
+    // crossing is reported (in and out) so refraction still has
+    // something to work with.
+    fn intersect(&self, r: Ray) -> Vec<Intersection> {
+        let r2 = self.saved_ray(r);
+        let speed = r2.direction.mag();
+        let dir = r2.direction / speed;
+
+        let mut xs = vec!();
+        let mut s = 0.0;
+
+        // a ray starting on the surface (shadow and reflection rays
+        // leave from over_point) shouldn't immediately hit it again
+        let mut on_surface = self.sdf.distance(r2.origin).abs() < self.hit_epsilon;
+
+        for _ in 0..self.max_steps {
+            if s > self.max_distance {
+                break;
+            }
+            let d = self.sdf.distance(r2.origin + dir * s).abs();
+            if d < self.hit_epsilon {
+                if !on_surface {
+                    xs.push(intersection(s / speed, self.get_index()));
+                    on_surface = true;
+                }
+                // nudge through so the next march finds the far side
+                s += self.hit_epsilon * 2.0;
+            } else {
+                on_surface = false;
+                s += d * self.step_scale;
+            }
+        }
+
+        xs
+    }
+
+    fn get_index(&self) -> usize {
+        self.supe.get_index()
+    }
+
+    fn set_index(&mut self, index: usize){
+        self.supe.set_index(index);
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.supe.get_parent()
+    }
+
+    fn set_parent(&mut self, parent_index: usize){
+        self.supe.set_parent(parent_index);
+    }
+
+    fn get_reference(&self) -> ShapeIndex {
+        self.supe.get_reference()
+    }
+
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "SdfShape"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        vec![self.max_steps as f64, self.hit_epsilon, self.max_distance, self.step_scale]
+    }
+
+    fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
+        0
+    }
+
+    fn get_object(&self, _index: usize) -> Option<&Box<dyn Shape>> {
+        None
+    }
+
+    fn get_size(&self) -> usize {
+        0
+    }
+}
+
+pub fn sdf_shape(sdf: Sdf) -> SdfShape {
+    SdfShape {
+        supe: shape(),
+        sdf,
+        max_steps: 256,
+        hit_epsilon: 0.0001,
+        max_distance: 100.0,
+        step_scale: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sdfs::{sdf_shape, sd_sphere, sd_box, sd_round_box, sd_torus,
+                      sd_capsule, translate, smooth_union, smooth_subtraction,
+                      twist, repeat};
+    use crate::shapes::Shape;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::transform::{scaling, translation};
+    use crate::equals::equals;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 0.001
+    }
+
+    #[test]
+    fn sphere_distance(){
+        let s = sd_sphere(1.0);
+        assert!(equals(s.distance(point(0.0, 0.0, 0.0)), -1.0));
+        assert!(equals(s.distance(point(0.0, 3.0, 0.0)),  2.0));
+        assert!(equals(s.distance(point(0.6, 0.8, 0.0)),  0.0));
+    }
+
+    #[test]
+    fn box_distance(){
+        let b = sd_box(1.0, 2.0, 3.0);
+        assert!(equals(b.distance(point(0.0, 0.0, 0.0)), -1.0));
+        assert!(equals(b.distance(point(3.0, 0.0, 0.0)),  2.0));
+        assert!(equals(b.distance(point(4.0, 6.0, 0.0)),  5.0));
+    }
+
+    #[test]
+    fn round_box_distance(){
+        let b = sd_round_box(1.0, 1.0, 1.0, 0.25);
+        assert!(equals(b.distance(point(2.0, 0.0, 0.0)), 1.0));
+
+        // the corner is pulled in along the diagonal
+        let corner = 0.75 + 0.25 / 3.0_f64.sqrt();
+        assert!(equals(b.distance(point(corner, corner, corner)), 0.0));
+    }
+
+    #[test]
+    fn torus_distance(){
+        let t = sd_torus(1.0, 0.25);
+        assert!(equals(t.distance(point(1.0, 0.0, 0.0)), -0.25));
+        assert!(equals(t.distance(point(0.0, 0.0, 0.0)),  0.75));
+        assert!(equals(t.distance(point(0.0, 1.0, -1.0)), 0.75));
+    }
+
+    #[test]
+    fn capsule_distance(){
+        let c = sd_capsule(point(0.0, -1.0, 0.0), point(0.0, 1.0, 0.0), 0.5);
+        assert!(equals(c.distance(point(1.0, 0.0, 0.0)), 0.5));
+        assert!(equals(c.distance(point(0.0, 2.0, 0.0)), 0.5));
+        assert!(equals(c.distance(point(0.0, 0.0, 0.0)), -0.5));
+    }
+
+    #[test]
+    fn translated_distance(){
+        let s = translate(sd_sphere(1.0), 0.0, 2.0, 0.0);
+        assert!(equals(s.distance(point(0.0, 2.0, 0.0)), -1.0));
+    }
+
+    #[test]
+    fn smooth_union_blends_below_the_minimum(){
+        let a = translate(sd_sphere(1.0), -1.0, 0.0, 0.0);
+        let b = translate(sd_sphere(1.0),  1.0, 0.0, 0.0);
+        let u = smooth_union(a.clone(), b.clone(), 0.5);
+
+        let p = point(0.0, 1.0, 0.0);
+        let hard = a.distance(p).min(b.distance(p));
+        assert!(u.distance(p) < hard);
+
+        // far away from the seam it's the same as a plain union
+        let p = point(-3.0, 0.0, 0.0);
+        assert!(equals(u.distance(p), a.distance(p)));
+    }
+
+    #[test]
+    fn smooth_subtraction_carves_out(){
+        let s = smooth_subtraction(sd_box(1.0, 1.0, 1.0), sd_sphere(0.5), 0.1);
+        assert!(s.distance(point(0.0, 0.0, 0.0)) > 0.0);
+        assert!(s.distance(point(0.9, 0.9, 0.9)) < 0.0);
+    }
+
+    #[test]
+    fn twist_leaves_the_middle_slice_alone(){
+        let b = sd_box(1.0, 2.0, 0.2);
+        let t = twist(b.clone(), 1.0);
+        let p = point(0.5, 0.0, 0.5);
+        assert!(equals(t.distance(p), b.distance(p)));
+
+        // a quarter turn higher up the long side has swung around
+        let t = twist(b.clone(), std::f64::consts::PI / 2.0);
+        assert!(t.distance(point(0.0, 1.0, 0.9)) < 0.0);
+        assert!(b.distance(point(0.0, 1.0, 0.9)) > 0.0);
+    }
+
+    #[test]
+    fn repetition_is_periodic(){
+        let r = repeat(sd_sphere(0.5), 3.0, 0.0, 3.0);
+        let d = r.distance(point(0.2, 0.0, 0.1));
+        assert!(equals(r.distance(point(3.2, 0.0, 0.1)), d));
+        assert!(equals(r.distance(point(-5.8, 0.0, 6.1)), d));
+        // y isn't repeated
+        assert!(equals(r.distance(point(0.0, 3.0, 0.0)), 2.5));
+    }
+
+    #[test]
+    fn ray_marching_a_sphere(){
+        let s = sdf_shape(sd_sphere(1.0));
+        let xs = s.intersect(ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+
+        assert_eq!(xs.len(), 2);
+        assert!(close(xs[0].t, 4.0));
+        assert!(close(xs[1].t, 6.0));
+    }
+
+    #[test]
+    fn ray_misses_sdf_shape(){
+        let s = sdf_shape(sd_sphere(1.0));
+        let xs = s.intersect(ray(point(0.0, 2.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn ray_from_the_surface_does_not_hit_itself(){
+        let s = sdf_shape(sd_sphere(1.0));
+        let xs = s.intersect(ray(point(0.0, 0.0, -1.00001), vector(0.0, 0.0, -1.0), 0));
+        assert_eq!(xs.len(), 0);
+
+        // but heading inward it finds the far side
+        let xs = s.intersect(ray(point(0.0, 0.0, -1.00001), vector(0.0, 0.0, 1.0), 0));
+        assert_eq!(xs.len(), 1);
+        assert!(close(xs[0].t, 2.0));
+    }
+
+    #[test]
+    fn marching_a_transformed_sdf_shape(){
+        let mut s = sdf_shape(sd_sphere(1.0));
+        s.set_transform(translation(0.0, 0.0, 2.0).mult(&scaling(2.0, 2.0, 2.0)));
+        let xs = s.intersect(ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+
+        assert_eq!(xs.len(), 2);
+        assert!(close(xs[0].t, 5.0));
+        assert!(close(xs[1].t, 9.0));
+    }
+
+    #[test]
+    fn step_count_is_configurable(){
+        let mut s = sdf_shape(sd_sphere(1.0));
+        s.max_steps = 1;
+        let xs = s.intersect(ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn hit_epsilon_is_configurable(){
+        // passes 0.05 above the sphere
+        let r = ray(point(0.0, 1.05, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let mut s = sdf_shape(sd_sphere(1.0));
+        assert_eq!(s.intersect(r).len(), 0);
+
+        s.hit_epsilon = 0.1;
+        assert!(s.intersect(r).len() > 0);
+    }
+
+    #[test]
+    fn repeated_shapes_are_all_found(){
+        let s = sdf_shape(repeat(sd_sphere(0.5), 0.0, 0.0, 2.0));
+        let xs = s.intersect(ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+
+        // spheres at z = -4, -2, 0 ... within max_distance of 100
+        assert!(xs.len() > 10);
+        assert!(close(xs[0].t, 0.5));
+        assert!(close(xs[1].t, 1.5));
+        assert!(close(xs[2].t, 2.5));
+    }
+
+    #[test]
+    fn normal_by_central_differences(){
+        let s = sdf_shape(sd_sphere(1.0));
+        let n = s.normal_at(point(0.0, 1.0, 0.0));
+        assert!(n.equals(vector(0.0, 1.0, 0.0)));
+
+        let b = sdf_shape(sd_box(1.0, 1.0, 1.0));
+        let n = b.normal_at(point(1.0, 0.3, -0.2));
+        assert!(n.equals(vector(1.0, 0.0, 0.0)));
+    }
+}
@@ -4,13 +4,30 @@ use crate::tuple::{Tuple, vector, point};
 use crate::rays::Ray;
 use crate::intersections::Intersection;
 use crate::shape_index::{ShapeIndex, shape_index};
+use crate::equals::equals;
 use core::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Debug,PartialEq,Clone)]
+// unlike the index (which is just a slot in World or a Group, and
+// gets reassigned on insertion) the id is handed out once when the
+// shape is constructed and never changes - clones share it
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Debug,Clone)]
 pub struct Base {
     transform: Matrix,
     material: Material,
     i: ShapeIndex,
+    id: usize,
+}
+
+// same rules as for dyn Shape - the id and the slot index are left out,
+// so two freshly built spheres with the same settings are equal
+impl PartialEq for Base {
+    fn eq(&self, other: &Base) -> bool {
+        self.transform.equals( other.transform.clone() ) &&
+        self.material.equals( other.material.clone() )
+    }
 }
 
 impl Shape for Base {
@@ -57,6 +74,14 @@ impl Shape for Base {
     fn get_reference(&self) -> ShapeIndex {
         self.i
     }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Shape"
+    }
     
     fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
         0
@@ -94,6 +119,39 @@ pub trait Shape {
     fn get_parent(&self) -> Option<usize>;
     fn set_parent(&mut self, parent_index: usize);
     fn get_reference(&self) -> ShapeIndex;
+    fn get_id(&self) -> usize;
+    fn get_kind(&self) -> &'static str;
+
+    // shape-specific values that take part in equality, e.g.
+    // cylinder min/max/closed - most shapes don't have any
+    fn get_params(&self) -> Vec<f64> {
+        vec!()
+    }
+
+    // surface parameterization at a point in object space - only
+    // shapes with a natural (u, v) mapping provide one
+    fn local_uv_at(&self, _object_point: Tuple) -> Option<(f64, f64)> {
+        None
+    }
+
+    // area sampling, for shapes that can serve as emitters: map (u, v)
+    // in [0, 1) to a point spread evenly over the surface in object space
+    fn local_sample(&self, _u: f64, _v: f64) -> Option<Tuple> {
+        None
+    }
+
+    // the sampled point in world space. Only the shape's own transform is
+    // applied, same as normal_at - parent groups don't take part, since
+    // world_to_object above is still a stub. The path tracer only samples
+    // shapes added straight to the World, so that's all it needs for now.
+    fn sample_point(&self, u: f64, v: f64) -> Option<Tuple> {
+        self.local_sample(u, v).map(|p| self.get_transform().multup(&p))
+    }
+
+    // world-space surface area, zero if the shape can't be sampled
+    fn area(&self) -> f64 {
+        0.0
+    }
 
     // text implements this as a mutable field on Shape,
     // but this causes mutability contagion across the entire
@@ -118,21 +176,72 @@ pub trait Shape {
 
 impl Debug for dyn Shape {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Shape {}", self.get_index())
+        let mut d = f.debug_struct(self.get_kind());
+        d.field("id", &self.get_id())
+         .field("index", &self.get_index())
+         .field("parent", &self.get_parent())
+         .field("transform", &self.get_transform().m)
+         .field("material", self.get_material());
+        if !self.get_params().is_empty() {
+            d.field("params", &self.get_params());
+        }
+        if self.get_size() > 0 {
+            d.field("children", &self.get_size());
+        }
+        d.finish()
     }
 }
 
+// structural equality - two shapes are equal if they are the same kind
+// with the same transform, material and parameters (and for groups, the
+// same children). The id is deliberately left out; compare get_id() if
+// you want identity instead.
 impl PartialEq for dyn Shape {
-    fn eq(&self, _: &(dyn Shape + 'static)) -> bool {
-        true   // need to research right way to compare trait objects
+    fn eq(&self, other: &(dyn Shape + 'static)) -> bool {
+        if self.get_kind() != other.get_kind() ||
+           !self.get_transform().equals( other.get_transform().clone() ) ||
+           !self.get_material().equals( other.get_material().clone() ) {
+            return false;
+        }
+
+        let a = self.get_params();
+        let b = other.get_params();
+        if a.len() != b.len() {
+            return false;
+        }
+        // exact check first so that infinite min/max compare equal
+        if !a.iter().zip(b.iter()).all(|(x, y)| x == y || equals(*x, *y)) {
+            return false;
+        }
+
+        if self.get_size() != other.get_size() {
+            return false;
+        }
+        for n in 0..self.get_size() {
+            match (self.get_object(n), other.get_object(n)) {
+                (Some(x), Some(y)) => if **x != **y { return false; },
+                _                  => return false,
+            }
+        }
+
+        true
     }
 }
 
+// how much the transform stretches an area lying in the object's
+// xz plane - used by the flat shapes to report world-space area
+pub fn xz_area_scale(t: &Matrix) -> f64 {
+    let u = t.multup( &vector(1.0, 0.0, 0.0) );
+    let v = t.multup( &vector(0.0, 0.0, 1.0) );
+    u.cross(&v).mag()
+}
+
 pub fn shape() -> Base {
     Base {
         transform: identity(),
         material: material(),
         i: shape_index(),
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
     }
 }
 
@@ -146,6 +255,9 @@ mod tests {
     use crate::tuple::{point, vector};
     use crate::groups::group;
     use crate::spheres::sphere;
+    use crate::planes::plane;
+    use crate::cylinders::cylinder;
+    use crate::color::color;
     use std::f64::consts::{PI, SQRT_2};
 
     #[test]
@@ -243,4 +355,101 @@ mod tests {
         let p = s.world_to_object(point(-2.0, 0.0, -10.0));
         assert!(p.equals(point(0.0, 0.0, -1.0)));
     }
+
+    #[test]
+    fn shapes_have_unique_ids(){
+        let s1 = sphere();
+        let s2 = sphere();
+        let p = plane();
+        assert_ne!(s1.get_id(), s2.get_id());
+        assert_ne!(s1.get_id(), p.get_id());
+    }
+
+    #[test]
+    fn id_is_stable_across_indexing_and_clones(){
+        let mut s = sphere();
+        let id = s.get_id();
+        s.set_index(7);
+        assert_eq!(s.get_id(), id);
+        assert_eq!(s.clone().get_id(), id);
+    }
+
+    #[test]
+    fn identical_shapes_are_equal(){
+        let mut s1 = sphere();
+        s1.set_transform(translation(1.0, 2.0, 3.0));
+        let mut s2 = sphere();
+        s2.set_transform(translation(1.0, 2.0, 3.0));
+
+        assert!( s1 == s2 );
+        assert!( *(Box::new(s1) as Box<dyn Shape>) == *(Box::new(s2) as Box<dyn Shape>) );
+        assert!( plane() == plane() );
+    }
+
+    #[test]
+    fn shapes_of_different_kinds_are_not_equal(){
+        let s = Box::new(sphere()) as Box<dyn Shape>;
+        let p = Box::new(plane()) as Box<dyn Shape>;
+        assert!( *s != *p );
+    }
+
+    #[test]
+    fn shapes_with_different_transforms_are_not_equal(){
+        let s1 = Box::new(sphere()) as Box<dyn Shape>;
+        let mut s2 = sphere();
+        s2.set_transform(scaling(0.5, 0.5, 0.5));
+        assert!( *s1 != *(Box::new(s2) as Box<dyn Shape>) );
+    }
+
+    #[test]
+    fn shapes_with_different_materials_are_not_equal(){
+        let s1 = Box::new(sphere()) as Box<dyn Shape>;
+        let mut s2 = sphere();
+        let mut m = material();
+        m.color = color(0.8, 1.0, 0.6);
+        s2.set_material(m);
+        assert!( *s1 != *(Box::new(s2) as Box<dyn Shape>) );
+    }
+
+    #[test]
+    fn cylinder_parameters_take_part_in_equality(){
+        let c1 = Box::new(cylinder()) as Box<dyn Shape>;
+        let c2 = Box::new(cylinder()) as Box<dyn Shape>;
+        assert!( *c1 == *c2 );
+
+        let mut c3 = cylinder();
+        c3.minimum = -1.0;
+        assert!( *c1 != *(Box::new(c3) as Box<dyn Shape>) );
+
+        let mut c4 = cylinder();
+        c4.closed = true;
+        assert!( *c1 != *(Box::new(c4) as Box<dyn Shape>) );
+    }
+
+    #[test]
+    fn groups_compare_their_children(){
+        let mut g1 = group();
+        g1.add_child(Box::new(sphere()));
+        let mut g2 = group();
+        g2.add_child(Box::new(sphere()));
+        let mut g3 = group();
+        g3.add_child(Box::new(plane()));
+
+        let g1 = Box::new(g1) as Box<dyn Shape>;
+        assert!( *g1 == *(Box::new(g2) as Box<dyn Shape>) );
+        assert!( *g1 != *(Box::new(g3) as Box<dyn Shape>) );
+        assert!( *g1 != *(Box::new(group()) as Box<dyn Shape>) );
+    }
+
+    #[test]
+    fn debug_output_describes_the_shape(){
+        let mut c = cylinder();
+        c.minimum = 1.0;
+        let id = c.get_id();
+        let s = format!("{:?}", Box::new(c) as Box<dyn Shape>);
+
+        assert!( s.starts_with("Cylinder") );
+        assert!( s.contains(&format!("id: {}", id)) );
+        assert!( s.contains("params: [1.0, inf, 0.0]") );
+    }
 }
@@ -1,10 +1,11 @@
 use crate::rays::Ray;
-use crate::tuple::{Tuple, origin};
+use crate::tuple::{Tuple, point, vector, origin};
 use crate::intersections::{Intersection, intersection, intersections};
 use crate::matrix::Matrix;
 use crate::shapes::{Base, Shape, shape};
 use crate::shape_index::ShapeIndex;
 use crate::materials::{Material, material};
+use std::f64::consts::PI;
 
 #[derive(Debug,PartialEq,Clone)]
 pub struct Sphere {
@@ -75,6 +76,45 @@ impl Shape for Sphere {
         self.supe.get_reference()
     }
     
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Sphere"
+    }
+
+    // spherical map - u around the equator, v from the south pole (0)
+    // to the north pole (1)
+    fn local_uv_at(&self, object_point: Tuple) -> Option<(f64, f64)> {
+        let theta = object_point.x.atan2(object_point.z);
+        let radius = (object_point - origin()).mag();
+        let phi = (object_point.y / radius).clamp(-1.0, 1.0).acos();
+        let u = 1.0 - (theta / (2.0 * PI) + 0.5);
+        let v = 1.0 - phi / PI;
+        Some((u.rem_euclid(1.0), v))
+    }
+
+    // uniform over the unit sphere - only uniform in world space too
+    // when the sphere is scaled the same along every axis
+    fn local_sample(&self, u: f64, v: f64) -> Option<Tuple> {
+        let z = 1.0 - 2.0 * v;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u;
+        Some(point(r * phi.cos(), r * phi.sin(), z))
+    }
+
+    // exact for spheres, Thomsen's approximation for ellipsoids
+    fn area(&self) -> f64 {
+        let t = self.get_transform();
+        let a = t.multup(&vector(1.0, 0.0, 0.0)).mag();
+        let b = t.multup(&vector(0.0, 1.0, 0.0)).mag();
+        let c = t.multup(&vector(0.0, 0.0, 1.0)).mag();
+        let p = 1.6075;
+        let mean = ((a * b).powf(p) + (a * c).powf(p) + (b * c).powf(p)) / 3.0;
+        4.0 * PI * mean.powf(1.0 / p)
+    }
+
     fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
         0
     }
@@ -321,4 +361,46 @@ mod tests {
         assert!(equals(s.get_material().transparency, 1.0)); 
         assert!(equals(s.get_material().refractive_index, 1.5)); 
     }
+
+    #[test]
+    fn samples_lie_on_the_sphere(){
+        let mut s = sphere();
+        s.set_transform(translation(0.0, 2.0, 0.0).mult(&scaling(3.0, 3.0, 3.0)));
+        for i in 0..10 {
+            for j in 0..=10 {
+                let p = s.sample_point(i as f64 / 10.0, j as f64 / 10.0).unwrap();
+                let d = p - point(0.0, 2.0, 0.0);
+                assert!(equals(d.mag(), 3.0));
+            }
+        }
+    }
+
+    #[test]
+    fn area_of_a_sphere(){
+        let mut s = sphere();
+        assert!(equals(s.area(), 4.0 * PI));
+
+        s.set_transform(scaling(2.0, 2.0, 2.0));
+        assert!(equals(s.area(), 16.0 * PI));
+
+        // prolate spheroid with a=b=1, c=2 has area ~21.4784
+        s.set_transform(scaling(1.0, 1.0, 2.0));
+        assert!((s.area() - 21.4784).abs() < 0.05);
+    }
+
+    #[test]
+    fn uv_mapping_on_a_sphere(){
+        let s = sphere();
+        let cases = [(point(0.0, 0.0, -1.0), 0.0, 0.5),
+                     (point(1.0, 0.0, 0.0), 0.25, 0.5),
+                     (point(0.0, 0.0, 1.0), 0.5, 0.5),
+                     (point(-1.0, 0.0, 0.0), 0.75, 0.5),
+                     (point(0.0, 1.0, 0.0), 0.5, 1.0),
+                     (point(0.0, -1.0, 0.0), 0.5, 0.0),
+                     (point(SQRT_2 / 2.0, SQRT_2 / 2.0, 0.0), 0.25, 0.75)];
+        for (p, u, v) in cases {
+            let (su, sv) = s.local_uv_at(p).unwrap();
+            assert!(equals(su, u) && equals(sv, v));
+        }
+    }
 }
@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// counters for profiling a render. They live in a thread local so the
+// world, shapes and matrices can bump them without passing anything
+// around, and cost next to nothing while nobody is collecting.
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct Stats {
+    pub primary_rays: u64,
+    pub shadow_rays: u64,         // any visibility test: lights, emitters, fog, AO
+    pub reflection_rays: u64,     // path tracer bounces count here too,
+    pub refraction_rays: u64,     // unless they go into the surface
+    pub intersection_tests: BTreeMap<&'static str, u64>,   // by get_kind()
+    pub max_depth: usize,
+    pub inverse_calls: u64,
+    pub inverse_time: Duration,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.shadow_rays + self.reflection_rays + self.refraction_rays
+    }
+
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        s += &format!("Render time:       {:.3} s\n", self.elapsed.as_secs_f64());
+        s += &format!("Primary rays:      {}\n", self.primary_rays);
+        s += &format!("Shadow rays:       {}\n", self.shadow_rays);
+        s += &format!("Reflection rays:   {}\n", self.reflection_rays);
+        s += &format!("Refraction rays:   {}\n", self.refraction_rays);
+        s += &format!("Rays per second:   {:.0}\n", per_second(self.rays(), self.elapsed));
+        s += &format!("Max depth:         {}\n", self.max_depth);
+        s += &format!("Inverse calls:     {} ({:.3} s)\n", self.inverse_calls,
+                      self.inverse_time.as_secs_f64());
+        s += "Intersection tests:\n";
+        for (kind, n) in &self.intersection_tests {
+            s += &format!("  {:<16} {}\n", kind, n);
+        }
+        s
+    }
+}
+
+// how far a render has got, handed to the observer as it goes
+#[derive(Debug,Clone,PartialEq)]
+pub struct Progress {
+    pub done: usize,        // pixels finished
+    pub total: usize,
+    pub elapsed: Duration,
+    pub rays: u64,          // all kinds, so far
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 { 1.0 } else { self.done as f64 / self.total as f64 }
+    }
+
+    // straight line guess from the speed so far
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let per_pixel = self.elapsed.as_secs_f64() / self.done as f64;
+        Some(Duration::from_secs_f64(per_pixel * (self.total - self.done) as f64))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        per_second(self.rays, self.elapsed)
+    }
+}
+
+thread_local! {
+    static STATS: RefCell<Option<(Stats, Instant)>> = const { RefCell::new(None) };
+}
+
+// clears the counters and starts collecting on this thread
+pub fn start() {
+    STATS.with(|s| *s.borrow_mut() = Some((Stats::default(), Instant::now())));
+}
+
+// stops collecting and hands back what was counted
+pub fn finish() -> Stats {
+    STATS.with(|s| match s.borrow_mut().take() {
+        Some((mut stats, started)) => {
+            stats.elapsed = started.elapsed();
+            stats
+        },
+        None => Stats::default(),
+    })
+}
+
+// what's been counted so far, without stopping
+pub fn current() -> Stats {
+    STATS.with(|s| match s.borrow().as_ref() {
+        Some((stats, started)) => Stats { elapsed: started.elapsed(), ..stats.clone() },
+        None => Stats::default(),
+    })
+}
+
+pub fn collecting() -> bool {
+    STATS.with(|s| s.borrow().is_some())
+}
+
+// does nothing unless start has been called
+pub fn record<F: FnOnce(&mut Stats)>(f: F) {
+    STATS.with(|s| {
+        if let Some((stats, _)) = s.borrow_mut().as_mut() {
+            f(stats);
+        }
+    });
+}
+
+pub fn count_intersection(kind: &'static str) {
+    record(|s| *s.intersection_tests.entry(kind).or_insert(0) += 1);
+}
+
+fn per_second(n: u64, d: Duration) -> f64 {
+    let secs = d.as_secs_f64();
+    if secs > 0.0 { n as f64 / secs } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{start, finish, current, collecting, record, Progress};
+    use crate::world::default_world;
+    use crate::camera::camera;
+    use crate::rays::ray;
+    use crate::tuple::{point, vector};
+    use crate::transform::view_transform;
+    use std::time::Duration;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn nothing_is_counted_unless_collecting(){
+        assert!(!collecting());
+        record(|s| s.primary_rays += 1);
+        start();
+        assert_eq!(current().primary_rays, 0);
+        record(|s| s.primary_rays += 1);
+        assert_eq!(finish().primary_rays, 1);
+        assert!(!collecting());
+    }
+
+    #[test]
+    fn counting_a_ray_into_the_default_world(){
+        let w = default_world();
+        start();
+        w.color_at(ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0));
+        let s = finish();
+        assert_eq!(s.shadow_rays, 1);
+        assert_eq!(s.reflection_rays, 0);
+        // once for the camera ray and once for the shadow ray
+        assert_eq!(s.intersection_tests["Sphere"], 4);
+        assert!(s.inverse_calls > 0);
+        assert!(s.report().contains("Sphere"));
+    }
+
+    #[test]
+    fn observed_render_reports_progress(){
+        let w = default_world();
+        let mut c = camera(11, 5, PI / 2.0);
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+
+        let mut seen = vec!();
+        let (image, s) = c.render_observed(&w, &mut |p: &Progress| seen.push(p.done));
+        assert_eq!(seen, vec![11, 22, 33, 44, 55]);
+        assert_eq!(s.primary_rays, 55);
+        assert!(s.shadow_rays > 0 && s.shadow_rays < 55);
+        assert!(image.pixel_at(5, 2).equals(w.color_at(c.ray_for_pixel(5, 2))));
+    }
+
+    #[test]
+    fn eta_from_the_speed_so_far(){
+        let p = Progress { done: 25, total: 100, elapsed: Duration::from_secs(10), rays: 500 };
+        assert_eq!(p.eta(), Some(Duration::from_secs(30)));
+        assert!((p.fraction() - 0.25).abs() < 1e-9);
+        assert!((p.rays_per_second() - 50.0).abs() < 1e-9);
+
+        let none = Progress { done: 0, ..p };
+        assert_eq!(none.eta(), None);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::color::{Color, color};
+
+// squeezes scene brightness (anything from 0 up) into 0..1 for display
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ToneMap {
+    // the old behaviour, anything over 1 just clips
+    Clamp,
+    // x / (1 + x) - never clips, but flattens highlights a lot
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve, a gentle toe and shoulder
+    Aces,
+}
+
+// noise added before rounding to 8 bits, which trades banding in smooth
+// gradients for a fine grain
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Dither {
+    None,
+    // 4x4 Bayer matrix
+    Ordered,
+    // interleaved gradient noise - not true blue noise, but the energy is
+    // up at high frequencies too and it needs no precomputed texture
+    BlueNoise,
+}
+
+// everything between a linear render and the 8-bit values in a file
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Output {
+    pub exposure: f64,      // in stops, so +1 doubles the brightness
+    pub tone_map: ToneMap,
+    pub srgb: bool,
+    pub dither: Dither,
+}
+
+const BAYER: [[f64; 4]; 4] = [[ 0.0,  8.0,  2.0, 10.0],
+                              [12.0,  4.0, 14.0,  6.0],
+                              [ 3.0, 11.0,  1.0,  9.0],
+                              [15.0,  7.0, 13.0,  5.0]];
+
+impl Output {
+    // exposure, tone curve and transfer function, still as floats in 0..1
+    pub fn apply(&self, c: Color) -> Color {
+        let scale = 2f64.powf(self.exposure);
+        let channel = |v: f64| {
+            let v = tone_map(self.tone_map, (v * scale).max(0.0));
+            if self.srgb { srgb_encode(v) } else { v }
+        };
+        color(channel(c.r), channel(c.g), channel(c.b))
+    }
+
+    // 8-bit values for the pixel at (x, y) - the position only matters
+    // for dithering
+    pub fn encode(&self, c: Color, x: usize, y: usize) -> [u8; 3] {
+        let c = self.apply(c);
+        let d = self.threshold(x, y);
+        [quantize(c.r, d), quantize(c.g, d), quantize(c.b, d)]
+    }
+
+    // where between two 8-bit levels a value gets rounded up, 0.5 being
+    // plain rounding
+    fn threshold(&self, x: usize, y: usize) -> f64 {
+        match self.dither {
+            Dither::None      => 0.5,
+            Dither::Ordered   => (BAYER[y % 4][x % 4] + 0.5) / 16.0,
+            Dither::BlueNoise => {
+                let v = 0.06711056 * x as f64 + 0.00583715 * y as f64;
+                (52.9829189 * v.fract()).fract()
+            },
+        }
+    }
+}
+
+// the same numbers to_ppm always wrote
+pub fn linear_output() -> Output {
+    Output {
+        exposure: 0.0,
+        tone_map: ToneMap::Clamp,
+        srgb: false,
+        dither: Dither::None,
+    }
+}
+
+// what most renders want on screen
+pub fn output() -> Output {
+    Output {
+        exposure: 0.0,
+        tone_map: ToneMap::Aces,
+        srgb: true,
+        dither: Dither::BlueNoise,
+    }
+}
+
+pub fn tone_map(t: ToneMap, v: f64) -> f64 {
+    match t {
+        ToneMap::Clamp    => v.clamp(0.0, 1.0),
+        ToneMap::Reinhard => v / (1.0 + v),
+        ToneMap::Aces     => ((v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14)).clamp(0.0, 1.0),
+    }
+}
+
+// linear light to the sRGB curve, and back
+pub fn srgb_encode(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_decode(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// same scaling as Canvas::pix_255 when d is 0.5
+fn quantize(v: f64, d: f64) -> u8 {
+    (v * 256.0 - 0.5 + d).floor().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tonemap::{Output, ToneMap, Dither, output, linear_output, tone_map,
+                         srgb_encode, srgb_decode};
+    use crate::canvas::Canvas;
+    use crate::color::color;
+    use crate::equals::equals;
+
+    #[test]
+    fn linear_output_matches_the_old_scaling(){
+        let out = linear_output();
+        for v in [-0.5, 0.0, 0.2, 0.5, 0.999, 1.0, 1.5] {
+            let e = out.encode(color(v, v, v), 3, 7);
+            assert_eq!(e[0] as i32, Canvas::pix_255(v));
+        }
+    }
+
+    #[test]
+    fn srgb_round_trips_and_brightens_midtones(){
+        for v in [0.0, 0.001, 0.18, 0.5, 1.0] {
+            assert!(equals(srgb_decode(srgb_encode(v)), v));
+        }
+        assert!(equals(srgb_encode(1.0), 1.0));
+        assert!(srgb_encode(0.18) > 0.45);
+    }
+
+    #[test]
+    fn tone_curves_keep_highlights_in_range(){
+        assert!(equals(tone_map(ToneMap::Clamp, 4.0), 1.0));
+        assert!(equals(tone_map(ToneMap::Reinhard, 1.0), 0.5));
+        assert!(tone_map(ToneMap::Reinhard, 100.0) < 1.0);
+
+        // ACES keeps getting brighter for a while instead of clipping
+        let a = tone_map(ToneMap::Aces, 1.0);
+        let b = tone_map(ToneMap::Aces, 2.0);
+        assert!(a < b && b <= 1.0);
+        assert!(equals(tone_map(ToneMap::Aces, 0.0), 0.0));
+    }
+
+    #[test]
+    fn exposure_is_in_stops(){
+        let mut out = linear_output();
+        out.exposure = 1.0;
+        assert!(out.apply(color(0.25, 0.1, 0.0)).equals(color(0.5, 0.2, 0.0)));
+        out.exposure = -2.0;
+        assert!(out.apply(color(0.8, 0.4, 4.0)).equals(color(0.2, 0.1, 1.0)));
+    }
+
+    #[test]
+    fn dithering_averages_out_to_the_true_value(){
+        // 0.3 of the way between two levels
+        let v = (100.0 + 0.3) / 255.0;
+        for dither in [Dither::Ordered, Dither::BlueNoise] {
+            let out = Output { dither, ..linear_output() };
+            let mut total = 0.0;
+            for y in 0..16 {
+                for x in 0..16 {
+                    total += out.encode(color(v, v, v), x, y)[0] as f64;
+                }
+            }
+            let mean = total / 256.0;
+            assert!((mean - 255.0 * v).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn display_output_never_clips_bright_pixels_to_the_same_value(){
+        let out = Output { dither: Dither::None, ..output() };
+        let a = out.encode(color(2.0, 2.0, 2.0), 0, 0);
+        let b = out.encode(color(8.0, 8.0, 8.0), 0, 0);
+        assert!(a[0] < b[0]);
+    }
+}
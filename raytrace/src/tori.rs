@@ -0,0 +1,309 @@
+use crate::shapes::{Shape, Base, shape};
+use crate::shape_index::ShapeIndex;
+use crate::intersections::{Intersection, intersection};
+use crate::tuple::{Tuple, point, vector};
+use crate::rays::Ray;
+use crate::materials::Material;
+use crate::matrix::Matrix;
+use crate::numeric::solve_quartic;
+use crate::equals::EPSILON;
+
+// torus lying in the xz plane, centered on the origin - the tube
+// (radius minor) sweeps around the y axis at distance major
+pub struct Torus {
+    supe: Base,
+    pub major: f64,
+    pub minor: f64,
+}
+
+impl Shape for Torus {
+    fn get_transform(&self) -> &Matrix {
+        self.supe.get_transform()
+    }
+
+    fn set_transform(&mut self, t: Matrix){
+        self.supe.set_transform( t );
+    }
+
+    fn get_material(&self) -> &Material {
+        self.supe.get_material()
+    }
+
+    fn set_material(&mut self, m: Material){
+        self.supe.set_material( m );
+    }
+
+    // gradient of (x²+y²+z²+R²-r²)² - 4R²(x²+z²), constant factor dropped
+    fn local_normal_at(&self, object_point: Tuple) -> Tuple {
+        let r2 = self.major.powf(2.0);
+        let s = object_point.x.powf(2.0) +
+                object_point.y.powf(2.0) +
+                object_point.z.powf(2.0) +
+                r2 - self.minor.powf(2.0);
+
+        vector(object_point.x * (s - 2.0 * r2),
+               object_point.y * s,
+               object_point.z * (s - 2.0 * r2))
+    }
+
+    fn intersect(&self, r: Ray) -> Vec<Intersection> {
+        let r2 = self.saved_ray(r);
+
+        let (tmin, tmax) = self.check_bounds(r2);
+        if tmin > tmax || tmax < 0.0 {
+            return vec!();
+        }
+
+        // the quartic loses precision quickly as the origin moves
+        // away from the torus, so solve from the edge of the bounding
+        // box and add the offset back in afterwards
+        let offset = if tmin > 0.0 { tmin } else { 0.0 };
+        let o = r2.position(offset);
+        let d = r2.direction;
+
+        let big_r2 = self.major.powf(2.0);
+        let dd = d.dot(&d);
+        let od = o.x * d.x + o.y * d.y + o.z * d.z;
+        let e = o.x.powf(2.0) + o.y.powf(2.0) + o.z.powf(2.0) +
+                big_r2 - self.minor.powf(2.0);
+
+        let a4 = dd * dd;
+        let a3 = 4.0 * dd * od;
+        let a2 = 2.0 * dd * e + 4.0 * od * od -
+                 4.0 * big_r2 * (d.x.powf(2.0) + d.z.powf(2.0));
+        let a1 = 4.0 * od * e - 8.0 * big_r2 * (o.x * d.x + o.z * d.z);
+        let a0 = e * e - 4.0 * big_r2 * (o.x.powf(2.0) + o.z.powf(2.0));
+
+        solve_quartic(a4, a3, a2, a1, a0).iter()
+                                         .map(|t| intersection(t + offset, self.get_index()))
+                                         .collect()
+    }
+
+    fn get_index(&self) -> usize {
+        self.supe.get_index()
+    }
+
+    fn set_index(&mut self, index: usize){
+        self.supe.set_index(index);
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.supe.get_parent()
+    }
+
+    fn set_parent(&mut self, parent_index: usize){
+        self.supe.set_parent(parent_index);
+    }
+
+    fn get_reference(&self) -> ShapeIndex {
+        self.supe.get_reference()
+    }
+
+    fn get_id(&self) -> usize {
+        self.supe.get_id()
+    }
+
+    fn get_kind(&self) -> &'static str {
+        "Torus"
+    }
+
+    fn get_params(&self) -> Vec<f64> {
+        vec![self.major, self.minor]
+    }
+
+    fn add_child(&mut self, mut _child: Box<dyn Shape>) -> usize {
+        0
+    }
+
+    fn get_object(&self, _index: usize) -> Option<&Box<dyn Shape>> {
+        None
+    }
+
+    fn get_size(&self) -> usize {
+        0
+    }
+}
+
+impl Torus {
+    // object-space bounding box as (min, max) corners
+    pub fn bounds(&self) -> (Tuple, Tuple) {
+        let outer = self.major + self.minor;
+        (point(-outer, -self.minor, -outer), point(outer, self.minor, outer))
+    }
+
+    // same slab test as Cube, against the bounding box
+    fn check_bounds(&self, r: Ray) -> (f64, f64) {
+        let (min, max) = self.bounds();
+        let xvals = Self::check_axis(r.origin.x, r.direction.x, min.x, max.x);
+        let yvals = Self::check_axis(r.origin.y, r.direction.y, min.y, max.y);
+        let zvals = Self::check_axis(r.origin.z, r.direction.z, min.z, max.z);
+
+        let tmin = xvals.0.max(yvals.0).max(zvals.0);
+        let tmax = xvals.1.min(yvals.1).min(zvals.1);
+        (tmin, tmax)
+    }
+
+    fn check_axis(o: f64, d: f64, min: f64, max: f64) -> (f64, f64) {
+        // unlike Cube we can't lean on (max - o) * f64::INFINITY here - a ray
+        // skimming the box face has o == max, and 0 * f64::INFINITY is NaN
+        if d.abs() <= EPSILON {
+            if o < min || o > max {
+                return (f64::INFINITY, -f64::INFINITY);
+            }
+            return (-f64::INFINITY, f64::INFINITY);
+        }
+
+        let tmin = (min - o) / d;
+        let tmax = (max - o) / d;
+
+        if tmin > tmax { (tmax, tmin) } else { (tmin, tmax) }
+    }
+}
+
+pub fn torus() -> Torus {
+    Torus {
+        supe: shape(),
+        major: 1.0,
+        minor: 0.25,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tori::torus;
+    use crate::tuple::{point, vector};
+    use crate::rays::ray;
+    use crate::shapes::Shape;
+    use crate::transform::scaling;
+    use crate::equals::equals;
+
+    #[test]
+    fn default_torus_radii(){
+        let t = torus();
+        assert_eq!(t.major, 1.0);
+        assert_eq!(t.minor, 0.25);
+    }
+
+    #[test]
+    fn ray_through_both_sides_of_tube(){
+        let t = torus();
+        let r = ray(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let xs = t.intersect(r);
+
+        assert_eq!(xs.len(), 4);
+        assert!(equals(xs[0].t, 3.75));
+        assert!(equals(xs[1].t, 4.25));
+        assert!(equals(xs[2].t, 5.75));
+        assert!(equals(xs[3].t, 6.25));
+    }
+
+    #[test]
+    fn ray_down_through_the_hole_misses(){
+        let t = torus();
+        let r = ray(point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+        assert_eq!(t.intersect(r).len(), 0);
+
+        let r = ray(point(0.5, 5.0, 0.3), vector(0.0, -1.0, 0.0), 0);
+        assert_eq!(t.intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn ray_down_through_the_tube(){
+        let t = torus();
+        let r = ray(point(1.0, 5.0, 0.0), vector(0.0, -1.0, 0.0), 0);
+        let xs = t.intersect(r);
+
+        assert_eq!(xs.len(), 2);
+        assert!(equals(xs[0].t, 4.75));
+        assert!(equals(xs[1].t, 5.25));
+    }
+
+    #[test]
+    fn ray_misses_a_torus(){
+        let t = torus();
+        let r = ray(point(0.0, 2.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        assert_eq!(t.intersect(r).len(), 0);
+
+        let r = ray(point(2.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        assert_eq!(t.intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn ray_grazing_top_of_torus(){
+        // skims the top of the tube where it crosses x = 0 - two
+        // tangent points, each a double root
+        let t = torus();
+        let r = ray(point(0.0, 0.25, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let xs = t.intersect(r);
+
+        assert_eq!(xs.len(), 4);
+        assert!(equals(xs[0].t, 4.0));
+        assert!(equals(xs[1].t, 4.0));
+        assert!(equals(xs[2].t, 6.0));
+        assert!(equals(xs[3].t, 6.0));
+    }
+
+    #[test]
+    fn ray_grazing_outer_rim(){
+        let t = torus();
+        let r = ray(point(1.25, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0);
+        let xs = t.intersect(r);
+
+        assert_eq!(xs.len(), 2);
+        assert!(equals(xs[0].t, 5.0));
+        assert!(equals(xs[1].t, 5.0));
+    }
+
+    #[test]
+    fn ray_from_inside_the_tube(){
+        let t = torus();
+        let r = ray(point(1.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), 0);
+        let xs = t.intersect(r);
+
+        assert_eq!(xs.len(), 4);
+        assert!(equals(xs[0].t, -2.25));
+        assert!(equals(xs[1].t, -1.75));
+        assert!(equals(xs[2].t, -0.25));
+        assert!(equals(xs[3].t, 0.25));
+    }
+
+    #[test]
+    fn intersecting_a_scaled_torus(){
+        let mut t = torus();
+        t.set_transform(scaling(2.0, 2.0, 2.0));
+        let r = ray(point(0.0, 0.0, -10.0), vector(0.0, 0.0, 1.0), 0);
+        let xs = t.intersect(r);
+
+        assert_eq!(xs.len(), 4);
+        assert!(equals(xs[0].t, 7.5));
+        assert!(equals(xs[3].t, 12.5));
+    }
+
+    #[test]
+    fn normal_vector_on_a_torus(){
+        let t = torus();
+
+        let n = t.normal_at(point(1.25, 0.0, 0.0));
+        assert!(n.equals(vector(1.0, 0.0, 0.0)));
+
+        let n = t.normal_at(point(0.75, 0.0, 0.0));
+        assert!(n.equals(vector(-1.0, 0.0, 0.0)));
+
+        let n = t.normal_at(point(0.0, 0.25, 1.0));
+        assert!(n.equals(vector(0.0, 1.0, 0.0)));
+
+        let n = t.normal_at(point(0.0, -0.25, -1.0));
+        assert!(n.equals(vector(0.0, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn bounds_of_a_torus(){
+        let mut t = torus();
+        t.major = 2.0;
+        t.minor = 0.5;
+        let (min, max) = t.bounds();
+
+        assert!(min.equals(point(-2.5, -0.5, -2.5)));
+        assert!(max.equals(point( 2.5,  0.5,  2.5)));
+    }
+}
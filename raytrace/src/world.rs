@@ -9,12 +9,22 @@ use crate::rays::{Ray, ray};
 use crate::intersections::{Intersection, hit, Computations, 
                            prepare_computations, schlick};
 use crate::patterns::Pattern;
+use crate::occlusion::{Occlusion, occlusion_at};
+use crate::backgrounds::Background;
+use crate::canvas::Canvas;
+use crate::media::{Medium, medium_at_origin, march};
+use crate::sampling::{rng, point_seed};
+use crate::stats::{record, count_intersection};
 
 #[derive(Debug)]
 pub struct World {
     pub light: Option<Light>,
+    pub occlusion: Option<Occlusion>,   // scales the ambient term when set
+    pub background: Option<Background>, // seen by rays that miss, black if None
+    pub fog: Option<Medium>,            // fills everywhere outside shapes with media
     objects: Vec<Box<dyn Shape>>,
     patterns: Vec<Box<dyn Pattern>>,
+    textures: Vec<Canvas>,
 }
 
 impl World {
@@ -22,6 +32,7 @@ impl World {
         let mut result = vec!();
 
         for obj in &self.objects {
+            count_intersection(obj.get_kind());
             let mut xs = obj.intersect(r);  // see note in Group
             if xs.len() > 0 {
                 result.append(&mut xs);
@@ -33,53 +44,95 @@ impl World {
     }
 
     pub fn shade_hit(&self, comps: Computations) -> Color {
-        let binding = point_light( point(0.0, 0.0, 0.0), color(0.0, 0.0, 0.0) );
-        let l = match &self.light {
-            Some(lgt) => lgt,
-            None      => &binding,
-        };
+        let (surface, rest, _) = self.shade_hit_parts(comps);
+        surface + rest
+    }
 
+    // shade_hit split up for the render passes - the surface lighting,
+    // everything else (emission, reflection, refraction), and whether
+    // the light was blocked
+    pub fn shade_hit_parts(&self, comps: Computations) -> (Color, Color, bool) {
         let shadowed = self.is_shadowed(comps.over_point);
-
-        let surface = lighting(self.objects[comps.object].get_material().clone(), 
-                               &self.objects[comps.object],
-                               &l, 
-                               comps.point, 
-                               comps.eyev, 
-                               comps.normalv, 
-                               shadowed,
-                               &self);
+        let surface = self.lit_surface(&comps, shadowed);
         let reflected = self.reflected_color(&comps);
         let refracted = self.refracted_color(&comps);
 
         let material = self.objects[comps.object].get_material();
+        let emitted = material.emissive;
         if material.reflective > 0.0 && material.transparency > 0.0 {
             let reflectance = schlick(comps);
-            return surface + 
-                   reflected * reflectance + 
-                   refracted * (1.0 - reflectance);
+            (surface, emitted + reflected * reflectance + refracted * (1.0 - reflectance), shadowed)
         } else {
-            return surface + reflected + refracted;
+            (surface, emitted + reflected + refracted, shadowed)
         }
     }
 
+    // just the Phong (or microfacet) part of shade_hit - ambient, diffuse
+    // and specular from the light, no reflection or refraction
+    pub fn surface_lighting(&self, comps: &Computations) -> Color {
+        self.lit_surface(comps, self.is_shadowed(comps.over_point))
+    }
+
+    fn lit_surface(&self, comps: &Computations, shadowed: bool) -> Color {
+        let binding = point_light( point(0.0, 0.0, 0.0), color(0.0, 0.0, 0.0) );
+        let l = match &self.light {
+            Some(lgt) => lgt,
+            None      => &binding,
+        };
+
+        let mut m = self.objects[comps.object].get_material().clone();
+        if let Some(ao) = &self.occlusion {
+            let mut r = rng(point_seed(ao.seed, comps.point));
+            m.ambient *= occlusion_at(self, comps.over_point, comps.normalv, ao, &mut r);
+        }
+
+        lighting(m, 
+                 &self.objects[comps.object],
+                 &l, 
+                 comps.point, 
+                 comps.eyev, 
+                 comps.normalv, 
+                 shadowed,
+                 &self)
+    }
+
     pub fn color_at(&self, r: Ray) -> Color {
         let xs = self.intersect(r);
+        self.color_for_hits(r, &xs)
+    }
+
+    // color_at for when the caller already has r's intersections
+    pub fn color_for_hits(&self, r: Ray, xs: &Vec<Intersection>) -> Color {
+        record(|s| s.max_depth = s.max_depth.max(r.count));
         let n = xs.len();
-        if n == 0 || xs[n-1].t < 0.0 {
-            return color(0.0, 0.0, 0.0)
+        let (surface, distance) = if n == 0 || xs[n-1].t < 0.0 {
+            (self.background_color(r), f64::INFINITY)
         } else {
             let mut hit = xs[0];
             if hit.t < 0.0 {
-                for i in &xs {
+                for i in xs {
                     if i.t >= 0.0 {
                         hit = *i;
                         break;
                     }
                 }
             }
-            let comps = prepare_computations(hit, r, self, &xs);
-            self.shade_hit(comps)
+            let comps = prepare_computations(hit, r, self, xs);
+            (self.shade_hit(comps), hit.t)
+        };
+
+        // fog, or the inside of a shape with a medium, between the ray's
+        // origin and whatever it hit
+        match medium_at_origin(self, xs) {
+            Some((m, container)) => march(self, r, distance, m, container, surface),
+            None                 => surface,
+        }
+    }
+
+    pub fn background_color(&self, r: Ray) -> Color {
+        match &self.background {
+            Some(b) => b.color_at(r.direction),
+            None    => color(0.0, 0.0, 0.0),
         }
     }
 
@@ -89,6 +142,7 @@ impl World {
         let direction = v.normal();
 
         let r = ray(p, direction, 0);
+        record(|s| s.shadow_rays += 1);
         let xs = self.intersect(r);
 
         let mut result = false;
@@ -115,6 +169,7 @@ impl World {
             color(0.0, 0.0, 0.0)
         } else {
             let reflect_ray = ray(comps.over_point, comps.reflectv, comps.count+1);
+            record(|s| s.reflection_rays += 1);
             let col = self.color_at(reflect_ray);
 
             col * reflect_value
@@ -141,6 +196,7 @@ impl World {
         let direction = comps.normalv * (n_ratio * cos_i - cos_t) -
                         comps.eyev * n_ratio;
         let refract_ray = ray(comps.under_point, direction, comps.count+1);
+        record(|s| s.refraction_rays += 1);
         let col = self.color_at(refract_ray);
 
         col * transparency
@@ -157,6 +213,15 @@ impl World {
         &(self.objects[index])
     }
 
+    // for changing a shape after it's been added, e.g. to animate it
+    pub fn get_object_mut(&mut self, index: usize) -> &mut Box<dyn Shape> {
+        &mut self.objects[index]
+    }
+
+    pub fn get_objects_len(&self) -> usize {
+        self.objects.len()
+    }
+
     pub fn add_pattern(&mut self, mut pat: Box<dyn Pattern>) -> usize {
         let current = self.patterns.len();
         pat.set_index( current as usize );
@@ -171,13 +236,28 @@ impl World {
     pub fn get_patterns_len(&self) -> usize {
         self.patterns.len()
     }
+
+    // images for normal maps and the like, referenced by index the same
+    // way patterns are
+    pub fn add_texture(&mut self, c: Canvas) -> usize {
+        self.textures.push(c);
+        self.textures.len() - 1
+    }
+
+    pub fn get_texture(&self, index: usize) -> &Canvas {
+        &self.textures[index]
+    }
 }
 
 pub fn world() -> World {
     World { 
         light: None,
+        occlusion: None,
+        background: None,
+        fog: None,
         objects: vec![],
         patterns: vec![],
+        textures: vec![],
     }
 }
 
@@ -195,8 +275,12 @@ pub fn default_world() -> World {
 
     World { 
         light: Some( point_light(point(-10.0, 10.0, -10.0), color(1.0, 1.0, 1.0))),
+        occlusion: None,
+        background: None,
+        fog: None,
         objects: vec![Box::new(s1), Box::new(s2)],
         patterns: vec![],
+        textures: vec![],
     }
 }
 
@@ -213,6 +297,9 @@ mod tests {
     use crate::materials::material;
     use crate::rays::ray;
     use crate::intersections::{intersection, prepare_computations, intersections};
+    use crate::occlusion::occlusion;
+    use crate::backgrounds::{solid_background, gradient_background};
+    use crate::media::{medium, absorbing};
     use crate::planes::plane;
     use crate::matrix::identity;
     use crate::patterns::pattern;
@@ -251,6 +338,9 @@ mod tests {
         });
         assert!( w.objects.contains( &(Box::new(s1) as Box<dyn Shape>) ));
         assert!( w.objects.contains( &(Box::new(s2) as Box<dyn Shape>) ));
+
+        let s3 = sphere();
+        assert!( !w.objects.contains( &(Box::new(s3) as Box<dyn Shape>) ));
     }
 
     #[test]
@@ -301,6 +391,67 @@ mod tests {
         assert!( c.equals( color(0.0, 0.0, 0.0) ));
     }
 
+    #[test]
+    fn color_when_ray_misses_with_background(){
+        let mut w = default_world();
+        w.background = Some(gradient_background(color(0.0, 0.0, 0.0), color(0.2, 0.4, 1.0)));
+        let r = ray( point(0.0, 0.0, -5.0), vector(0.0, 1.0, 0.0), 0 );
+        assert!( w.color_at(r).equals( color(0.2, 0.4, 1.0) ));
+
+        // everything behind the ray counts as a miss too
+        let r = ray( point(0.0, 0.0, 5.0), vector(0.0, 0.0, 1.0), 0 );
+        assert!( w.color_at(r).equals( color(0.1, 0.2, 0.5) ));
+    }
+
+    #[test]
+    fn mirror_reflects_the_background(){
+        let mut w = world();
+        w.light = Some(point_light( point(-10.0, 10.0, -10.0), color(1.0, 1.0, 1.0) ));
+        w.background = Some(solid_background(color(0.25, 0.5, 0.75)));
+        let mut p = plane();
+        let mut m = material();
+        m.color = color(0.0, 0.0, 0.0);
+        m.ambient = 0.0;
+        m.specular = 0.0;
+        m.reflective = 1.0;
+        p.set_material(m);
+        w.add_object(Box::new(p));
+
+        let r = ray(point(0.0, 1.0, -1.0), vector(0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0), 0);
+        assert!( w.color_at(r).equals( color(0.25, 0.5, 0.75) ));
+    }
+
+    #[test]
+    fn fog_dims_what_is_behind_it(){
+        let mut w = default_world();
+        w.fog = Some(medium(color(0.1, 0.2, 0.0), color(0.0, 0.0, 0.0)));
+        let r = ray( point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0 );
+        let c = w.color_at(r);
+        let expected = color(0.38066 * (-0.4f64).exp(), 0.47583 * (-0.8f64).exp(), 0.2855);
+        assert!( c.equals(expected) );
+    }
+
+    #[test]
+    fn thick_tinted_glass(){
+        let mut w = world();
+        w.light = Some(point_light( point(-10.0, 10.0, -10.0), color(1.0, 1.0, 1.0) ));
+        w.background = Some(solid_background(color(1.0, 1.0, 1.0)));
+        let mut s = sphere();
+        let mut m = material();
+        m.color = color(0.0, 0.0, 0.0);
+        m.ambient = 0.0;
+        m.diffuse = 0.0;
+        m.specular = 0.0;
+        m.transparency = 1.0;
+        m.medium = Some(absorbing(color(0.5, 1.0, 1.0)));
+        s.set_material(m);
+        w.add_object(Box::new(s));
+
+        // two units of glass through the middle
+        let r = ray( point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0 );
+        assert!( w.color_at(r).equals(color(0.25, 1.0, 1.0)) );
+    }
+
     #[test]
     fn color_when_ray_hits(){
         let w = default_world();
@@ -389,6 +540,72 @@ mod tests {
         assert!( c.equals(color(0.1, 0.1, 0.1)));
     }
 
+    #[test]
+    fn shading_an_emissive_surface(){
+        let mut w = world();
+        w.light = Some( point_light(point(-10.0, 10.0, -10.0), color(1.0, 1.0, 1.0)));
+        let mut s = sphere();
+        let mut m = material();
+        m.color = color(0.8, 1.0, 0.6);
+        m.diffuse = 0.7;
+        m.specular = 0.2;
+        m.emissive = color(0.5, 0.25, 0.0);
+        s.set_material(m);
+        w.add_object(Box::new(s));
+
+        let r = ray( point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0 );
+        let i = intersection(4.0, 0);
+        let xs = intersections(&[i]);
+        let comps = prepare_computations(i, r, &w, &xs);
+
+        let c = w.shade_hit(comps);
+        assert!( c.equals( color(0.88066, 0.72583, 0.2855) ));
+    }
+
+    #[test]
+    fn occlusion_leaves_open_surfaces_alone(){
+        let mut w = default_world();
+        let mut ao = occlusion();
+        ao.distance = 10.0;
+        w.occlusion = Some(ao);
+        let r = ray( point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0), 0 );
+        let i = intersection(4.0, 0);
+        let xs = intersections(&[i]);
+        let comps = prepare_computations(i, r, &w, &xs);
+
+        let c = w.shade_hit(comps);
+        assert!( c.equals( color(0.38066, 0.47583, 0.2855) ));
+    }
+
+    #[test]
+    fn occlusion_darkens_ambient_in_a_crevice(){
+        let mut w = world();
+        w.light = Some(point_light( point(0.0, 0.0, -10.0), color(1.0, 1.0, 1.0) ));
+        let s1 = sphere();
+        let mut s2 = sphere();
+        s2.set_transform( translation(0.0, 0.0, 2.2) );
+        w.add_object(Box::new(s1));
+        w.add_object(Box::new(s2));
+
+        // shadowed point on s2 facing s1 across a small gap, so only
+        // ambient is left
+        let r = ray(point(0.0, 0.0, 1.1), vector(0.0, 0.0, 1.0), 0);
+        let i = intersection(0.1, 1);
+        let xs = intersections(&[i]);
+
+        let comps = prepare_computations(i, r, &w, &xs);
+        assert!( w.shade_hit(comps).equals(color(0.1, 0.1, 0.1)) );
+
+        w.occlusion = Some(occlusion());
+        let comps = prepare_computations(i, r, &w, &xs);
+        let c = w.shade_hit(comps);
+        assert!( c.r < 0.05 && c.r > 0.0 );
+
+        // same point, same result
+        let comps = prepare_computations(i, r, &w, &xs);
+        assert!( w.shade_hit(comps).equals(c) );
+    }
+
     #[test]
     fn hit_should_offset_point(){
         let mut w = world();